@@ -0,0 +1,109 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alfred_tools::checkpoint::short;
+use alfred_tools::{builtin_tools, Checkpoint, CheckpointError, Checkpoints, GitError, SideEffect};
+
+const MAX_LISTED: usize = 20;
+const MAX_DIFF_LINES: usize = 200;
+
+// Whether a tool call may change files and so needs a checkpoint first.
+// Tools the registry doesn't know are assumed to.
+pub fn writes_files(tool: &str) -> bool {
+    builtin_tools()
+        .iter()
+        .find(|spec| spec.name == tool)
+        .is_none_or(|spec| spec.side_effect == SideEffect::WritesFs)
+}
+
+// Snapshots the working tree before something that may write files. Outside
+// a git repository there is nothing to snapshot, so that's not an error.
+pub async fn take_checkpoint(checkpoints: Checkpoints, prompt: String) -> Option<Checkpoint> {
+    let cwd = std::env::current_dir().ok()?.to_string_lossy().to_string();
+    let result = tokio::task::spawn_blocking(move || checkpoints.create(&cwd, &prompt))
+        .await
+        .ok()?;
+    match result {
+        Ok(checkpoint) => Some(checkpoint),
+        Err(CheckpointError::Git(GitError::NotARepository(_))) => None,
+        Err(e) => {
+            tracing::warn!("checkpoint failed: {}", e);
+            None
+        }
+    }
+}
+
+// `/rewind` lists checkpoints, `/rewind diff <id> [<id>]` compares them (or
+// one against the working tree) and `/rewind <id>` restores one. Runs git,
+// so call it off the event loop.
+pub fn handle_rewind(checkpoints: &Checkpoints, args: &str) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default().to_string_lossy().to_string();
+    let words: Vec<&str> = args.split_whitespace().collect();
+    let result = match words[..] {
+        [] => checkpoints.list(&cwd).map(|list| describe_list(&list)),
+        ["diff", from] => checkpoints.diff(&cwd, from, None).map(|diff| fence_diff(&diff)),
+        ["diff", from, to] => checkpoints.diff(&cwd, from, Some(to)).map(|diff| fence_diff(&diff)),
+        [id] => checkpoints.rewind(&cwd, id).map(|outcome| {
+            let mut text = format!(
+                "Rewound to {} ({}).\n",
+                short(&outcome.restored.id),
+                first_line(&outcome.restored.prompt)
+            );
+            for path in &outcome.written {
+                text.push_str(&format!("- restored `{}`\n", path));
+            }
+            for path in &outcome.removed {
+                text.push_str(&format!("- removed `{}`\n", path));
+            }
+            text.push_str(&format!("\nUndo with `/rewind {}`.", short(&outcome.safety.id)));
+            text
+        }),
+        _ => return "Usage: /rewind | /rewind <id> | /rewind diff <id> [<id>]".to_string(),
+    };
+    result.unwrap_or_else(|e| e.to_string())
+}
+
+fn describe_list(checkpoints: &[Checkpoint]) -> String {
+    if checkpoints.is_empty() {
+        return "No checkpoints yet.".to_string();
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or_default();
+    let mut text = String::from("Checkpoints (newest first):\n");
+    for checkpoint in checkpoints.iter().take(MAX_LISTED) {
+        text.push_str(&format!(
+            "- `{}` {} — {}\n",
+            short(&checkpoint.id),
+            ago(now - checkpoint.timestamp),
+            first_line(&checkpoint.prompt)
+        ));
+    }
+    text.push_str("\nRestore one with `/rewind <id>`.");
+    text
+}
+
+fn fence_diff(diff: &str) -> String {
+    if diff.trim().is_empty() {
+        return "No differences.".to_string();
+    }
+    let lines: Vec<&str> = diff.lines().collect();
+    let mut text = format!("```diff\n{}\n```", lines[..lines.len().min(MAX_DIFF_LINES)].join("\n"));
+    if lines.len() > MAX_DIFF_LINES {
+        text.push_str(&format!("\n({} more lines)", lines.len() - MAX_DIFF_LINES));
+    }
+    text
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default()
+}
+
+fn ago(seconds: i64) -> String {
+    match seconds {
+        s if s < 60 => format!("{}s ago", s.max(0)),
+        s if s < 3600 => format!("{}m ago", s / 60),
+        s if s < 86_400 => format!("{}h ago", s / 3600),
+        s => format!("{}d ago", s / 86_400),
+    }
+}
@@ -0,0 +1,226 @@
+use std::io::{self, Write};
+
+use alfred_core::{AgentEvent, AgentRouter, Message, Role};
+use alfred_tools::checkpoint::short;
+use alfred_tools::git::{Commit, DiffTarget, GitResult, GitTool, LogOptions};
+
+// Diffs beyond this are cut before they go to the model.
+const MAX_DIFF_CHARS: usize = 48_000;
+const MAX_PR_COMMITS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitStyle {
+    Plain,
+    Conventional,
+}
+
+#[derive(Debug)]
+pub enum Changes {
+    Staged(String),
+    // Nothing is staged; these files would be committed by `stage_all`.
+    Unstaged(Vec<String>),
+    Clean,
+}
+
+pub fn staged_changes(git: &GitTool, cwd: &str) -> GitResult<Changes> {
+    let diff = git.diff(cwd, &DiffTarget::Staged)?;
+    if !diff.trim().is_empty() {
+        return Ok(Changes::Staged(diff));
+    }
+    let status = git.status(cwd)?;
+    if status.clean {
+        return Ok(Changes::Clean);
+    }
+    Ok(Changes::Unstaged(status.entries.into_iter().map(|entry| entry.path).collect()))
+}
+
+// Stages every change in the repository and returns the staged diff.
+pub fn stage_all(git: &GitTool, cwd: &str) -> GitResult<String> {
+    git.add(cwd, &[":/".to_string()])?;
+    git.diff(cwd, &DiffTarget::Staged)
+}
+
+pub fn commit_messages(diff: &str, style: CommitStyle) -> Vec<Message> {
+    let format = match style {
+        CommitStyle::Plain => {
+            "Write a subject line in the imperative mood, at most 72 characters, without a trailing period."
+        }
+        CommitStyle::Conventional => {
+            "Use the Conventional Commits format for the subject: `type(scope): summary`, where type is one of \
+             feat, fix, docs, style, refactor, perf, test, build, ci or chore, the scope is optional, and the \
+             whole subject is at most 72 characters. Add `!` after the type for breaking changes."
+        }
+    };
+    let system = format!(
+        "You write git commit messages. {} If the change needs explaining, add a blank line and a short body \
+         wrapped at 72 columns that says what changed and why. Reply with the commit message only, no code \
+         fences or commentary.",
+        format
+    );
+    vec![
+        Message::new(Role::System, system),
+        Message::new(Role::User, format!("Staged diff:\n\n{}", truncate(diff))),
+    ]
+}
+
+pub fn pr_messages(base: &str, commits: &[Commit], diff: &str) -> Vec<Message> {
+    let system = "You draft pull request descriptions. Reply with a title on the first line, a blank line, then \
+                  a markdown body: one or two sentences on what the change does and why, followed by the notable \
+                  changes as a short bullet list. No code fences around the whole reply.";
+    let mut log = String::new();
+    for commit in commits {
+        log.push_str(&format!("- {}\n", commit.subject));
+        for line in commit.body.lines().filter(|line| !line.trim().is_empty()) {
+            log.push_str(&format!("  {}\n", line));
+        }
+    }
+    vec![
+        Message::new(Role::System, system.to_string()),
+        Message::new(
+            Role::User,
+            format!("Commits since `{}`:\n{}\nDiff:\n\n{}", base, log, truncate(diff)),
+        ),
+    ]
+}
+
+pub fn pr_context(git: &GitTool, cwd: &str, base: &str) -> GitResult<(Vec<Commit>, String)> {
+    let commits = git.log(
+        cwd,
+        &LogOptions {
+            range: Some(format!("{}..HEAD", base)),
+            max_count: MAX_PR_COMMITS,
+            ..LogOptions::default()
+        },
+    )?;
+    let diff = git.diff(cwd, &DiffTarget::Range(format!("{}...HEAD", base)))?;
+    Ok((commits, diff))
+}
+
+// Runs a one-shot completion and returns the reply text.
+pub async fn complete(router: &dyn AgentRouter, messages: &[Message]) -> anyhow::Result<String> {
+    let mut reply = String::new();
+    for event in router.respond(messages).await? {
+        if let AgentEvent::MessageDelta(content) = event {
+            reply.push_str(&content);
+        }
+    }
+    let reply = strip_fences(&reply);
+    if reply.is_empty() {
+        anyhow::bail!("The model returned an empty message");
+    }
+    Ok(reply)
+}
+
+// `alfred commit`: drafts a message for the staged changes and commits it
+// after the user accepts or edits it. `yes` skips every question.
+pub async fn run_commit_command(
+    router: &dyn AgentRouter,
+    git: &GitTool,
+    style: CommitStyle,
+    yes: bool,
+) -> anyhow::Result<()> {
+    let cwd = std::env::current_dir()?.to_string_lossy().to_string();
+    let diff = match staged_changes(git, &cwd)? {
+        Changes::Staged(diff) => diff,
+        Changes::Clean => {
+            println!("Nothing to commit.");
+            return Ok(());
+        }
+        Changes::Unstaged(files) => {
+            println!("Nothing is staged. Changed files:");
+            for file in &files {
+                println!("  {}", file);
+            }
+            if !yes && ask("Stage all of them and commit? [y/N] ")? != "y" {
+                return Ok(());
+            }
+            stage_all(git, &cwd)?
+        }
+    };
+
+    let message = complete(router, &commit_messages(&diff, style)).await?;
+    println!("\n{}\n", message);
+    let commit = if yes {
+        git.commit(&cwd, &message)?
+    } else {
+        match ask("Commit with this message? [y/e(dit)/N] ")?.as_str() {
+            "y" => git.commit(&cwd, &message)?,
+            "e" => git.commit_with_editor(&cwd, &message)?,
+            _ => {
+                println!("Not committed; changes stay staged.");
+                return Ok(());
+            }
+        }
+    };
+    println!("Committed {} {}", short(&commit.hash), commit.subject);
+    Ok(())
+}
+
+// `alfred pr-draft <base>`: prints a title and body for the branch.
+pub async fn run_pr_draft(router: &dyn AgentRouter, git: &GitTool, base: &str) -> anyhow::Result<()> {
+    let cwd = std::env::current_dir()?.to_string_lossy().to_string();
+    let (commits, diff) = pr_context(git, &cwd, base)?;
+    if commits.is_empty() {
+        anyhow::bail!("No commits between `{}` and HEAD", base);
+    }
+    println!("{}", complete(router, &pr_messages(base, &commits, &diff)).await?);
+    Ok(())
+}
+
+fn ask(question: &str) -> io::Result<String> {
+    print!("{}", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_ascii_lowercase())
+}
+
+fn strip_fences(text: &str) -> String {
+    let trimmed = text.trim();
+    let Some(inner) = trimmed.strip_prefix("```") else {
+        return trimmed.to_string();
+    };
+    let inner = inner.split_once('\n').map_or("", |(_, rest)| rest);
+    inner.trim_end().trim_end_matches("```").trim().to_string()
+}
+
+fn truncate(diff: &str) -> String {
+    if diff.len() <= MAX_DIFF_CHARS {
+        return diff.to_string();
+    }
+    let mut cut = MAX_DIFF_CHARS;
+    while !diff.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    format!("{}\n[diff truncated, {} more bytes]", &diff[..cut], diff.len() - cut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Canned(&'static str);
+
+    #[async_trait::async_trait]
+    impl AgentRouter for Canned {
+        async fn respond(&self, _messages: &[Message]) -> anyhow::Result<Vec<AgentEvent>> {
+            Ok(vec![AgentEvent::MessageDelta(self.0.to_string()), AgentEvent::Done])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_complete_strips_fences() {
+        let reply = complete(&Canned("```text\nfeat: add jobs\n\nBody.\n```\n"), &[]).await.unwrap();
+        assert_eq!(reply, "feat: add jobs\n\nBody.");
+        assert!(complete(&Canned("  "), &[]).await.is_err());
+    }
+
+    #[test]
+    fn test_prompts() {
+        let diff = "x".repeat(MAX_DIFF_CHARS + 10);
+        let messages = commit_messages(&diff, CommitStyle::Conventional);
+        assert!(messages[0].content.contains("Conventional Commits"));
+        assert!(messages[1].content.ends_with("[diff truncated, 10 more bytes]"));
+        assert!(!commit_messages("d", CommitStyle::Plain)[0].content.contains("Conventional"));
+    }
+}
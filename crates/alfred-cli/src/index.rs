@@ -0,0 +1,156 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use alfred_rag::{
+    project_cache_dir, DiskIndex, Embedder, HashEmbedder, IndexEvent, IndexStats, IndexerConfig, LocalEmbedder,
+    RemoteEmbedder, RemoteEmbedderConfig, RepoIndexer,
+};
+use alfred_tools::config::{Config, IndexConfig};
+use anyhow::Context;
+use tokio::sync::mpsc;
+
+use crate::AppEvent;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+// The workspace index lives in the user cache dir, keyed by the root path.
+// Provider profiles come from `global` only, so a project config can't send
+// a global API key to a host of its choosing.
+pub async fn open_indexer(root: &Path, config: &IndexConfig, global: &Config) -> anyhow::Result<RepoIndexer> {
+    let dir = project_cache_dir(root).context("No cache directory for the index")?;
+    let (embedder, model, dimension) = open_embedder(config, global).await?;
+    let index = DiskIndex::open(&dir, &model, dimension)?;
+    if index.invalidated() {
+        tracing::info!("Embedding model changed; rebuilding the index in {}", dir.display());
+    }
+    let config = IndexerConfig {
+        include: config.include.clone(),
+        exclude: config.exclude.clone(),
+        ..IndexerConfig::default()
+    };
+    // Same identity as the index header, so the manifest is reset whenever
+    // the index is.
+    let model = format!("{}/{}", model, dimension);
+    RepoIndexer::new(root, &dir, Arc::new(index), embedder, &model, config)
+}
+
+// The embedder `index.embedder` names, with the model id and dimension the
+// index header records.
+async fn open_embedder(config: &IndexConfig, global: &Config) -> anyhow::Result<(Arc<dyn Embedder>, String, usize)> {
+    match config.embedder.as_deref().unwrap_or("hash") {
+        "hash" => {
+            let embedder = HashEmbedder::default();
+            let (model, dimension) = (embedder.model_id(), embedder.dimension());
+            Ok((Arc::new(embedder), model, dimension))
+        }
+        "local" => {
+            let path = config.model_path.as_deref().context(
+                "index.embedder = \"local\" needs index.model_path: a directory with config.json, \
+                 model.safetensors and vocab.txt or tokenizer.json",
+            )?;
+            let embedder = LocalEmbedder::load(path)?;
+            let (model, dimension) = (embedder.model_id().to_string(), embedder.dimension());
+            Ok((Arc::new(embedder), model, dimension))
+        }
+        name => {
+            let Some(profile) = global.provider(name) else {
+                anyhow::bail!("Unknown embedder `{}`: not `hash`, `local` or a [providers.{}] profile", name, name);
+            };
+            let model = config
+                .embedding_model
+                .clone()
+                .with_context(|| format!("index.embedder = \"{}\" needs index.embedding_model", name))?;
+            let embedder = RemoteEmbedder::new(RemoteEmbedderConfig {
+                api_key: profile.resolve_api_key(),
+                base_url: profile.base_url,
+                headers: profile.headers,
+                model,
+                dimensions: config.dimensions,
+                ..RemoteEmbedderConfig::default()
+            })?;
+            let dimension = embedder.dimension().await?;
+            let model = embedder.model_id();
+            Ok((Arc::new(embedder), model, dimension))
+        }
+    }
+}
+
+pub fn describe_stats(stats: &IndexStats) -> String {
+    let mut parts = vec![format!("indexed {} files ({} chunks)", stats.indexed, stats.chunks_added)];
+    if stats.removed > 0 {
+        parts.push(format!("removed {}", stats.removed));
+    }
+    parts.push(format!("{} unchanged", stats.unchanged));
+    if stats.skipped > 0 {
+        parts.push(format!("{} skipped", stats.skipped));
+    }
+    format!("Index: {} in {} ms", parts.join(", "), stats.duration_ms)
+}
+
+// `alfred index`: brings the index for the current directory up to date,
+// printing progress to stderr. `watch` keeps going until Ctrl+C.
+pub async fn run_index_command(watch: bool) -> anyhow::Result<()> {
+    let root = std::env::current_dir()?;
+    let global = Config::load().await.unwrap_or_default();
+    let config = Config::load_for_project(&root).await.unwrap_or_default();
+    let indexer = open_indexer(&root, &config.index, &global).await?;
+
+    let (tx, mut rx) = mpsc::channel::<IndexEvent>(256);
+    let printer = tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            match event {
+                IndexEvent::Scanned { files } => eprintln!("Scanning {} files", files),
+                IndexEvent::Indexed { path, chunks, done, total } => {
+                    eprintln!("[{}/{}] {} ({} chunks)", done, total, path, chunks)
+                }
+                IndexEvent::Removed { path, .. } => eprintln!("removed {}", path),
+                IndexEvent::Skipped { path, reason } => eprintln!("skipped {}: {}", path, reason),
+                IndexEvent::Finished(stats) if !watch || stats.changed() => println!("{}", describe_stats(&stats)),
+                IndexEvent::Finished(_) => {}
+            }
+        }
+    });
+
+    if watch {
+        tokio::select! {
+            _ = indexer.watch(WATCH_INTERVAL, Some(&tx)) => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    } else {
+        indexer.run(Some(&tx)).await?;
+    }
+    drop(tx);
+    let _ = printer.await;
+    Ok(())
+}
+
+// Indexes the workspace without blocking the TUI, then keeps watching it if
+// `index.watch` is set. Only passes that change something are reported.
+pub fn spawn_background(root: PathBuf, config: IndexConfig, global: Config, notices: mpsc::Sender<AppEvent>) {
+    tokio::spawn(async move {
+        let indexer = match open_indexer(&root, &config, &global).await {
+            Ok(indexer) => indexer,
+            Err(e) => {
+                let _ = notices.send(AppEvent::Notice(format!("Indexing failed: {:#}", e))).await;
+                return;
+            }
+        };
+        let (tx, mut rx) = mpsc::channel::<IndexEvent>(256);
+        let forward = notices.clone();
+        tokio::spawn(async move {
+            while let Some(event) = rx.recv().await {
+                if let IndexEvent::Finished(stats) = event {
+                    if stats.changed() && forward.send(AppEvent::Notice(describe_stats(&stats))).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        if config.watch {
+            indexer.watch(WATCH_INTERVAL, Some(&tx)).await;
+        } else if let Err(e) = indexer.run(Some(&tx)).await {
+            let _ = notices.send(AppEvent::Notice(format!("Indexing failed: {:#}", e))).await;
+        }
+    });
+}
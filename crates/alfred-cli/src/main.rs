@@ -1,12 +1,17 @@
 use std::env;
 use std::io::{self, Stdout};
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
-use alfred_core::{Message, Role, AgentRouter, AgentEvent};
+use alfred_core::{Message, Role, AgentRouter, AgentEvent, ToolOutput, ToolResult};
 use alfred_core::providers::openrouter::OpenRouterProvider;
+use commit::{Changes, CommitStyle};
+use worktree::WorktreeAction;
 use alfred_tools::config::Config;
-use anyhow::Result;
+use alfred_tools::checkpoint::short;
+use alfred_tools::{Checkpoints, EnvPolicy, GitTool, HttpRequest, HttpTool, JobInfo, JobManager, JobState, PtyManager, Sandbox, ShellCommand, ShellPolicy, ShellTool, TemplateValues, ToolRegistry, Verdict};
+use anyhow::{Context, Result};
 use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::{execute, terminal};
@@ -18,8 +23,15 @@ use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
 use ratatui::Terminal;
 use tokio::sync::mpsc;
 use tokio::time;
+use tokio_util::sync::CancellationToken;
 
+mod checkpoints;
+mod commit;
+mod index;
 mod markdown;
+mod run;
+mod tools;
+mod worktree;
 
 // OneDark Theme Colors
 const ONEDARK_BG: Color = Color::Rgb(40, 44, 52);
@@ -31,6 +43,8 @@ const ONEDARK_MAGENTA: Color = Color::Rgb(198, 120, 221);
 const ONEDARK_CYAN: Color = Color::Rgb(86, 182, 194);
 
 const DEFAULT_SYSTEM_PROMPT: &str = include_str!("../../../prompts/SOUL.md");
+// Free on OpenRouter; openai/gpt-3.5-turbo works too.
+const DEFAULT_MODEL: &str = "google/gemini-2.0-flash-001";
 
 #[derive(Debug)]
 enum AppEvent {
@@ -38,6 +52,13 @@ enum AppEvent {
     Tick,
     AgentChunk(String),
     AgentDone,
+    ToolOutput(ToolOutput),
+    ToolDone(ToolResult),
+    // A status line for the transcript from a background task.
+    Notice(String),
+    // What `/commit` found to commit, or why it couldn't look.
+    CommitChanges(CommitStyle, Result<Changes, String>),
+    CommitDraft(Result<String, String>),
 }
 
 enum AppMode {
@@ -45,6 +66,87 @@ enum AppMode {
     Chat,
 }
 
+const TOOL_PANE_HEIGHT: u16 = 12;
+const TOOL_PANE_MAX_LINES: usize = 1000;
+
+struct ToolPane {
+    command_line: String,
+    lines: Vec<ToolOutput>,
+    running: bool,
+    collapsed: bool,
+    cancel: CancellationToken,
+}
+
+impl ToolPane {
+    fn height(&self) -> u16 {
+        if self.collapsed {
+            3
+        } else {
+            TOOL_PANE_HEIGHT
+        }
+    }
+
+    fn title(&self) -> String {
+        let state = if self.running { "running" } else { "finished" };
+        let toggle = if self.collapsed { "Ctrl+T to expand" } else { "Ctrl+T to collapse" };
+        format!("$ {} [{}] ({})", self.command_line, state, toggle)
+    }
+
+    fn render_lines(&self) -> Text<'_> {
+        let visible = self.height().saturating_sub(2) as usize;
+        let start = self.lines.len().saturating_sub(visible);
+        let lines = self.lines[start..]
+            .iter()
+            .map(|output| {
+                let color = if output.stream == "stderr" { ONEDARK_RED } else { ONEDARK_FG };
+                Line::from(Span::styled(output.line.clone(), Style::default().fg(color)))
+            })
+            .collect::<Vec<_>>();
+        Text::from(lines)
+    }
+}
+
+const PTY_PANE_HEIGHT: u16 = 16;
+const JOB_LOG_LINES: usize = 40;
+
+// The most recently started PTY session, redrawn from its screen on every tick.
+struct PtyPane {
+    id: String,
+    command_line: String,
+    screen: String,
+    exit_code: Option<i32>,
+}
+
+impl PtyPane {
+    fn title(&self) -> String {
+        let state = match self.exit_code {
+            Some(code) => format!("exited {}", code),
+            None => "running".to_string(),
+        };
+        format!("{} $ {} [{}] (/pty send <text>, /pty kill)", self.id, self.command_line, state)
+    }
+
+    fn render_lines(&self) -> Text<'_> {
+        let visible = PTY_PANE_HEIGHT.saturating_sub(2) as usize;
+        let lines: Vec<&str> = self.screen.trim_end().lines().collect();
+        let start = lines.len().saturating_sub(visible);
+        Text::from(lines[start..].iter().map(|line| Line::from(line.to_string())).collect::<Vec<_>>())
+    }
+}
+
+// Where a user-entered command runs once the shell policy allows it.
+enum CommandTarget {
+    Shell,
+    Pty,
+    Job(String),
+}
+
+// A command waiting for the user to confirm it.
+struct PendingCommand {
+    command_line: String,
+    target: CommandTarget,
+}
+
 struct App {
     messages: Vec<Message>,
     input: String,
@@ -52,11 +154,32 @@ struct App {
     scroll: u16,
     mode: AppMode,
     config: Config,
+    // Global config overlaid with `.alfred/config.toml` from the cwd.
+    workspace_config: Config,
+    tool_pane: Option<ToolPane>,
+    pending_command: Option<PendingCommand>,
+    // Like `jobs` and `checkpoints`, holds the error instead when the env
+    // config is invalid, so nothing runs with a policy the user didn't write.
+    pty: Result<Arc<PtyManager>, String>,
+    pty_pane: Option<PtyPane>,
+    // Background jobs; dropping the last handle kills them. Holds the error
+    // instead when the shell or sandbox config is invalid, so jobs never run
+    // without them.
+    jobs: Result<Arc<JobManager>, String>,
+    checkpoints: Result<Checkpoints, String>,
+    // A drafted commit message being edited; Ctrl+S commits it.
+    commit_editor: Option<String>,
+    // `/commit` found nothing staged and waits for `y` to stage everything.
+    pending_commit: Option<CommitStyle>,
 }
 
 impl App {
     async fn new() -> Self {
         let config = Config::load().await.unwrap_or_default();
+        let workspace_config = match env::current_dir() {
+            Ok(cwd) => Config::load_for_project(&cwd).await.unwrap_or_else(|_| config.clone()),
+            Err(_) => config.clone(),
+        };
         // Check both config and environment variable
         let mode = if config.openrouter_api_key.is_some() || env::var("OPENROUTER_API_KEY").is_ok() {
             AppMode::Chat
@@ -68,19 +191,38 @@ impl App {
             .await
             .unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string());
 
+        let env_policy = env_policy(&workspace_config);
+        let pty = env_policy
+            .clone()
+            .map(|env| Arc::new(PtyManager::new(&workspace_config.shell, env)));
+
         let mut messages = Vec::new();
         if matches!(mode, AppMode::Chat) {
              messages.push(Message::new(Role::System, system_prompt));
         }
 
-        Self {
+        let mut app = Self {
             messages,
             input: String::new(),
             streaming_idx: None,
             scroll: 0,
             mode,
             config,
-        }
+            workspace_config,
+            tool_pane: None,
+            pending_command: None,
+            pty,
+            pty_pane: None,
+            jobs: Err(String::new()),
+            checkpoints: env_policy.map(|env| Checkpoints::new(GitTool::new(env))),
+            commit_editor: None,
+            pending_commit: None,
+        };
+        app.jobs = app
+            .shell_tool()
+            .map(|tool| Arc::new(JobManager::new(tool)))
+            .map_err(|e| format!("{:#}", e));
+        app
     }
 
     fn push_user(&mut self, content: String) {
@@ -111,6 +253,428 @@ impl App {
         self.streaming_idx = None;
     }
 
+    fn append_tool_output(&mut self, output: ToolOutput) {
+        if let Some(pane) = self.tool_pane.as_mut() {
+            if pane.lines.len() == TOOL_PANE_MAX_LINES {
+                pane.lines.remove(0);
+            }
+            pane.lines.push(output);
+        }
+    }
+
+    fn check_and_start_shell(&mut self, command_line: String, target: CommandTarget, tx: mpsc::Sender<AppEvent>) {
+        let workspace = env::current_dir().unwrap_or_default();
+        let verdict = match ShellPolicy::from_config(&self.workspace_config.shell) {
+            Ok(policy) => policy.check(&ShellCommand::script(command_line.clone()), &workspace),
+            Err(e) => Verdict::Deny {
+                reason: format!("invalid shell rules in config: {}", e),
+            },
+        };
+        match verdict {
+            Verdict::Allow => self.start_command(command_line, target, tx),
+            Verdict::Deny { reason } => {
+                self.messages.push(Message::new(Role::Tool, format!("Blocked `{}`: {}", command_line, reason)));
+            }
+            Verdict::Confirm { reasons } => {
+                let mut text = format!("`{}` needs confirmation:\n", command_line);
+                for reason in reasons {
+                    text.push_str(&format!("- {}\n", reason));
+                }
+                text.push_str("\nType `y` to run it, anything else to skip.");
+                self.messages.push(Message::new(Role::Tool, text));
+                self.pending_command = Some(PendingCommand { command_line, target });
+            }
+        }
+    }
+
+    fn start_command(&mut self, command_line: String, target: CommandTarget, tx: mpsc::Sender<AppEvent>) {
+        match target {
+            CommandTarget::Shell => self.start_shell(command_line, tx),
+            CommandTarget::Pty => self.start_pty(command_line),
+            CommandTarget::Job(name) => {
+                let started = match &self.jobs {
+                    Ok(jobs) => jobs.start(&name, ShellCommand::script(command_line.clone())).map_err(|e| e.to_string()),
+                    Err(e) => Err(e.clone()),
+                };
+                let text = match started {
+                    Ok(info) => format!("Started job `{}` (pid {}): `{}`", name, info.pid.unwrap_or_default(), command_line),
+                    Err(e) => format!("Did not start job `{}`: {}", name, e),
+                };
+                self.messages.push(Message::new(Role::Tool, text));
+            }
+        }
+    }
+
+    // `/tools` lists the tools available in this project, including HTTP
+    // templates from `.alfred/tools`; `/tools call <name> <json>` runs a
+    // template by hand.
+    fn handle_tools_command(&mut self, args: &str, tx: mpsc::Sender<AppEvent>) {
+        let cwd = env::current_dir().unwrap_or_default();
+        let registry = match ToolRegistry::load(&cwd) {
+            Ok(registry) => registry,
+            Err(e) => {
+                self.messages.push(Message::new(Role::Tool, e.to_string()));
+                return;
+            }
+        };
+        let mut parts = args.splitn(3, ' ');
+        match (parts.next().unwrap_or_default(), parts.next()) {
+            ("", None) => {
+                let text = registry
+                    .specs()
+                    .iter()
+                    .map(|spec| {
+                        let confirm = if spec.side_effect.needs_confirmation() { ", confirm" } else { "" };
+                        format!("- `{}` ({}{}) — {}", spec.name, spec.side_effect.as_str(), confirm, spec.description)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                self.messages.push(Message::new(Role::Tool, text));
+            }
+            ("call", Some(name)) => {
+                let Some(template) = registry.template(name).cloned() else {
+                    self.messages.push(Message::new(Role::Tool, format!("No tool template named `{}`.", name)));
+                    return;
+                };
+                let arguments = match serde_json::from_str(parts.next().unwrap_or("{}")) {
+                    Ok(arguments) => arguments,
+                    Err(e) => {
+                        self.messages.push(Message::new(Role::Tool, format!("Invalid JSON arguments: {}", e)));
+                        return;
+                    }
+                };
+                let network = self.workspace_config.network.clone();
+                // Grants and the env policy come from the global config only,
+                // since the templates come from the project.
+                let grants = self.config.tools.clone();
+                let env_policy = match env_policy(&self.config) {
+                    Ok(env_policy) => env_policy,
+                    Err(e) => {
+                        self.messages.push(Message::new(Role::Tool, format!("Did not call `{}`: {}", template.name, e)));
+                        return;
+                    }
+                };
+                tokio::spawn(async move {
+                    let secrets = alfred_tools::config::load_secrets().await.unwrap_or_default();
+                    let result = match (TemplateValues::new(&grants, &env_policy, &secrets), HttpTool::from_config(&network)) {
+                        (Ok(values), Ok(http)) => template.call(&http, &arguments, &values).await,
+                        (Err(e), _) => Err(e),
+                        (_, Err(e)) => Err(e.into()),
+                    };
+                    let text = match result {
+                        Ok(output) => format!(
+                            "`{}` returned {}\n```json\n{}\n```",
+                            template.name,
+                            output.status,
+                            serde_json::to_string_pretty(&output.result).unwrap_or_default()
+                        ),
+                        Err(e) => format!("`{}` failed: {}", template.name, e),
+                    };
+                    let _ = tx.send(AppEvent::Notice(text)).await;
+                });
+            }
+            _ => {
+                self.messages.push(Message::new(
+                    Role::Tool,
+                    "Usage: /tools | /tools call <template> <json arguments>".to_string(),
+                ));
+            }
+        }
+    }
+
+    // `/fetch <url>` GETs a page through the project's network allowlist.
+    fn fetch_url(&mut self, url: String, tx: mpsc::Sender<AppEvent>) {
+        if url.is_empty() {
+            self.messages.push(Message::new(Role::Tool, "Usage: /fetch <url>".to_string()));
+            return;
+        }
+        let tool = match HttpTool::from_config(&self.workspace_config.network) {
+            Ok(tool) => tool,
+            Err(e) => {
+                self.messages.push(Message::new(Role::Tool, e.to_string()));
+                return;
+            }
+        };
+        tokio::spawn(async move {
+            let request = HttpRequest {
+                url: url.clone(),
+                ..HttpRequest::default()
+            };
+            let text = match tool.fetch(&request).await {
+                Ok(response) => {
+                    let mut text = format!("{} {}\n\n{}", response.status, response.url, response.body.trim_end());
+                    if response.truncated {
+                        text.push_str("\n\n[response truncated]");
+                    }
+                    text
+                }
+                Err(e) => format!("Fetching {} failed: {}", url, e),
+            };
+            let _ = tx.send(AppEvent::Notice(text)).await;
+        });
+    }
+
+    // `/jobs` lists background jobs; `/jobs start <name> <command>`,
+    // `/jobs logs <name>` and `/jobs stop <name>` manage them.
+    fn handle_jobs_command(&mut self, args: &str, tx: mpsc::Sender<AppEvent>) {
+        let jobs = match &self.jobs {
+            Ok(jobs) => jobs.clone(),
+            Err(e) => {
+                self.messages.push(Message::new(Role::Tool, format!("Background jobs are unavailable: {}", e)));
+                return;
+            }
+        };
+        let mut parts = args.splitn(3, ' ');
+        let verb = parts.next().unwrap_or_default();
+        let name = parts.next().unwrap_or_default().to_string();
+        let rest = parts.next().unwrap_or_default().trim().to_string();
+        match verb {
+            "" => {
+                let jobs = jobs.list();
+                let text = if jobs.is_empty() {
+                    "No background jobs.".to_string()
+                } else {
+                    jobs.iter().map(describe_job).collect::<Vec<_>>().join("\n")
+                };
+                self.messages.push(Message::new(Role::Tool, text));
+            }
+            "start" if !name.is_empty() && !rest.is_empty() => {
+                self.check_and_start_shell(rest, CommandTarget::Job(name), tx);
+            }
+            "logs" if !name.is_empty() => {
+                let text = match jobs.output(&name, 0, JOB_LOG_LINES) {
+                    Ok(output) => {
+                        let lines = output.lines.iter().map(|line| line.line.as_str()).collect::<Vec<_>>();
+                        format!("{}\n```\n{}\n```", describe_job(&output.job), lines.join("\n"))
+                    }
+                    Err(e) => e.to_string(),
+                };
+                self.messages.push(Message::new(Role::Tool, text));
+            }
+            "stop" if !name.is_empty() => {
+                tokio::spawn(async move {
+                    let text = match jobs.stop(&name).await {
+                        Ok(info) => format!("Stopped {}", describe_job(&info)),
+                        Err(e) => e.to_string(),
+                    };
+                    let _ = tx.send(AppEvent::Notice(text)).await;
+                });
+            }
+            _ => {
+                self.messages.push(Message::new(
+                    Role::Tool,
+                    "Usage: /jobs | /jobs start <name> <command> | /jobs logs <name> | /jobs stop <name>".to_string(),
+                ));
+            }
+        }
+    }
+
+    fn git_tool(&mut self) -> Option<GitTool> {
+        match env_policy(&self.workspace_config) {
+            Ok(env) => Some(GitTool::new(env)),
+            Err(e) => {
+                self.messages.push(Message::new(Role::Tool, format!("Did not run git: {}", e)));
+                None
+            }
+        }
+    }
+
+    // `/commit [--conventional]` drafts a message for the staged changes.
+    fn start_commit(&mut self, args: &str, tx: mpsc::Sender<AppEvent>) {
+        let style = if args.split_whitespace().any(|arg| arg == "--conventional") {
+            CommitStyle::Conventional
+        } else {
+            CommitStyle::Plain
+        };
+        let Some(git) = self.git_tool() else {
+            return;
+        };
+        spawn_git(tx, move |cwd| {
+            let changes = commit::staged_changes(&git, cwd).map_err(|e| e.to_string());
+            AppEvent::CommitChanges(style, changes)
+        });
+    }
+
+    // `y` to the prompt `/commit` shows when nothing is staged.
+    fn stage_all_and_commit(&mut self, style: CommitStyle, tx: mpsc::Sender<AppEvent>) {
+        let Some(git) = self.git_tool() else {
+            return;
+        };
+        spawn_git(tx, move |cwd| {
+            let changes = commit::stage_all(&git, cwd).map(Changes::Staged).map_err(|e| e.to_string());
+            AppEvent::CommitChanges(style, changes)
+        });
+    }
+
+    fn handle_commit_changes(&mut self, style: CommitStyle, changes: Result<Changes, String>, tx: mpsc::Sender<AppEvent>) {
+        match changes {
+            Ok(Changes::Staged(diff)) => self.draft_commit(diff, style, tx),
+            Ok(Changes::Clean) => self.messages.push(Message::new(Role::Tool, "Nothing to commit.".to_string())),
+            Ok(Changes::Unstaged(files)) => {
+                let mut text = String::from("Nothing is staged. Changed files:\n");
+                for file in files {
+                    text.push_str(&format!("- `{}`\n", file));
+                }
+                text.push_str("\nType `y` to stage all of them, anything else to skip.");
+                self.messages.push(Message::new(Role::Tool, text));
+                self.pending_commit = Some(style);
+            }
+            Err(e) => self.messages.push(Message::new(Role::Tool, e)),
+        }
+    }
+
+    fn draft_commit(&mut self, diff: String, style: CommitStyle, tx: mpsc::Sender<AppEvent>) {
+        let Some(provider) = configured_provider(&self.config) else {
+            self.messages.push(Message::new(Role::Tool, "Drafting commit messages needs an OpenRouter API key.".to_string()));
+            return;
+        };
+        self.messages.push(Message::new(Role::Tool, "Drafting a commit message...".to_string()));
+        tokio::spawn(async move {
+            let draft = commit::complete(&provider, &commit::commit_messages(&diff, style))
+                .await
+                .map_err(|e| e.to_string());
+            let _ = tx.send(AppEvent::CommitDraft(draft)).await;
+        });
+    }
+
+    fn finish_commit_edit(&mut self, tx: mpsc::Sender<AppEvent>) {
+        let Some(message) = self.commit_editor.take() else {
+            return;
+        };
+        let message = message.trim();
+        if message.is_empty() {
+            self.messages.push(Message::new(Role::Tool, "Commit aborted: empty message.".to_string()));
+            return;
+        }
+        let Some(git) = self.git_tool() else {
+            return;
+        };
+        let message = message.to_string();
+        spawn_git(tx, move |cwd| {
+            let text = match git.commit(cwd, &message) {
+                Ok(commit) => format!("Committed `{}` {}", short(&commit.hash), commit.subject),
+                Err(e) => format!("Commit failed: {}", e),
+            };
+            AppEvent::Notice(text)
+        });
+    }
+
+    fn shell_tool(&self) -> Result<ShellTool> {
+        let workspace = env::current_dir()?;
+        let config = &self.workspace_config;
+        let mut tool = ShellTool::from_config(&config.shell).with_env(EnvPolicy::from_config(&config.env)?);
+        if let Some(sandbox) = Sandbox::from_config(&config.sandbox, &workspace)? {
+            tool = tool.with_sandbox(sandbox);
+        }
+        Ok(tool)
+    }
+
+    fn start_shell(&mut self, command_line: String, tx: mpsc::Sender<AppEvent>) {
+        let tool = match self.shell_tool() {
+            Ok(tool) => tool,
+            Err(e) => {
+                self.messages.push(Message::new(Role::Tool, format!("Did not run `{}`: {}", command_line, e)));
+                return;
+            }
+        };
+        let cancel = CancellationToken::new();
+        let checkpoints = self.checkpoints.clone().ok();
+        spawn_shell(tool, ShellCommand::script(command_line.clone()), cancel.clone(), checkpoints, tx);
+        self.tool_pane = Some(ToolPane {
+            command_line,
+            lines: Vec::new(),
+            running: true,
+            collapsed: false,
+            cancel,
+        });
+    }
+
+    fn start_pty(&mut self, command_line: String) {
+        // portable-pty spawns without a pre-exec hook, so the sandbox can't apply.
+        let workspace = env::current_dir().unwrap_or_default();
+        if let Ok(Some(_)) = Sandbox::from_config(&self.workspace_config.sandbox, &workspace) {
+            self.messages.push(Message::new(
+                Role::Tool,
+                format!("Did not start `{}`: PTY sessions can't run under a sandbox profile.", command_line),
+            ));
+            return;
+        }
+        let pty = match &self.pty {
+            Ok(pty) => pty,
+            Err(e) => {
+                self.messages.push(Message::new(Role::Tool, format!("Did not start `{}`: {}", command_line, e)));
+                return;
+            }
+        };
+        match pty.start(&ShellCommand::script(command_line.clone())) {
+            Ok(id) => {
+                self.messages.push(Message::new(Role::Tool, format!("Started {} `{}`.", id, command_line)));
+                self.pty_pane = Some(PtyPane {
+                    id,
+                    command_line,
+                    screen: String::new(),
+                    exit_code: None,
+                });
+            }
+            Err(e) => {
+                self.messages.push(Message::new(Role::Tool, format!("Did not start `{}`: {}", command_line, e)));
+            }
+        }
+    }
+
+    // `/pty <command>`, `/pty send <text>` and `/pty kill` drive the PTY pane.
+    fn handle_pty_command(&mut self, args: &str, tx: mpsc::Sender<AppEvent>) {
+        let (verb, rest) = args.split_once(' ').unwrap_or((args, ""));
+        // A pane only exists for a session the manager started.
+        match (verb, self.pty_pane.as_ref(), &self.pty) {
+            ("send", Some(pane), Ok(pty)) => {
+                if let Err(e) = pty.send(&pane.id, &format!("{}\r", rest)) {
+                    self.messages.push(Message::new(Role::Tool, format!("{}: {}", pane.id, e)));
+                }
+            }
+            ("kill", Some(pane), Ok(pty)) => {
+                let id = pane.id.clone();
+                let transcript = pty.transcript(&id).unwrap_or_default();
+                let _ = pty.terminate(&id);
+                self.pty_pane = None;
+                let mut text = format!("Terminated {}.", id);
+                if !transcript.trim().is_empty() {
+                    text = format!("```\n{}\n```\n{}", transcript.trim_end(), text);
+                }
+                self.messages.push(Message::new(Role::Tool, text));
+            }
+            ("send" | "kill", _, _) => {
+                self.messages.push(Message::new(Role::Tool, "No PTY session is running.".to_string()));
+            }
+            ("", _, _) => {
+                self.messages.push(Message::new(Role::Tool, "Usage: /pty <command> | /pty send <text> | /pty kill".to_string()));
+            }
+            _ => self.check_and_start_shell(args.to_string(), CommandTarget::Pty, tx),
+        }
+    }
+
+    fn refresh_pty_pane(&mut self) {
+        let (Some(pane), Ok(pty)) = (self.pty_pane.as_mut(), &self.pty) else {
+            return;
+        };
+        if let Ok(screen) = pty.screen(&pane.id) {
+            pane.screen = screen;
+        }
+        pane.exit_code = pty
+            .list()
+            .into_iter()
+            .find(|info| info.id == pane.id)
+            .and_then(|info| info.exit_code);
+    }
+
+    fn finish_tool(&mut self, result: ToolResult) {
+        if let Some(pane) = self.tool_pane.as_mut() {
+            pane.running = false;
+            let summary = tools::describe_shell_result(&pane.command_line, &result);
+            self.messages.push(Message::new(Role::Tool, summary));
+        }
+    }
+
     fn render_messages(&self) -> Text<'_> {
         let mut lines = Vec::new();
         for message in &self.messages {
@@ -134,6 +698,33 @@ impl App {
     }
 }
 
+fn env_policy(config: &Config) -> Result<EnvPolicy, String> {
+    EnvPolicy::from_config(&config.env).map_err(|e| format!("invalid env config: {:#}", e))
+}
+
+fn configured_provider(config: &Config) -> Option<OpenRouterProvider> {
+    let api_key = env::var("OPENROUTER_API_KEY").ok().or(config.openrouter_api_key.clone())?;
+    (!api_key.is_empty()).then(|| OpenRouterProvider::new(api_key, DEFAULT_MODEL.to_string()))
+}
+
+async fn git_workflow_tools() -> Result<(OpenRouterProvider, GitTool)> {
+    let config = Config::load().await.unwrap_or_default();
+    let Some(provider) = configured_provider(&config) else {
+        anyhow::bail!("No OpenRouter API key found. Set OPENROUTER_API_KEY or configure it.");
+    };
+    let env_policy = EnvPolicy::from_config(&config.env).context("Invalid env config")?;
+    Ok((provider, GitTool::new(env_policy)))
+}
+
+fn describe_job(job: &JobInfo) -> String {
+    let state = match job.state {
+        JobState::Running => format!("running {}s", job.uptime_ms / 1000),
+        JobState::Exited { code: Some(code), .. } => format!("exited {}", code),
+        JobState::Exited { signal, .. } => format!("killed by signal {}", signal.unwrap_or_default()),
+    };
+    format!("- `{}` [{}] `{}`", job.name, state, job.command_line)
+}
+
 struct TerminalGuard;
 
 impl Drop for TerminalGuard {
@@ -164,16 +755,46 @@ fn install_panic_hook() {
     }));
 }
 
-fn print_json_event(event_type: &str, fields: &[(&str, &str)]) {
+fn json_event(event_type: &str, fields: &[(&str, &str)]) -> String {
     let mut map = serde_json::Map::new();
     map.insert("type".to_string(), serde_json::Value::String(event_type.to_string()));
     for (key, value) in fields {
         map.insert(key.to_string(), serde_json::Value::String(value.to_string()));
     }
-    println!("{}", serde_json::Value::Object(map));
+    serde_json::Value::Object(map).to_string()
+}
+
+fn print_json_event(event_type: &str, fields: &[(&str, &str)]) {
+    println!("{}", json_event(event_type, fields));
+}
+
+// Shell access for run mode, set up like the TUI's `!` commands.
+async fn run_tools(git: &GitTool, worktree: bool) -> Result<run::RunTools, String> {
+    let workspace = env::current_dir().map_err(|e| e.to_string())?;
+    let config = Config::load_for_project(&workspace).await.map_err(|e| format!("{:#}", e))?;
+    let env_policy = EnvPolicy::from_config(&config.env).map_err(|e| format!("invalid env config: {:#}", e))?;
+    let policy = ShellPolicy::from_config(&config.shell).map_err(|e| format!("invalid shell rules in config: {:#}", e))?;
+    let mut shell = ShellTool::from_config(&config.shell).with_env(env_policy);
+    if let Some(sandbox) = Sandbox::from_config(&config.sandbox, &workspace).map_err(|e| format!("{:#}", e))? {
+        shell = shell.with_sandbox(sandbox);
+    }
+    Ok(run::RunTools {
+        shell,
+        policy,
+        workspace,
+        // A task worktree is already isolated, so it needs no checkpoint.
+        checkpoints: (!worktree).then(|| Checkpoints::new(git.clone())),
+    })
 }
 
-async fn run_json_mode(prompt: &str, _mode: &str, _cwd: Option<PathBuf>) -> Result<()> {
+#[allow(clippy::vec_init_then_push)]
+async fn run_json_mode(prompt: &str, _mode: &str, cwd: Option<PathBuf>, worktree: Option<WorktreeAction>) -> Result<()> {
+    // Tools resolve paths from the process cwd, and a task worktree is
+    // created from the repository it's in.
+    if let Some(dir) = &cwd {
+        env::set_current_dir(dir).with_context(|| format!("Failed to enter {}", dir.display()))?;
+    }
+
     let config = Config::load().await.unwrap_or_default();
     let api_key = match env::var("OPENROUTER_API_KEY").ok().or(config.openrouter_api_key.clone()) {
         Some(key) if !key.is_empty() => key,
@@ -191,59 +812,102 @@ async fn run_json_mode(prompt: &str, _mode: &str, _cwd: Option<PathBuf>) -> Resu
     messages.push(Message::new(Role::System, system_prompt));
     messages.push(Message::new(Role::User, prompt.to_string()));
 
-    let provider = OpenRouterProvider::new(api_key, "google/gemini-2.0-flash-001".to_string());
+    let provider = OpenRouterProvider::new(api_key, DEFAULT_MODEL.to_string());
 
-    match provider.respond(&messages).await {
-        Ok(events) => {
-            for event in events {
-                match event {
-                    AgentEvent::MessageDelta(content) => {
-                        print_json_event("delta", &[("content", &content)]);
-                    }
-                    AgentEvent::ToolRequest(call) => {
-                        let args_str = call.arguments.to_string();
-                        print_json_event("tool_request", &[
-                            ("name", &call.name),
-                            ("arguments", &args_str),
-                        ]);
-                    }
-                    AgentEvent::ToolResult(result) => {
-                        let output_str = result.output.to_string();
-                        print_json_event("tool_result", &[
-                            ("name", &result.name),
-                            ("output", &output_str),
-                            ("is_error", &result.is_error.to_string()),
-                        ]);
-                    }
-                    AgentEvent::Done => {}
-                }
-            }
+    let env_policy = EnvPolicy::from_config(&config.env).context("Invalid env config")?;
+    let git = GitTool::new(env_policy);
+    let task_worktree = match worktree {
+        Some(_) => Some(worktree::start(&git, &env::current_dir()?)?),
+        None => None,
+    };
+    let tools = run_tools(&git, task_worktree.is_some()).await;
+
+    let failed = match run::run_agent(&provider, messages, tools, &mut |line| println!("{}", line)).await {
+        Ok(()) => {
             print_json_event("done", &[("result", "completed")]);
+            false
         }
         Err(e) => {
             eprintln!("Error: {}", e);
-            std::process::exit(1);
+            true
         }
-    }
+    };
 
+    if let (Some(task_worktree), Some(action)) = (task_worktree, worktree) {
+        worktree::finish(&git, task_worktree, action, prompt)?;
+    }
+    if failed {
+        std::process::exit(1);
+    }
     Ok(())
 }
 
-fn parse_args() -> Option<(String, String, Option<PathBuf>)> {
+enum CliCommand {
+    Run {
+        prompt: String,
+        mode: String,
+        cwd: Option<PathBuf>,
+        worktree: Option<WorktreeAction>,
+    },
+    Commit {
+        style: CommitStyle,
+        yes: bool,
+    },
+    PrDraft {
+        base: String,
+    },
+    Index {
+        watch: bool,
+    },
+}
+
+fn parse_args() -> Result<Option<CliCommand>> {
     let args: Vec<String> = env::args().collect();
     if args.len() < 2 {
-        return None;
+        return Ok(None);
+    }
+
+    if args[1] == "commit" {
+        let flags = &args[2..];
+        let style = if flags.iter().any(|flag| flag == "--conventional") {
+            CommitStyle::Conventional
+        } else {
+            CommitStyle::Plain
+        };
+        let yes = flags.iter().any(|flag| flag == "--yes" || flag == "-y");
+        return Ok(Some(CliCommand::Commit { style, yes }));
+    }
+
+    if args[1] == "pr-draft" {
+        let base = args.get(2).cloned().unwrap_or_else(|| "main".to_string());
+        return Ok(Some(CliCommand::PrDraft { base }));
+    }
+
+    if args[1] == "index" {
+        let watch = args[2..].iter().any(|flag| flag == "--watch");
+        return Ok(Some(CliCommand::Index { watch }));
     }
 
     if args[1] == "run" {
         let mut prompt = None;
         let mut mode = "fs-agent".to_string();
         let mut cwd = None;
+        let mut worktree = None;
 
         let mut i = 2;
         while i < args.len() {
             match args[i].as_str() {
                 "--jsonl" => {}
+                "--worktree" => {
+                    worktree = worktree.or(Some(WorktreeAction::Ask));
+                }
+                "--worktree-action" if i + 1 < args.len() => {
+                    let Some(action) = WorktreeAction::parse(&args[i + 1]) else {
+                        anyhow::bail!("Unknown --worktree-action `{}`; expected ask, merge, keep or discard", args[i + 1]);
+                    };
+                    worktree = Some(action);
+                    i += 1;
+                }
                 "--mode" if i + 1 < args.len() => {
                     mode = args[i + 1].clone();
                     i += 1;
@@ -264,21 +928,32 @@ fn parse_args() -> Option<(String, String, Option<PathBuf>)> {
             i += 1;
         }
 
-        if let Some(p) = prompt {
-            return Some((p, mode, cwd));
+        if let Some(prompt) = prompt {
+            return Ok(Some(CliCommand::Run { prompt, mode, cwd, worktree }));
         }
     }
 
-    None
+    Ok(None)
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    if let Some((prompt, mode, cwd)) = parse_args() {
-        if let Some(ref dir) = cwd {
-            std::env::set_current_dir(dir)?;
+    match parse_args()? {
+        Some(CliCommand::Run { prompt, mode, cwd, worktree }) => {
+            return run_json_mode(&prompt, &mode, cwd, worktree).await;
+        }
+        Some(CliCommand::Commit { style, yes }) => {
+            let (provider, git) = git_workflow_tools().await?;
+            return commit::run_commit_command(&provider, &git, style, yes).await;
         }
-        return run_json_mode(&prompt, &mode, cwd).await;
+        Some(CliCommand::PrDraft { base }) => {
+            let (provider, git) = git_workflow_tools().await?;
+            return commit::run_pr_draft(&provider, &git, &base).await;
+        }
+        Some(CliCommand::Index { watch }) => {
+            return index::run_index_command(watch).await;
+        }
+        None => {}
     }
 
     tracing_subscriber::fmt()
@@ -295,6 +970,11 @@ async fn main() -> Result<()> {
     spawn_tick(tx.clone());
 
     let mut app = App::new().await;
+    if app.workspace_config.index.background {
+        if let Ok(cwd) = env::current_dir() {
+            index::spawn_background(cwd, app.workspace_config.index.clone(), app.config.clone(), tx.clone());
+        }
+    }
 
     loop {
         terminal.draw(|frame| {
@@ -338,21 +1018,62 @@ async fn main() -> Result<()> {
                     frame.render_widget(input, chunks[1]);
                 }
                 AppMode::Chat => {
+                    let pane_height = app.tool_pane.as_ref().map_or(0, ToolPane::height);
+                    let pty_height = if app.pty_pane.is_some() { PTY_PANE_HEIGHT } else { 0 };
                     let chunks = Layout::default()
                         .direction(Direction::Vertical)
-                        .constraints([Constraint::Min(2), Constraint::Length(3)])
+                        .constraints([
+                            Constraint::Min(2),
+                            Constraint::Length(pane_height),
+                            Constraint::Length(pty_height),
+                            Constraint::Length(3),
+                        ])
                         .split(frame.size());
 
-                    let messages = Paragraph::new(app.render_messages())
-                        .block(Block::default()
-                            .borders(Borders::ALL)
-                            .title("Alfred")
-                            .border_style(Style::default().fg(ONEDARK_BLUE))
-                        )
-                        .style(Style::default().fg(ONEDARK_FG).bg(ONEDARK_BG))
-                        .wrap(Wrap { trim: false })
-                        .scroll((app.scroll, 0));
-                    frame.render_widget(messages, chunks[0]);
+                    if let Some(draft) = &app.commit_editor {
+                        let editor = Paragraph::new(format!("{}_", draft))
+                            .block(Block::default()
+                                .borders(Borders::ALL)
+                                .title("Commit message (Ctrl+S to commit, Esc to cancel)")
+                                .border_style(Style::default().fg(ONEDARK_GREEN))
+                            )
+                            .style(Style::default().fg(ONEDARK_FG).bg(ONEDARK_BG))
+                            .wrap(Wrap { trim: false });
+                        frame.render_widget(editor, chunks[0]);
+                    } else {
+                        let messages = Paragraph::new(app.render_messages())
+                            .block(Block::default()
+                                .borders(Borders::ALL)
+                                .title("Alfred")
+                                .border_style(Style::default().fg(ONEDARK_BLUE))
+                            )
+                            .style(Style::default().fg(ONEDARK_FG).bg(ONEDARK_BG))
+                            .wrap(Wrap { trim: false })
+                            .scroll((app.scroll, 0));
+                        frame.render_widget(messages, chunks[0]);
+                    }
+
+                    if let Some(pane) = &app.tool_pane {
+                        let tool = Paragraph::new(pane.render_lines())
+                            .block(Block::default()
+                                .borders(Borders::ALL)
+                                .title(pane.title())
+                                .border_style(Style::default().fg(ONEDARK_CYAN))
+                            )
+                            .style(Style::default().fg(ONEDARK_FG).bg(ONEDARK_BG));
+                        frame.render_widget(tool, chunks[1]);
+                    }
+
+                    if let Some(pane) = &app.pty_pane {
+                        let pty = Paragraph::new(pane.render_lines())
+                            .block(Block::default()
+                                .borders(Borders::ALL)
+                                .title(pane.title())
+                                .border_style(Style::default().fg(ONEDARK_MAGENTA))
+                            )
+                            .style(Style::default().fg(ONEDARK_FG).bg(ONEDARK_BG));
+                        frame.render_widget(pty, chunks[2]);
+                    }
 
                     let input = Paragraph::new(format!("> {}", app.input))
                         .block(Block::default()
@@ -361,7 +1082,7 @@ async fn main() -> Result<()> {
                             .border_style(Style::default().fg(ONEDARK_BLUE))
                         )
                         .style(Style::default().fg(ONEDARK_GREEN).bg(ONEDARK_BG));
-                    frame.render_widget(input, chunks[1]);
+                    frame.render_widget(input, chunks[3]);
                 }
             }
         })?;
@@ -372,9 +1093,38 @@ async fn main() -> Result<()> {
         };
 
         match event {
+            AppEvent::Input(Event::Key(key)) if key.kind == KeyEventKind::Press && app.commit_editor.is_some() => {
+                let control = key.modifiers.contains(KeyModifiers::CONTROL);
+                match key.code {
+                    KeyCode::Char('c') if control => break,
+                    KeyCode::Char('s') if control => app.finish_commit_edit(tx.clone()),
+                    KeyCode::Esc => {
+                        app.commit_editor = None;
+                        app.messages.push(Message::new(Role::Tool, "Commit cancelled; changes stay staged.".to_string()));
+                    }
+                    KeyCode::Enter => app.commit_editor.iter_mut().for_each(|draft| draft.push('\n')),
+                    KeyCode::Backspace => {
+                        app.commit_editor.iter_mut().for_each(|draft| {
+                            draft.pop();
+                        });
+                    }
+                    KeyCode::Char(ch) => app.commit_editor.iter_mut().for_each(|draft| draft.push(ch)),
+                    _ => {}
+                }
+            }
             AppEvent::Input(Event::Key(key)) if key.kind == KeyEventKind::Press => {
                 match key.code {
                     KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                    KeyCode::Char('x') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        if let Some(pane) = &app.tool_pane {
+                            pane.cancel.cancel();
+                        }
+                    }
+                    KeyCode::Char('t') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        if let Some(pane) = app.tool_pane.as_mut() {
+                            pane.collapsed = !pane.collapsed;
+                        }
+                    }
                     KeyCode::Esc => break,
                     KeyCode::Enter => {
                         let content = app.input.trim().to_string();
@@ -395,6 +1145,70 @@ async fn main() -> Result<()> {
                                     app.mode = AppMode::Chat;
                                     app.input.clear();
                                 }
+                                AppMode::Chat if app.pending_command.is_some() => {
+                                    app.input.clear();
+                                    if let Some(pending) = app.pending_command.take() {
+                                        if content.eq_ignore_ascii_case("y") {
+                                            app.start_command(pending.command_line, pending.target, tx.clone());
+                                        } else {
+                                            app.messages.push(Message::new(Role::Tool, format!("Did not run `{}`.", pending.command_line)));
+                                        }
+                                    }
+                                }
+                                AppMode::Chat if content == "/jobs" || content.starts_with("/jobs ") => {
+                                    app.input.clear();
+                                    let args = content["/jobs".len()..].trim().to_string();
+                                    app.handle_jobs_command(&args, tx.clone());
+                                }
+                                AppMode::Chat if app.pending_commit.is_some() => {
+                                    app.input.clear();
+                                    let style = app.pending_commit.take().unwrap_or(CommitStyle::Plain);
+                                    if content.eq_ignore_ascii_case("y") {
+                                        app.stage_all_and_commit(style, tx.clone());
+                                    } else {
+                                        app.messages.push(Message::new(Role::Tool, "Nothing committed.".to_string()));
+                                    }
+                                }
+                                AppMode::Chat if content == "/commit" || content.starts_with("/commit ") => {
+                                    app.input.clear();
+                                    app.start_commit(&content["/commit".len()..], tx.clone());
+                                }
+                                AppMode::Chat if content == "/tools" || content.starts_with("/tools ") => {
+                                    app.input.clear();
+                                    let args = content["/tools".len()..].trim().to_string();
+                                    app.handle_tools_command(&args, tx.clone());
+                                }
+                                AppMode::Chat if content == "/fetch" || content.starts_with("/fetch ") => {
+                                    app.input.clear();
+                                    let url = content["/fetch".len()..].trim().to_string();
+                                    app.fetch_url(url, tx.clone());
+                                }
+                                AppMode::Chat if content == "/rewind" || content.starts_with("/rewind ") => {
+                                    app.input.clear();
+                                    let args = content["/rewind".len()..].to_string();
+                                    match app.checkpoints.clone() {
+                                        Ok(checkpoints) => spawn_git(tx.clone(), move |_| {
+                                            AppEvent::Notice(checkpoints::handle_rewind(&checkpoints, &args))
+                                        }),
+                                        Err(e) => app.messages.push(Message::new(Role::Tool, format!("Did not run git: {}", e))),
+                                    }
+                                }
+                                AppMode::Chat if content == "/pty" || content.starts_with("/pty ") => {
+                                    app.input.clear();
+                                    let args = content["/pty".len()..].trim().to_string();
+                                    app.handle_pty_command(&args, tx.clone());
+                                }
+                                AppMode::Chat if content.starts_with('!') => {
+                                    // One shell command at a time; the pane belongs to it.
+                                    if app.tool_pane.as_ref().is_some_and(|pane| pane.running) {
+                                        continue;
+                                    }
+                                    app.input.clear();
+                                    let command_line = content[1..].trim().to_string();
+                                    if !command_line.is_empty() {
+                                        app.check_and_start_shell(command_line, CommandTarget::Shell, tx.clone());
+                                    }
+                                }
                                 AppMode::Chat => {
                                     app.push_user(content.clone());
                                     app.input.clear();
@@ -405,7 +1219,7 @@ async fn main() -> Result<()> {
                                         .or(app.config.openrouter_api_key.clone());
 
                                     if let Some(key) = api_key {
-                                        spawn_agent(app.messages.clone(), tx.clone(), key);
+                                        spawn_agent(app.messages.clone(), tx.clone(), key, app.checkpoints.clone().ok());
                                     } else {
                                         spawn_mock_agent(content, tx.clone());
                                     }
@@ -431,11 +1245,27 @@ async fn main() -> Result<()> {
             AppEvent::Input(Event::Resize(_, _)) => {}
             AppEvent::AgentChunk(chunk) => app.append_assistant_chunk(chunk),
             AppEvent::AgentDone => app.finish_assistant(),
-            AppEvent::Tick => {}
+            AppEvent::ToolOutput(output) => app.append_tool_output(output),
+            AppEvent::ToolDone(result) => app.finish_tool(result),
+            AppEvent::Notice(text) => app.messages.push(Message::new(Role::Tool, text)),
+            AppEvent::CommitChanges(style, changes) => app.handle_commit_changes(style, changes, tx.clone()),
+            AppEvent::CommitDraft(Ok(draft)) => app.commit_editor = Some(draft),
+            AppEvent::CommitDraft(Err(e)) => {
+                app.messages.push(Message::new(Role::Tool, format!("Could not draft a commit message: {}", e)));
+            }
+            AppEvent::Tick => app.refresh_pty_pane(),
             _ => {}
         }
     }
 
+    if let Some(pane) = app.tool_pane.take() {
+        pane.cancel.cancel();
+    }
+    // Background tasks may still hold the manager, so don't rely on drop.
+    if let Ok(jobs) = &app.jobs {
+        jobs.kill_all();
+    }
+
     Ok(())
 }
 
@@ -470,6 +1300,47 @@ fn spawn_tick(tx: mpsc::Sender<AppEvent>) {
     });
 }
 
+fn spawn_shell(
+    tool: ShellTool,
+    command: ShellCommand,
+    cancel: CancellationToken,
+    checkpoints: Option<Checkpoints>,
+    tx: mpsc::Sender<AppEvent>,
+) {
+    tokio::spawn(async move {
+        if let Some(checkpoints) = checkpoints {
+            let prompt = format!("!{}", command.script.clone().unwrap_or_default());
+            checkpoints::take_checkpoint(checkpoints, prompt).await;
+        }
+        let (events_tx, mut events_rx) = mpsc::channel::<AgentEvent>(256);
+        let forward_tx = tx.clone();
+        let forward = tokio::spawn(async move {
+            while let Some(event) = events_rx.recv().await {
+                if let AgentEvent::ToolOutput(output) = event {
+                    if forward_tx.send(AppEvent::ToolOutput(output)).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        let result = tools::run_shell(&tool, command, cancel, events_tx).await;
+        let _ = forward.await;
+        let _ = tx.send(AppEvent::ToolDone(result)).await;
+    });
+}
+
+// Runs blocking git work in the current directory off the event loop and
+// reports back with the event it returns.
+fn spawn_git(tx: mpsc::Sender<AppEvent>, work: impl FnOnce(&str) -> AppEvent + Send + 'static) {
+    tokio::spawn(async move {
+        let cwd = env::current_dir().unwrap_or_default().to_string_lossy().to_string();
+        let event = tokio::task::spawn_blocking(move || work(&cwd))
+            .await
+            .unwrap_or_else(|e| AppEvent::Notice(format!("git failed: {}", e)));
+        let _ = tx.send(event).await;
+    });
+}
+
 fn spawn_mock_agent(input: String, tx: mpsc::Sender<AppEvent>) {
     tokio::spawn(async move {
         let reply = format!("(mock) I heard: {}", input);
@@ -483,20 +1354,27 @@ fn spawn_mock_agent(input: String, tx: mpsc::Sender<AppEvent>) {
     });
 }
 
-fn spawn_agent(messages: Vec<Message>, tx: mpsc::Sender<AppEvent>, api_key: String) {
+fn spawn_agent(messages: Vec<Message>, tx: mpsc::Sender<AppEvent>, api_key: String, mut checkpoints: Option<Checkpoints>) {
     tokio::spawn(async move {
-        // Use a default model, e.g., google/gemini-2.0-flash-001 (free on OpenRouter) or openai/gpt-3.5-turbo
-        let provider = OpenRouterProvider::new(api_key, "google/gemini-2.0-flash-001".to_string());
+        let prompt = messages.last().map(|message| message.content.clone()).unwrap_or_default();
+        let provider = OpenRouterProvider::new(api_key, DEFAULT_MODEL.to_string());
         
         match provider.respond(&messages).await {
             Ok(events) => {
+                // One checkpoint per turn, before the first call that may write files.
                 for event in events {
+                    #[allow(clippy::collapsible_match)]
                     match event {
                         AgentEvent::MessageDelta(content) => {
                              if tx.send(AppEvent::AgentChunk(content)).await.is_err() {
                                 return;
                             }
                         }
+                        AgentEvent::ToolRequest(call) if checkpoints::writes_files(&call.name) => {
+                            if let Some(checkpoints) = checkpoints.take() {
+                                checkpoints::take_checkpoint(checkpoints, prompt.clone()).await;
+                            }
+                        }
                         _ => {}
                     }
                 }
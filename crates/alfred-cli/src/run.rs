@@ -0,0 +1,223 @@
+use std::path::PathBuf;
+
+use alfred_core::{AgentEvent, AgentRouter, Message, Role, ToolCall, ToolResult};
+use alfred_tools::{Checkpoints, ShellPolicy, ShellTool, Verdict};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::checkpoints;
+use crate::json_event;
+use crate::tools::{self, SHELL_TOOL};
+
+// Model round trips per `alfred run`. Each one may run tool calls whose
+// results go back to the model in the next.
+const MAX_TURNS: usize = 16;
+
+// What tool calls in run mode can use. Nobody is there to confirm a
+// command, so anything the shell rules would ask about is refused.
+pub struct RunTools {
+    pub shell: ShellTool,
+    pub policy: ShellPolicy,
+    pub workspace: PathBuf,
+    // Snapshot the working tree before the first call that may write files;
+    // `None` when that's not needed, e.g. in a task worktree.
+    pub checkpoints: Option<Checkpoints>,
+}
+
+// Runs the agent on `messages`, executing its shell calls and writing every
+// event to `emit` as a JSONL line. Returns once the model stops asking for
+// tools.
+pub async fn run_agent(
+    router: &dyn AgentRouter,
+    mut messages: Vec<Message>,
+    tools: Result<RunTools, String>,
+    emit: &mut dyn FnMut(String),
+) -> anyhow::Result<()> {
+    let prompt = messages.last().map(|message| message.content.clone()).unwrap_or_default();
+    let mut checkpoints = tools.as_ref().ok().and_then(|tools| tools.checkpoints.clone());
+    for _ in 0..MAX_TURNS {
+        let mut reply = String::new();
+        let mut results = Vec::new();
+        for event in router.respond(&messages).await? {
+            match event {
+                AgentEvent::MessageDelta(content) => {
+                    emit(json_event("delta", &[("content", &content)]));
+                    reply.push_str(&content);
+                }
+                AgentEvent::ToolRequest(call) => {
+                    if checkpoints::writes_files(&call.name) {
+                        if let Some(checkpoints) = checkpoints.take() {
+                            if let Some(checkpoint) = checkpoints::take_checkpoint(checkpoints, prompt.clone()).await {
+                                emit(json_event("checkpoint", &[("id", &checkpoint.id)]));
+                            }
+                        }
+                    }
+                    emit(json_event(
+                        "tool_request",
+                        &[("name", &call.name), ("arguments", &call.arguments.to_string())],
+                    ));
+                    let result = execute(&call, &tools, emit).await;
+                    emit_result(&result, emit);
+                    results.push((call, result));
+                }
+                AgentEvent::ToolResult(result) => emit_result(&result, emit),
+                AgentEvent::ToolOutput(_) | AgentEvent::Done => {}
+            }
+        }
+        if results.is_empty() {
+            return Ok(());
+        }
+        messages.push(Message::new(Role::Assistant, reply));
+        for (call, result) in results {
+            let command_line = call.arguments["command"].as_str().unwrap_or_default();
+            messages.push(Message::new(Role::Tool, tools::describe_shell_result(command_line, &result)));
+        }
+    }
+    anyhow::bail!("Stopped after {} turns of tool calls", MAX_TURNS)
+}
+
+// Runs one tool call, streaming its output lines as `tool_output`.
+async fn execute(call: &ToolCall, tools: &Result<RunTools, String>, emit: &mut dyn FnMut(String)) -> ToolResult {
+    let refuse = |reason: String| ToolResult {
+        name: call.name.clone(),
+        output: serde_json::Value::String(reason),
+        is_error: true,
+    };
+    if call.name != SHELL_TOOL {
+        return refuse(format!("`{}` isn't available in run mode", call.name));
+    }
+    let Some(command) = tools::shell_command(&call.arguments) else {
+        return refuse("expected a `command` string".to_string());
+    };
+    let tools = match tools {
+        Ok(tools) => tools,
+        Err(e) => return refuse(e.clone()),
+    };
+    match tools.policy.check(&command, &tools.workspace) {
+        Verdict::Allow => {}
+        Verdict::Deny { reason } => return refuse(format!("blocked: {}", reason)),
+        Verdict::Confirm { reasons } => {
+            return refuse(format!("needs confirmation, which run mode can't ask for: {}", reasons.join("; ")));
+        }
+    }
+
+    let (tx, mut rx) = mpsc::channel::<AgentEvent>(256);
+    let forward = async {
+        while let Some(event) = rx.recv().await {
+            if let AgentEvent::ToolOutput(output) = event {
+                emit(json_event(
+                    "tool_output",
+                    &[("name", &output.name), ("stream", &output.stream), ("line", &output.line)],
+                ));
+            }
+        }
+    };
+    let (result, ()) = tokio::join!(tools::run_shell(&tools.shell, command, CancellationToken::new(), tx), forward);
+    result
+}
+
+fn emit_result(result: &ToolResult, emit: &mut dyn FnMut(String)) {
+    emit(json_event(
+        "tool_result",
+        &[
+            ("name", &result.name),
+            ("output", &result.output.to_string()),
+            ("is_error", &result.is_error.to_string()),
+        ],
+    ));
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use alfred_tools::config::ShellConfig;
+
+    // Replies with each turn in order, recording what it was sent.
+    struct Scripted {
+        turns: Mutex<Vec<Vec<AgentEvent>>>,
+        seen: Mutex<Vec<Vec<Message>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AgentRouter for Scripted {
+        async fn respond(&self, messages: &[Message]) -> anyhow::Result<Vec<AgentEvent>> {
+            self.seen.lock().unwrap().push(messages.to_vec());
+            Ok(self.turns.lock().unwrap().remove(0))
+        }
+    }
+
+    fn shell_call(command: &str) -> AgentEvent {
+        AgentEvent::ToolRequest(ToolCall {
+            name: SHELL_TOOL.to_string(),
+            arguments: serde_json::json!({ "command": command }),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_run_agent_streams_tool_output() {
+        let router = Scripted {
+            turns: Mutex::new(vec![
+                vec![
+                    AgentEvent::MessageDelta("Checking.".to_string()),
+                    shell_call("echo one; echo two >&2"),
+                    shell_call("rm -rf /"),
+                ],
+                vec![AgentEvent::MessageDelta("All good.".to_string()), AgentEvent::Done],
+            ]),
+            seen: Mutex::new(Vec::new()),
+        };
+        let config = ShellConfig {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string()],
+            deny: vec!["rm".to_string()],
+            ..ShellConfig::default()
+        };
+        let tools = RunTools {
+            shell: ShellTool::from_config(&config),
+            policy: ShellPolicy::from_config(&config).unwrap(),
+            workspace: std::env::temp_dir(),
+            checkpoints: None,
+        };
+        let mut lines = Vec::new();
+        let messages = vec![Message::new(Role::User, "check".to_string())];
+        run_agent(&router, messages, Ok(tools), &mut |line| lines.push(line)).await.unwrap();
+
+        let events: Vec<serde_json::Value> = lines.iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let of_type = |kind: &str| events.iter().filter(|event| event["type"] == kind).collect::<Vec<_>>();
+        let mut output: Vec<(&str, &str)> = of_type("tool_output")
+            .iter()
+            .map(|event| (event["stream"].as_str().unwrap(), event["line"].as_str().unwrap()))
+            .collect();
+        output.sort();
+        assert_eq!(output, [("stderr", "two"), ("stdout", "one")]);
+        assert_eq!(of_type("tool_output")[0]["name"], SHELL_TOOL);
+
+        let results = of_type("tool_result");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["is_error"], "false");
+        assert_eq!(results[1]["is_error"], "true");
+        assert!(results[1]["output"].as_str().unwrap().contains("blocked"));
+        assert_eq!(of_type("delta").last().unwrap()["content"], "All good.");
+
+        // The second turn saw both results.
+        let seen = router.seen.lock().unwrap();
+        let second = &seen[1];
+        assert_eq!(second[1].content, "Checking.");
+        assert!(second[2].content.starts_with("$ echo one; echo two >&2\n"));
+        assert!(second[3].content.contains("blocked"));
+    }
+
+    #[tokio::test]
+    async fn test_run_agent_refuses_without_tools() {
+        let router = Scripted {
+            turns: Mutex::new(vec![vec![shell_call("true")], vec![]]),
+            seen: Mutex::new(Vec::new()),
+        };
+        let mut lines = Vec::new();
+        let tools = Err("invalid env config".to_string());
+        run_agent(&router, Vec::new(), tools, &mut |line| lines.push(line)).await.unwrap();
+        assert!(lines.iter().any(|line| line.contains("tool_result") && line.contains("invalid env config")));
+    }
+}
@@ -0,0 +1,88 @@
+use alfred_core::{AgentEvent, ToolOutput, ToolResult};
+use alfred_tools::{OutputLine, ShellCommand, ShellTool};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+pub const SHELL_TOOL: &str = "shell";
+
+// The command for a `shell` tool call: `{"command": "...", "timeout_ms": 1000}`.
+pub fn shell_command(arguments: &serde_json::Value) -> Option<ShellCommand> {
+    let script = arguments.get("command")?.as_str()?;
+    Some(ShellCommand {
+        timeout_ms: arguments.get("timeout_ms").and_then(serde_json::Value::as_u64),
+        ..ShellCommand::script(script)
+    })
+}
+
+// Runs a shell command, forwarding each output line as `AgentEvent::ToolOutput`
+// while it runs and returning the aggregated result for the model.
+pub async fn run_shell(
+    tool: &ShellTool,
+    command: ShellCommand,
+    cancel: CancellationToken,
+    events: mpsc::Sender<AgentEvent>,
+) -> ToolResult {
+    let (tx, mut rx) = mpsc::channel::<OutputLine>(256);
+    let forward = tokio::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            let event = AgentEvent::ToolOutput(ToolOutput {
+                name: SHELL_TOOL.to_string(),
+                stream: line.stream.as_str().to_string(),
+                line: line.line,
+            });
+            if events.send(event).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let result = tool.run_streaming(command, &cancel, tx).await;
+    let _ = forward.await;
+
+    match result {
+        Ok(output) => ToolResult {
+            name: SHELL_TOOL.to_string(),
+            is_error: output.status != 0 || output.timed_out || output.cancelled,
+            output: serde_json::to_value(&output).unwrap_or_default(),
+        },
+        Err(e) => ToolResult {
+            name: SHELL_TOOL.to_string(),
+            output: serde_json::Value::String(e.to_string()),
+            is_error: true,
+        },
+    }
+}
+
+// Renders a shell `ToolResult` as transcript text the model can read back.
+pub fn describe_shell_result(command_line: &str, result: &ToolResult) -> String {
+    let output = &result.output;
+    let Some(status) = output.get("status") else {
+        return format!("$ {}\nfailed to run: {}", command_line, output.as_str().unwrap_or_default());
+    };
+
+    let mut text = format!("$ {}\n", command_line);
+    let stdout = output["stdout"].as_str().unwrap_or_default();
+    let stderr = output["stderr"].as_str().unwrap_or_default();
+    for stream in [stdout, stderr] {
+        if !stream.trim().is_empty() {
+            text.push_str("```\n");
+            text.push_str(stream.trim_end());
+            text.push_str("\n```\n");
+        }
+    }
+    if output["timed_out"].as_bool().unwrap_or(false) {
+        text.push_str("(timed out)");
+    } else if output["cancelled"].as_bool().unwrap_or(false) {
+        text.push_str("(cancelled)");
+    } else {
+        text.push_str(&format!(
+            "exit status {} in {} ms",
+            status,
+            output["duration_ms"].as_u64().unwrap_or(0)
+        ));
+    }
+    if output["output_abandoned"].as_bool().unwrap_or(false) {
+        text.push_str("\n(a background process still held the output open; later output was not captured)");
+    }
+    text
+}
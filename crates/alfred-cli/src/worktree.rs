@@ -0,0 +1,99 @@
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+use alfred_tools::{GitTool, TaskWorktree};
+
+use crate::print_json_event;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorktreeAction {
+    // Prompt on the terminal; without one, keep the branch.
+    Ask,
+    Merge,
+    Keep,
+    Discard,
+}
+
+impl WorktreeAction {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "ask" => Some(WorktreeAction::Ask),
+            "merge" => Some(WorktreeAction::Merge),
+            "keep" => Some(WorktreeAction::Keep),
+            "discard" => Some(WorktreeAction::Discard),
+            _ => None,
+        }
+    }
+}
+
+// Creates a worktree for the repository containing `dir` and moves into
+// the same directory inside it.
+pub fn start(git: &GitTool, dir: &Path) -> anyhow::Result<TaskWorktree> {
+    let worktree = TaskWorktree::create(git, &dir.to_string_lossy())?;
+    // `FsTool`, `ShellTool` and friends all resolve paths from the cwd;
+    // keep it at the same place in the checkout.
+    let subdir = dir
+        .canonicalize()
+        .ok()
+        .and_then(|dir| dir.strip_prefix(&worktree.repo_root).ok().map(Path::to_path_buf))
+        .unwrap_or_default();
+    std::env::set_current_dir(Path::new(&worktree.path).join(subdir))?;
+    print_json_event("worktree", &[("path", &worktree.path), ("branch", &worktree.branch)]);
+    Ok(worktree)
+}
+
+// Commits the task's changes to its branch, reports the diff and then
+// merges, keeps or discards it.
+pub fn finish(git: &GitTool, worktree: TaskWorktree, action: WorktreeAction, prompt: &str) -> anyhow::Result<()> {
+    std::env::set_current_dir(&worktree.repo_root)?;
+    let subject = prompt.lines().next().unwrap_or_default();
+    let subject: String = subject.chars().take(72).collect();
+    let commit = worktree.commit_all(git, &format!("alfred: {}\n\n{}", subject, prompt))?;
+    let diff = worktree.diff(git)?;
+    print_json_event("worktree_diff", &[("branch", &worktree.branch), ("diff", &diff)]);
+
+    let action = match (commit, action) {
+        // Nothing to merge or keep.
+        (None, _) => WorktreeAction::Discard,
+        (Some(_), WorktreeAction::Ask) => ask(&worktree.branch)?,
+        (Some(_), action) => action,
+    };
+    let branch = worktree.branch.clone();
+    let result = match action {
+        WorktreeAction::Merge => match worktree.clone().merge(git) {
+            Ok(()) => "merged",
+            Err(e) => {
+                // Usually conflicts with the user's own changes. The merge
+                // was aborted; leave the branch for them to merge by hand.
+                print_json_event("error", &[("message", &format!("merge failed, keeping branch: {}", e))]);
+                worktree.keep(git)?;
+                "kept"
+            }
+        },
+        WorktreeAction::Keep | WorktreeAction::Ask => {
+            worktree.keep(git)?;
+            "kept"
+        }
+        WorktreeAction::Discard => {
+            worktree.discard(git)?;
+            "discarded"
+        }
+    };
+    print_json_event("worktree_result", &[("action", result), ("branch", &branch)]);
+    Ok(())
+}
+
+fn ask(branch: &str) -> io::Result<WorktreeAction> {
+    if !io::stdin().is_terminal() {
+        return Ok(WorktreeAction::Keep);
+    }
+    eprint!("Changes are on `{}`. [m]erge, [k]eep branch or [d]iscard? ", branch);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(match answer.trim() {
+        "m" | "merge" => WorktreeAction::Merge,
+        "d" | "discard" => WorktreeAction::Discard,
+        _ => WorktreeAction::Keep,
+    })
+}
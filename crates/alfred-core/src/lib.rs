@@ -4,5 +4,5 @@ mod router;
 mod session;
 
 pub use models::{Message, Role};
-pub use router::{AgentEvent, AgentRouter, ToolCall, ToolResult};
+pub use router::{AgentEvent, AgentRouter, ToolCall, ToolOutput, ToolResult};
 pub use session::{AgentSession, SessionConfig, SessionEvent};
@@ -3,7 +3,7 @@ use async_trait::async_trait;
 use reqwest::Client;
 use serde_json::json;
 
-use crate::models::Message;
+use crate::models::{Message, Role};
 use crate::router::{AgentEvent, AgentRouter};
 
 pub struct OpenRouterProvider {
@@ -31,7 +31,7 @@ impl AgentRouter for OpenRouterProvider {
         
         let request_body = json!({
             "model": self.model,
-            "messages": messages,
+            "messages": request_messages(messages),
         });
 
         let response = self.client
@@ -57,6 +57,21 @@ impl AgentRouter for OpenRouterProvider {
     }
 }
 
+// Tool results aren't tied to a `tool_call_id` yet, so the API would reject
+// them with the `tool` role; pass them along as user-provided context instead.
+fn request_messages(messages: &[Message]) -> Vec<serde_json::Value> {
+    messages
+        .iter()
+        .map(|message| match message.role {
+            Role::Tool => json!({
+                "role": "user",
+                "content": format!("[tool output]\n{}", message.content),
+            }),
+            role => json!({ "role": role, "content": message.content }),
+        })
+        .collect()
+}
+
 fn parse_response(json: serde_json::Value) -> Result<Vec<AgentEvent>> {
     let content = json["choices"][0]["message"]["content"]
         .as_str()
@@ -93,6 +108,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_request_messages_maps_tool_role() {
+        let messages = vec![
+            Message::new(Role::User, "run it".to_string()),
+            Message::new(Role::Tool, "exit 0".to_string()),
+        ];
+        let request = request_messages(&messages);
+        assert_eq!(request[0], json!({ "role": "user", "content": "run it" }));
+        assert_eq!(request[1]["role"], "user");
+        assert_eq!(request[1]["content"], "[tool output]\nexit 0");
+    }
+
     #[test]
     fn test_parse_response_missing_content() {
         let response_json = json!({
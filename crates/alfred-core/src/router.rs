@@ -16,10 +16,19 @@ pub struct ToolResult {
     pub is_error: bool,
 }
 
+// One line of incremental output from a running tool, e.g. a shell command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolOutput {
+    pub name: String,
+    pub stream: String,
+    pub line: String,
+}
+
 #[derive(Debug, Clone)]
 pub enum AgentEvent {
     MessageDelta(String),
     ToolRequest(ToolCall),
+    ToolOutput(ToolOutput),
     ToolResult(ToolResult),
     Done,
 }
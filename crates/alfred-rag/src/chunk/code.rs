@@ -0,0 +1,439 @@
+use std::collections::HashSet;
+
+use tree_sitter::{Language, Node, Parser};
+
+use super::{chunk_id, line_windows, make_chunk, windows, ChunkMetadata, ChunkOptions};
+use crate::Chunk;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Lang {
+    Rust,
+    TypeScript,
+    Tsx,
+    Python,
+    Go,
+}
+
+impl Lang {
+    fn grammar(self) -> Language {
+        match self {
+            Lang::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Lang::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Lang::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Lang::Python => tree_sitter_python::LANGUAGE.into(),
+            Lang::Go => tree_sitter_go::LANGUAGE.into(),
+        }
+    }
+
+    fn separator(self) -> &'static str {
+        match self {
+            Lang::Rust => "::",
+            _ => ".",
+        }
+    }
+
+    // Nodes that belong to the definition right after them.
+    fn is_leading(self, kind: &str) -> bool {
+        match self {
+            Lang::Rust => matches!(kind, "line_comment" | "block_comment" | "attribute_item"),
+            _ => kind == "comment",
+        }
+    }
+}
+
+fn lang_for_path(path: &str) -> Option<(Lang, &'static str)> {
+    let extension = path.rsplit_once('.')?.1;
+    Some(match extension {
+        "rs" => (Lang::Rust, "rust"),
+        "ts" | "mts" | "cts" => (Lang::TypeScript, "typescript"),
+        "tsx" => (Lang::Tsx, "typescript"),
+        "js" | "jsx" | "mjs" | "cjs" => (Lang::Tsx, "javascript"),
+        "py" | "pyi" => (Lang::Python, "python"),
+        "go" => (Lang::Go, "go"),
+        _ => return None,
+    })
+}
+
+// The language name recorded in chunk metadata, for extensions the code
+// chunker parses.
+pub fn language_for_path(path: &str) -> Option<&'static str> {
+    lang_for_path(path).map(|(_, name)| name)
+}
+
+struct Definition<'a> {
+    kind: &'static str,
+    name: String,
+    // Replaces `name` in the chunk's id and symbol, so an impl doesn't share
+    // an id with its type: `impl Config`, `impl Display for Config`.
+    label: Option<String>,
+    // Where nested definitions live, for types too big for one chunk.
+    body: Option<Node<'a>>,
+}
+
+fn text<'a>(node: Node, source: &'a str) -> &'a str {
+    node.utf8_text(source.as_bytes()).unwrap_or_default()
+}
+
+fn field_text(node: Node, field: &str, source: &str) -> Option<String> {
+    node.child_by_field_name(field).map(|child| text(child, source).to_string())
+}
+
+fn classify<'a>(lang: Lang, node: Node<'a>, source: &str) -> Option<Definition<'a>> {
+    let named = |kind: &'static str| {
+        Some(Definition {
+            kind,
+            name: field_text(node, "name", source)?,
+            label: None,
+            body: None,
+        })
+    };
+    let container = |kind: &'static str, name: Option<String>, label: Option<String>| {
+        Some(Definition {
+            kind,
+            name: name?,
+            label,
+            body: node.child_by_field_name("body"),
+        })
+    };
+    match (lang, node.kind()) {
+        (Lang::Rust, "function_item" | "function_signature_item") => named("function"),
+        (Lang::Rust, "struct_item") => named("struct"),
+        (Lang::Rust, "enum_item") => named("enum"),
+        (Lang::Rust, "union_item") => named("union"),
+        (Lang::Rust, "macro_definition") => named("macro"),
+        (Lang::Rust, "trait_item") => container("trait", field_text(node, "name", source), None),
+        (Lang::Rust, "mod_item") => container("module", field_text(node, "name", source), None),
+        (Lang::Rust, "impl_item") => {
+            // `impl Display for Config` is filed under `Config`.
+            let name = field_text(node, "type", source)?;
+            let name = name.split('<').next().unwrap_or_default().trim().to_string();
+            let label = match field_text(node, "trait", source) {
+                Some(trait_name) => format!("impl {} for {}", trait_name, name),
+                None => format!("impl {}", name),
+            };
+            container("impl", Some(name), Some(label))
+        }
+
+        (Lang::TypeScript | Lang::Tsx, "export_statement") => {
+            let declaration = node.child_by_field_name("declaration")?;
+            classify(lang, declaration, source)
+        }
+        (Lang::TypeScript | Lang::Tsx, "function_declaration" | "generator_function_declaration") => named("function"),
+        (Lang::TypeScript | Lang::Tsx, "class_declaration" | "abstract_class_declaration") => {
+            container("class", field_text(node, "name", source), None)
+        }
+        (Lang::TypeScript | Lang::Tsx, "method_definition" | "abstract_method_signature") => named("method"),
+        (Lang::TypeScript | Lang::Tsx, "interface_declaration") => named("interface"),
+        (Lang::TypeScript | Lang::Tsx, "enum_declaration") => named("enum"),
+        (Lang::TypeScript | Lang::Tsx, "type_alias_declaration") => named("type"),
+        // `const handler = () => { ... }`
+        (Lang::TypeScript | Lang::Tsx, "lexical_declaration") => {
+            let declarator = node.named_child(0).filter(|child| child.kind() == "variable_declarator")?;
+            let value = declarator.child_by_field_name("value")?;
+            if !matches!(value.kind(), "arrow_function" | "function_expression" | "function") {
+                return None;
+            }
+            Some(Definition {
+                kind: "function",
+                name: field_text(declarator, "name", source)?,
+                label: None,
+                body: None,
+            })
+        }
+
+        (Lang::Python, "decorated_definition") => {
+            let definition = node.child_by_field_name("definition")?;
+            classify(lang, definition, source)
+        }
+        (Lang::Python, "function_definition") => named("function"),
+        (Lang::Python, "class_definition") => container("class", field_text(node, "name", source), None),
+
+        (Lang::Go, "function_declaration") => named("function"),
+        (Lang::Go, "method_declaration") => {
+            // `func (s *Server) Serve()` becomes `Server.Serve`.
+            let receiver = node
+                .child_by_field_name("receiver")
+                .and_then(|receiver| receiver.named_child(0))
+                .and_then(|parameter| parameter.child_by_field_name("type"))
+                .map(|receiver| text(receiver, source).trim_start_matches('*').to_string())?;
+            let receiver = receiver.split('[').next().unwrap_or_default().to_string();
+            Some(Definition {
+                kind: "method",
+                name: format!("{}.{}", receiver, field_text(node, "name", source)?),
+                label: None,
+                body: None,
+            })
+        }
+        (Lang::Go, "type_declaration") => {
+            let spec = node.named_child(0).filter(|child| child.kind() == "type_spec")?;
+            Some(Definition {
+                kind: "type",
+                name: field_text(spec, "name", source)?,
+                label: None,
+                body: None,
+            })
+        }
+        _ => None,
+    }
+}
+
+// Splits source files along function, type and impl/class boundaries with
+// tree-sitter. Types too big for one chunk are split into their members;
+// lines between definitions become `code` chunks. Unknown languages get
+// overlapping line windows.
+#[derive(Debug, Clone, Default)]
+pub struct CodeChunker {
+    options: ChunkOptions,
+}
+
+impl CodeChunker {
+    pub fn new(options: ChunkOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn chunk(&self, path: &str, source: &str) -> Vec<Chunk> {
+        let Some((lang, language)) = lang_for_path(path) else {
+            return line_windows(path, source, None, &self.options);
+        };
+        let mut parser = Parser::new();
+        let tree = match parser.set_language(&lang.grammar()) {
+            Ok(()) => parser.parse(source, None),
+            Err(_) => None,
+        };
+        let Some(tree) = tree else {
+            return line_windows(path, source, Some(language), &self.options);
+        };
+
+        let mut walk = Walk {
+            lang,
+            language,
+            path,
+            source,
+            lines: source.lines().collect(),
+            options: &self.options,
+            ids: HashSet::new(),
+            chunks: Vec::new(),
+        };
+        let root = tree.root_node();
+        if !walk.lines.is_empty() {
+            walk.scope(root, 1, walk.lines.len(), None);
+        }
+        walk.chunks.sort_by_cached_key(|chunk| {
+            let metadata = ChunkMetadata::from_chunk(chunk);
+            metadata.map(|m| (m.start_line, m.end_line)).unwrap_or_default()
+        });
+        walk.chunks
+    }
+}
+
+struct Walk<'s> {
+    lang: Lang,
+    language: &'static str,
+    path: &'s str,
+    source: &'s str,
+    lines: Vec<&'s str>,
+    options: &'s ChunkOptions,
+    ids: HashSet<String>,
+    chunks: Vec<Chunk>,
+}
+
+// 1-based inclusive line span of a node.
+fn span(node: Node) -> (usize, usize) {
+    let start = node.start_position().row + 1;
+    let end = node.end_position();
+    let end = if end.column == 0 && end.row + 1 > start { end.row } else { end.row + 1 };
+    (start, end)
+}
+
+impl Walk<'_> {
+    // Chunks the definitions among `parent`'s children and the lines in
+    // `start..=end` around them.
+    fn scope(&mut self, parent: Node, start: usize, end: usize, prefix: Option<&str>) {
+        let mut covered = Vec::new();
+        let mut leading: Option<usize> = None;
+        let mut previous_end = 0;
+        let mut cursor = parent.walk();
+        for child in parent.named_children(&mut cursor) {
+            let (child_start, child_end) = span(child);
+            if self.lang.is_leading(child.kind()) {
+                if leading.is_none() || child_start > previous_end + 1 {
+                    leading = Some(child_start);
+                }
+                previous_end = child_end;
+                continue;
+            }
+            let attached = leading.take().filter(|_| child_start <= previous_end + 1);
+            previous_end = child_end;
+            let Some(definition) = classify(self.lang, child, self.source) else {
+                continue;
+            };
+            let def_start = attached.unwrap_or(child_start).min(child_start);
+            let qualify = |name: &str| match prefix {
+                Some(prefix) => format!("{}{}{}", prefix, self.lang.separator(), name),
+                None => name.to_string(),
+            };
+            let symbol = qualify(&definition.name);
+            covered.push((def_start, child_end));
+
+            let too_big = child_end + 1 - def_start > self.options.max_lines;
+            match definition.body {
+                Some(body) if too_big => self.scope(body, def_start, child_end, Some(&symbol)),
+                _ => {
+                    let symbol = definition.label.as_deref().map_or(symbol, qualify);
+                    self.emit(def_start, child_end, Some(&symbol), definition.kind)
+                }
+            }
+        }
+
+        // Whatever no definition claimed: imports, constants, statements.
+        let mut line = start;
+        for (from, to) in covered.into_iter().chain([(end + 1, end + 1)]) {
+            if from > line {
+                self.emit_gap(line, from - 1, prefix);
+            }
+            line = line.max(to + 1);
+        }
+    }
+
+    // Lines with no words in them (blank, `}`) don't make a chunk alone.
+    fn emit_gap(&mut self, start: usize, end: usize, symbol: Option<&str>) {
+        let has_words = |line: &usize| self.lines[line - 1].chars().any(char::is_alphanumeric);
+        let Some(first) = (start..=end).find(has_words) else {
+            return;
+        };
+        let last = (start..=end).rev().find(has_words).unwrap_or(first);
+        self.emit(first, last, symbol, "code");
+    }
+
+    fn emit(&mut self, start: usize, end: usize, symbol: Option<&str>, kind: &str) {
+        let spans = if end + 1 - start > self.options.max_lines {
+            windows(start, end, self.options)
+        } else {
+            vec![(start, end)]
+        };
+        let split = spans.len() > 1 || kind == "code";
+        for (start_line, end_line) in spans {
+            let mut id = match (symbol, split) {
+                (Some(symbol), false) => chunk_id(self.path, Some(symbol), start_line, end_line),
+                (Some(symbol), true) => format!("{}:{}-{}", chunk_id(self.path, Some(symbol), 0, 0), start_line, end_line),
+                (None, _) => chunk_id(self.path, None, start_line, end_line),
+            };
+            // Overloads, `#[cfg]` variants and repeated `impl` blocks.
+            if !self.ids.insert(id.clone()) {
+                id = format!("{}@{}", id, start_line);
+                self.ids.insert(id.clone());
+            }
+            let metadata = ChunkMetadata {
+                path: self.path.to_string(),
+                language: Some(self.language.to_string()),
+                symbol: symbol.map(str::to_string),
+                kind: kind.to_string(),
+                start_line,
+                end_line,
+                headings: Vec::new(),
+            };
+            self.chunks.push(make_chunk(id, &self.lines, metadata));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(chunks: &[Chunk]) -> Vec<(String, String, usize, usize)> {
+        chunks
+            .iter()
+            .map(|chunk| {
+                let metadata = ChunkMetadata::from_chunk(chunk).unwrap();
+                (chunk.id.clone(), metadata.kind, metadata.start_line, metadata.end_line)
+            })
+            .collect()
+    }
+
+    const RUST: &str = r#"use std::fmt;
+
+const LIMIT: usize = 3;
+
+/// A config.
+#[derive(Debug)]
+pub struct Config {
+    name: String,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self { name: String::new() }
+    }
+
+    // Loads it.
+    pub fn load(&self) -> usize {
+        let a = 1;
+        let b = 2;
+        a + b
+    }
+}
+
+fn helper() {}
+"#;
+
+    #[test]
+    fn test_rust_symbols() {
+        let chunks = CodeChunker::default().chunk("src/config.rs", RUST);
+        assert_eq!(
+            summary(&chunks),
+            [
+                ("src/config.rs:1-3".to_string(), "code".to_string(), 1, 3),
+                ("src/config.rs#Config".to_string(), "struct".to_string(), 5, 9),
+                ("src/config.rs#impl Config".to_string(), "impl".to_string(), 11, 22),
+                ("src/config.rs#helper".to_string(), "function".to_string(), 24, 24),
+            ]
+        );
+        assert!(chunks[1].text.starts_with("/// A config.\n#[derive(Debug)]"));
+        let impls = "struct A;\nimpl A {}\nimpl Clone for A {\n    fn clone(&self) -> Self { A }\n}\n";
+        let ids: Vec<String> = CodeChunker::default().chunk("a.rs", impls).into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, ["a.rs#A", "a.rs#impl A", "a.rs#impl Clone for A"]);
+
+        // Too big for one chunk, so the impl is split into its methods.
+        let small = CodeChunker::new(ChunkOptions {
+            max_lines: 6,
+            window_lines: 4,
+            overlap_lines: 1,
+        });
+        let chunks = summary(&small.chunk("src/config.rs", RUST));
+        let ids: Vec<&str> = chunks.iter().map(|(id, ..)| id.as_str()).collect();
+        assert!(ids.contains(&"src/config.rs#Config::new"), "{:?}", ids);
+        assert!(ids.contains(&"src/config.rs#Config::load"), "{:?}", ids);
+        let load = chunks.iter().find(|(id, ..)| id == "src/config.rs#Config::load").unwrap();
+        assert_eq!((load.2, load.3), (16, 21));
+        assert!(ids.contains(&"src/config.rs#Config:11-11"), "{:?}", ids);
+    }
+
+    #[test]
+    fn test_other_languages() {
+        let python = "import os\n\n@cached\ndef load(path):\n    return os.stat(path)\n\nclass Store:\n    def get(self):\n        return 1\n";
+        let ids: Vec<String> = CodeChunker::default().chunk("store.py", python).into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, ["store.py:1-1", "store.py#load", "store.py#Store"]);
+
+        let go = "package main\n\ntype Server struct{}\n\nfunc (s *Server) Serve() error {\n\treturn nil\n}\n\nfunc main() {}\n";
+        let ids: Vec<String> = CodeChunker::default().chunk("main.go", go).into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, ["main.go:1-1", "main.go#Server", "main.go#Server.Serve", "main.go#main"]);
+
+        let ts = "// Greets.\nexport function greet(name: string) {\n  return name;\n}\n\nexport const add = (a: number, b: number) => a + b;\n\ninterface Point { x: number }\n\nclass Shape {\n  area(): number { return 0; }\n}\n";
+        let options = ChunkOptions {
+            max_lines: 2,
+            window_lines: 3,
+            overlap_lines: 1,
+        };
+        let chunks = CodeChunker::new(options).chunk("app.ts", ts);
+        let ids: Vec<&str> = chunks.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["app.ts#greet:1-3", "app.ts#greet:3-4", "app.ts#add", "app.ts#Point", "app.ts#Shape:10-10", "app.ts#Shape.area"]);
+        let metadata = ChunkMetadata::from_chunk(&chunks[5]).unwrap();
+        assert_eq!(metadata.language.as_deref(), Some("typescript"));
+        assert_eq!(metadata.symbol.as_deref(), Some("Shape.area"));
+
+        let chunks = CodeChunker::default().chunk("notes.txt", "a\nb\n");
+        assert_eq!(chunks[0].id, "notes.txt:1-2");
+        assert_eq!(ChunkMetadata::from_chunk(&chunks[0]).unwrap().kind, "window");
+    }
+}
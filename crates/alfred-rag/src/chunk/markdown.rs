@@ -0,0 +1,386 @@
+use std::collections::HashSet;
+
+use super::{make_chunk, ChunkMetadata, ChunkOptions};
+use crate::Chunk;
+
+// Sections with fewer non-blank lines than this are merged with a
+// neighbour.
+const MIN_SECTION_LINES: usize = 4;
+
+struct Section {
+    headings: Vec<String>,
+    start: usize,
+    end: usize,
+}
+
+// Splits Markdown by heading hierarchy. Tiny sections are merged into their
+// neighbours, oversize ones are split with overlap, and fenced code blocks
+// are never cut. Each chunk records its heading breadcrumb.
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownChunker {
+    options: ChunkOptions,
+}
+
+impl MarkdownChunker {
+    pub fn new(options: ChunkOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn chunk(&self, path: &str, text: &str) -> Vec<Chunk> {
+        let lines: Vec<&str> = text.lines().collect();
+        let fences = fences(&lines);
+        let sections = merge_small(sections(&lines, &fences), &lines, self.options.max_lines);
+
+        let mut ids = HashSet::new();
+        let mut chunks = Vec::new();
+        for section in sections {
+            // Atoms are single lines, except that a fenced block is one.
+            let mut atoms = Vec::new();
+            let mut line = section.start;
+            while line <= section.end {
+                let end = fences
+                    .iter()
+                    .find(|(start, _)| *start == line)
+                    .map_or(line, |(_, end)| (*end).min(section.end));
+                atoms.push((line, end));
+                line = end + 1;
+            }
+            let spans = pack(&atoms, &self.options);
+            let split = spans.len() > 1;
+            for (start_line, end_line) in spans {
+                let mut id = format!("{}#{}", path, slug(&section.headings));
+                if split {
+                    id = format!("{}:{}-{}", id, start_line, end_line);
+                }
+                if !ids.insert(id.clone()) {
+                    id = format!("{}@{}", id, start_line);
+                    ids.insert(id.clone());
+                }
+                let metadata = ChunkMetadata {
+                    path: path.to_string(),
+                    language: Some("markdown".to_string()),
+                    symbol: None,
+                    kind: "section".to_string(),
+                    start_line,
+                    end_line,
+                    headings: section.headings.clone(),
+                };
+                chunks.push(make_chunk(id, &lines, metadata));
+            }
+        }
+        chunks
+    }
+}
+
+// Plain text: paragraphs packed into windows, overlapping by whole
+// paragraphs where they fit.
+pub fn text_chunks(path: &str, text: &str, options: &ChunkOptions) -> Vec<Chunk> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut paragraphs = Vec::new();
+    let mut start = None;
+    for (index, line) in lines.iter().enumerate() {
+        let number = index + 1;
+        match (line.trim().is_empty(), start) {
+            (false, None) => start = Some(number),
+            (true, Some(from)) => {
+                paragraphs.push((from, number - 1));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        paragraphs.push((from, lines.len()));
+    }
+    pack(&paragraphs, options)
+        .into_iter()
+        .map(|(start_line, end_line)| {
+            let metadata = ChunkMetadata {
+                path: path.to_string(),
+                language: Some("text".to_string()),
+                symbol: None,
+                kind: "text".to_string(),
+                start_line,
+                end_line,
+                headings: Vec::new(),
+            };
+            make_chunk(format!("{}:{}-{}", path, start_line, end_line), &lines, metadata)
+        })
+        .collect()
+}
+
+// 1-based inclusive spans of fenced code blocks. An unclosed fence runs to
+// the end of the file, as CommonMark says.
+fn fences(lines: &[&str]) -> Vec<(usize, usize)> {
+    let mut fences = Vec::new();
+    let mut open: Option<(usize, char, usize)> = None;
+    for (index, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~');
+        let Some(marker) = marker else { continue };
+        let count = trimmed.chars().take_while(|c| *c == marker).count();
+        if count < 3 {
+            continue;
+        }
+        match open {
+            None => open = Some((index + 1, marker, count)),
+            Some((start, open_marker, open_count))
+                if marker == open_marker && count >= open_count && trimmed[count..].trim().is_empty() =>
+            {
+                fences.push((start, index + 1));
+                open = None;
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some((start, _, _)) = open {
+        fences.push((start, lines.len()));
+    }
+    fences
+}
+
+fn in_fence(fences: &[(usize, usize)], line: usize) -> bool {
+    fences.iter().any(|(start, end)| (*start..=*end).contains(&line))
+}
+
+// `# Title` style headings, and `Title` underlined with `===` or `---`.
+fn heading(lines: &[&str], index: usize) -> Option<(usize, String, bool)> {
+    let line = lines[index];
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() < 4 {
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        let rest = &trimmed[level..];
+        if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')) {
+            let title = rest.trim().trim_end_matches('#').trim();
+            return Some((level, title.to_string(), false));
+        }
+    }
+    let next = lines.get(index + 1)?.trim();
+    let level = match next.chars().next()? {
+        '=' => 1,
+        '-' => 2,
+        _ => return None,
+    };
+    let underline = next.chars().all(|c| c == next.chars().next().unwrap_or_default());
+    let paragraph = !trimmed.is_empty() && !trimmed.starts_with(['-', '*', '+', '>', '|']);
+    (underline && paragraph).then(|| (level, trimmed.trim().to_string(), true))
+}
+
+fn sections(lines: &[&str], fences: &[(usize, usize)]) -> Vec<Section> {
+    let mut sections = Vec::new();
+    let mut stack: Vec<(usize, String)> = Vec::new();
+    let mut current = Section {
+        headings: Vec::new(),
+        start: 1,
+        end: 0,
+    };
+    let mut index = 0;
+    while index < lines.len() {
+        let number = index + 1;
+        let found = (!in_fence(fences, number)).then(|| heading(lines, index)).flatten();
+        if let Some((level, title, setext)) = found {
+            if current.end >= current.start {
+                sections.push(current);
+            }
+            stack.retain(|(depth, _)| *depth < level);
+            stack.push((level, title));
+            current = Section {
+                headings: stack.iter().map(|(_, title)| title.clone()).collect(),
+                start: number,
+                end: number,
+            };
+            if setext {
+                index += 1;
+                current.end += 1;
+            }
+        } else {
+            current.end = number;
+        }
+        index += 1;
+    }
+    if current.end >= current.start {
+        sections.push(current);
+    }
+    sections
+}
+
+fn weight(section: &Section, lines: &[&str]) -> usize {
+    lines[section.start - 1..section.end]
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .count()
+}
+
+// A tiny section joins the section after it when that one is nested under
+// it (a title followed by its first subsection), otherwise the one before.
+// Either way the result stays within `max_lines`.
+fn merge_small(sections: Vec<Section>, lines: &[&str], max_lines: usize) -> Vec<Section> {
+    let flush = |merged: &mut Vec<Section>, section: Section| match merged.last_mut() {
+        Some(previous) if section.end + 1 - previous.start <= max_lines => previous.end = section.end,
+        _ => merged.push(section),
+    };
+    let mut merged: Vec<Section> = Vec::new();
+    let mut pending: Option<Section> = None;
+    for mut section in sections {
+        if let Some(parent) = pending.take() {
+            let nested = section.headings.len() > parent.headings.len() && section.headings.starts_with(&parent.headings);
+            if nested && section.end + 1 - parent.start <= max_lines {
+                section.start = parent.start;
+            } else {
+                flush(&mut merged, parent);
+            }
+        }
+        if weight(&section, lines) < MIN_SECTION_LINES {
+            pending = Some(section);
+        } else {
+            merged.push(section);
+        }
+    }
+    if let Some(section) = pending {
+        flush(&mut merged, section);
+    }
+    merged
+}
+
+// Packs atoms into windows of at most `window_lines` lines (an atom bigger
+// than that gets a window to itself) once the total exceeds `max_lines`.
+// Each window repeats the trailing atoms of the one before that fit in
+// `overlap_lines`.
+fn pack(atoms: &[(usize, usize)], options: &ChunkOptions) -> Vec<(usize, usize)> {
+    let (Some(first), Some(last)) = (atoms.first(), atoms.last()) else {
+        return Vec::new();
+    };
+    if last.1 + 1 - first.0 <= options.max_lines {
+        return vec![(first.0, last.1)];
+    }
+    let mut spans = Vec::new();
+    let mut start = 0;
+    while start < atoms.len() {
+        let mut end = start;
+        while end + 1 < atoms.len() && atoms[end + 1].1 + 1 - atoms[start].0 <= options.window_lines {
+            end += 1;
+        }
+        spans.push((atoms[start].0, atoms[end].1));
+        if end + 1 == atoms.len() {
+            break;
+        }
+        // Overlap only as far as still leaves room for the next atom.
+        let mut next = end + 1;
+        while next > start + 1
+            && atoms[end].1 + 1 - atoms[next - 1].0 <= options.overlap_lines
+            && atoms[end + 1].1 + 1 - atoms[next - 1].0 <= options.window_lines
+        {
+            next -= 1;
+        }
+        start = next;
+    }
+    spans
+}
+
+fn slug(headings: &[String]) -> String {
+    if headings.is_empty() {
+        return "top".to_string();
+    }
+    headings
+        .iter()
+        .map(|heading| {
+            let slug: String = heading
+                .to_lowercase()
+                .chars()
+                .filter_map(|c| match c {
+                    c if c.is_alphanumeric() => Some(c),
+                    ' ' | '-' | '_' => Some('-'),
+                    _ => None,
+                })
+                .collect();
+            slug.trim_matches('-').to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "# Alfred
+
+## Goals
+
+- Fast startup.
+- Strong retrieval.
+- Works offline.
+- Small binary.
+
+## Security
+
+Default-deny network.
+Sandboxed shell.
+Env filtering.
+
+```sh
+# not a heading
+alfred run
+```
+
+Setext
+------
+
+One.
+Two.
+Three.
+Four.
+";
+
+    fn summary(chunks: &[Chunk]) -> Vec<(String, usize, usize, String)> {
+        chunks
+            .iter()
+            .map(|chunk| {
+                let metadata = ChunkMetadata::from_chunk(chunk).unwrap();
+                (chunk.id.clone(), metadata.start_line, metadata.end_line, metadata.citation())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sections_and_breadcrumbs() {
+        let chunks = MarkdownChunker::default().chunk("docs/arch.md", DOC);
+        assert_eq!(
+            summary(&chunks),
+            [
+                ("docs/arch.md#alfred/goals".to_string(), 1, 9, "docs/arch.md › Alfred › Goals".to_string()),
+                ("docs/arch.md#alfred/security".to_string(), 10, 20, "docs/arch.md › Alfred › Security".to_string()),
+                ("docs/arch.md#alfred/setext".to_string(), 21, 27, "docs/arch.md › Alfred › Setext".to_string()),
+            ]
+        );
+        assert!(chunks[1].text.contains("# not a heading\nalfred run\n```"));
+    }
+
+    #[test]
+    fn test_oversize_sections_keep_fences() {
+        let options = ChunkOptions {
+            max_lines: 6,
+            window_lines: 5,
+            overlap_lines: 2,
+        };
+        let chunks = MarkdownChunker::new(options).chunk("docs/arch.md", DOC);
+        let security: Vec<_> = summary(&chunks)
+            .into_iter()
+            .filter(|(id, ..)| id.starts_with("docs/arch.md#alfred/security"))
+            .collect();
+        let spans: Vec<(usize, usize)> = security.iter().map(|(_, start, end, _)| (*start, *end)).collect();
+        // The fence (lines 16-19) is never split.
+        assert_eq!(spans, [(10, 14), (13, 15), (15, 19), (20, 20)]);
+        assert_eq!(security[0].0, "docs/arch.md#alfred/security:10-14");
+
+        let text = "one\ntwo\n\nthree\n\nfour\nfive\n";
+        let options = ChunkOptions {
+            max_lines: 3,
+            window_lines: 4,
+            overlap_lines: 1,
+        };
+        let chunks = text_chunks("notes.txt", text, &options);
+        let spans: Vec<_> = summary(&chunks).into_iter().map(|(_, start, end, _)| (start, end)).collect();
+        assert_eq!(spans, [(1, 4), (4, 7)]);
+    }
+}
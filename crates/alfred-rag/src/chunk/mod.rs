@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Chunk;
+
+mod code;
+mod markdown;
+
+pub use code::{language_for_path, CodeChunker};
+pub use markdown::{text_chunks, MarkdownChunker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChunkOptions {
+    // Anything longer is split into overlapping windows.
+    pub max_lines: usize,
+    pub window_lines: usize,
+    pub overlap_lines: usize,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            max_lines: 150,
+            window_lines: 80,
+            overlap_lines: 15,
+        }
+    }
+}
+
+// What `Chunk.metadata` holds for every chunker. Lines are 1-based and
+// inclusive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkMetadata {
+    pub path: String,
+    pub language: Option<String>,
+    // e.g. `Config::load`, `Parser.parse` or `Server.ServeHTTP`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    // `function`, `impl`, `class`, ... or `code` for lines between symbols
+    // and `window` for files without a chunker.
+    pub kind: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    // Markdown heading breadcrumb, outermost first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headings: Vec<String>,
+}
+
+impl ChunkMetadata {
+    pub fn from_chunk(chunk: &Chunk) -> Option<Self> {
+        serde_json::from_value(chunk.metadata.clone()).ok()
+    }
+
+    // `docs/architecture.md › Security` for documents,
+    // `src/config.rs:12-40 (Config::load)` for code.
+    pub fn citation(&self) -> String {
+        if !self.headings.is_empty() {
+            return format!("{} › {}", self.path, self.headings.join(" › "));
+        }
+        let lines = format!("{}:{}-{}", self.path, self.start_line, self.end_line);
+        match &self.symbol {
+            Some(symbol) => format!("{} ({})", lines, symbol),
+            None => lines,
+        }
+    }
+}
+
+// Picks a chunker by file extension.
+pub fn chunk_file(path: &str, text: &str, options: &ChunkOptions) -> Vec<Chunk> {
+    let extension = path.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("md" | "markdown" | "mdx") => MarkdownChunker::new(*options).chunk(path, text),
+        Some("txt" | "text" | "rst" | "adoc") => text_chunks(path, text, options),
+        _ => CodeChunker::new(*options).chunk(path, text),
+    }
+}
+
+// Ids look like `src/config.rs#Config::load`, or `src/config.rs:10-42` for
+// chunks without a symbol, so they sort and read as repo → file → symbol.
+pub(crate) fn chunk_id(path: &str, symbol: Option<&str>, start_line: usize, end_line: usize) -> String {
+    match symbol {
+        Some(symbol) => format!("{}#{}", path, symbol),
+        None => format!("{}:{}-{}", path, start_line, end_line),
+    }
+}
+
+pub(crate) fn make_chunk(id: String, lines: &[&str], metadata: ChunkMetadata) -> Chunk {
+    Chunk {
+        id,
+        text: lines[metadata.start_line - 1..metadata.end_line].join("\n"),
+        metadata: serde_json::to_value(&metadata).unwrap_or_default(),
+    }
+}
+
+// Splits 1-based lines `start..=end` into windows of `window_lines`
+// overlapping by `overlap_lines`.
+pub(crate) fn windows(start: usize, end: usize, options: &ChunkOptions) -> Vec<(usize, usize)> {
+    let size = options.window_lines.max(1);
+    let step = size.saturating_sub(options.overlap_lines).max(1);
+    let mut spans = Vec::new();
+    let mut from = start;
+    loop {
+        let to = (from + size - 1).min(end);
+        spans.push((from, to));
+        if to == end {
+            return spans;
+        }
+        from += step;
+    }
+}
+
+// Overlapping line windows for files no chunker understands.
+pub fn line_windows(path: &str, text: &str, language: Option<&str>, options: &ChunkOptions) -> Vec<Chunk> {
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.iter().all(|line| line.trim().is_empty()) {
+        return Vec::new();
+    }
+    windows(1, lines.len(), options)
+        .into_iter()
+        .map(|(start_line, end_line)| {
+            let metadata = ChunkMetadata {
+                path: path.to_string(),
+                language: language.map(str::to_string),
+                symbol: None,
+                kind: "window".to_string(),
+                start_line,
+                end_line,
+                headings: Vec::new(),
+            };
+            make_chunk(chunk_id(path, None, start_line, end_line), &lines, metadata)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_windows_overlap() {
+        let options = ChunkOptions {
+            window_lines: 4,
+            overlap_lines: 1,
+            ..ChunkOptions::default()
+        };
+        assert_eq!(windows(1, 10, &options), [(1, 4), (4, 7), (7, 10)]);
+        assert_eq!(windows(3, 4, &options), [(3, 4)]);
+
+        let text = (1..=10).map(|i| format!("line {}", i)).collect::<Vec<_>>().join("\n");
+        let chunks = line_windows("notes.txt", &text, None, &options);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[1].id, "notes.txt:4-7");
+        assert!(chunks[1].text.starts_with("line 4\n"));
+        let metadata = ChunkMetadata::from_chunk(&chunks[2]).unwrap();
+        assert_eq!((metadata.start_line, metadata.end_line), (7, 10));
+        assert_eq!(metadata.citation(), "notes.txt:7-10");
+        assert!(line_windows("empty.txt", "\n \n", None, &options).is_empty());
+    }
+}
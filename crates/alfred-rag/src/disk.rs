@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::vector::{check_dimension, dot, normalize, top_k};
+use crate::{fnv1a, Chunk, Index, QueryResult};
+
+const FORMAT_VERSION: u32 = 1;
+const HEADER_FILE: &str = "header.json";
+const LOCK_FILE: &str = "lock";
+// Compact on open once dead slots outnumber live ones by this much.
+const COMPACT_MIN_DEAD: usize = 1024;
+
+// Identifies the embeddings in an index. Opening it with a different model or
+// dimension throws the old contents away.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexHeader {
+    pub version: u32,
+    pub model: String,
+    pub dimension: usize,
+    // Names the current data files; bumped by every compaction.
+    pub generation: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum LogRecord {
+    Add { slot: usize, chunk: Chunk },
+    Remove { id: String },
+}
+
+struct State {
+    // Chunk id -> (slot, chunk).
+    live: HashMap<String, (usize, Chunk)>,
+    // Normalized embeddings, `dimension` floats per slot, dead ones included.
+    vectors: Vec<f32>,
+    vectors_file: File,
+    log_file: File,
+    generation: u64,
+}
+
+impl State {
+    fn slots(&self, dimension: usize) -> usize {
+        self.vectors.len() / dimension
+    }
+}
+
+// A persistent `Index`: an append-only file of flat little-endian `f32`
+// vectors plus a JSON-lines log of adds and removes, both replayed into
+// memory on open. A vector is written before the log record that refers to
+// it, so a crash mid-write leaves at most an unreferenced vector or a torn
+// last line, and both are dropped on the next open. `compact` rewrites the
+// live entries under a new generation and switches to it by atomically
+// replacing the header. Only one `DiskIndex` may have a directory open at a
+// time; it holds an exclusive lock on `lock` until dropped.
+pub struct DiskIndex {
+    dir: PathBuf,
+    header: IndexHeader,
+    invalidated: bool,
+    state: Mutex<State>,
+    _lock: File,
+}
+
+impl DiskIndex {
+    pub fn open(dir: &Path, model: &str, dimension: usize) -> anyhow::Result<Self> {
+        anyhow::ensure!(dimension > 0, "index dimension must be positive");
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let lock = lock(dir)?;
+        let wanted = IndexHeader {
+            version: FORMAT_VERSION,
+            model: model.to_string(),
+            dimension,
+            generation: 0,
+        };
+        let (header, invalidated) = match read_header(dir) {
+            Some(header)
+                if header.version == FORMAT_VERSION && header.model == model && header.dimension == dimension =>
+            {
+                (header, false)
+            }
+            existing => {
+                let header = IndexHeader {
+                    generation: existing.as_ref().map_or(0, |header| header.generation + 1),
+                    ..wanted
+                };
+                File::create(vectors_path(dir, header.generation))?;
+                File::create(log_path(dir, header.generation))?;
+                write_header(dir, &header)?;
+                (header, existing.is_some())
+            }
+        };
+        remove_stale_files(dir, header.generation);
+
+        let state = load(dir, &header)?;
+        let index = Self {
+            dir: dir.to_path_buf(),
+            header,
+            invalidated,
+            state: Mutex::new(state),
+            _lock: lock,
+        };
+        let (live, slots) = {
+            let state = index.state.lock().unwrap();
+            (state.live.len(), state.slots(dimension))
+        };
+        if slots - live > live.max(COMPACT_MIN_DEAD) {
+            index.compact()?;
+        }
+        Ok(index)
+    }
+
+    pub fn header(&self) -> IndexHeader {
+        IndexHeader {
+            generation: self.state.lock().unwrap().generation,
+            ..self.header.clone()
+        }
+    }
+
+    // Whether `open` discarded an index built with another model/dimension.
+    pub fn invalidated(&self) -> bool {
+        self.invalidated
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Slots held by removed or replaced chunks.
+    pub fn dead_slots(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.slots(self.header.dimension) - state.live.len()
+    }
+
+    // Flushes both data files to disk.
+    pub fn sync(&self) -> anyhow::Result<()> {
+        let state = self.state.lock().unwrap();
+        state.vectors_file.sync_data()?;
+        state.log_file.sync_data()?;
+        Ok(())
+    }
+
+    // Rewrites the live entries without dead slots.
+    pub fn compact(&self) -> anyhow::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let dimension = self.header.dimension;
+        let next = IndexHeader {
+            generation: state.generation + 1,
+            ..self.header.clone()
+        };
+
+        let mut entries: Vec<&(usize, Chunk)> = state.live.values().collect();
+        entries.sort_by_key(|(slot, _)| *slot);
+        let mut vectors = Vec::with_capacity(entries.len() * dimension);
+        let mut live = HashMap::with_capacity(entries.len());
+        {
+            let mut vectors_out = BufWriter::new(File::create(vectors_path(&self.dir, next.generation))?);
+            let mut log_out = BufWriter::new(File::create(log_path(&self.dir, next.generation))?);
+            for (new_slot, (slot, chunk)) in entries.into_iter().enumerate() {
+                let vector = &state.vectors[slot * dimension..(slot + 1) * dimension];
+                write_vector(&mut vectors_out, vector)?;
+                vectors.extend_from_slice(vector);
+                write_record(&mut log_out, &LogRecord::Add { slot: new_slot, chunk: chunk.clone() })?;
+                live.insert(chunk.id.clone(), (new_slot, chunk.clone()));
+            }
+            vectors_out.into_inner()?.sync_all()?;
+            log_out.into_inner()?.sync_all()?;
+        }
+        write_header(&self.dir, &next)?;
+        remove_stale_files(&self.dir, next.generation);
+
+        *state = State {
+            live,
+            vectors,
+            vectors_file: append(&vectors_path(&self.dir, next.generation))?,
+            log_file: append(&log_path(&self.dir, next.generation))?,
+            generation: next.generation,
+        };
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Index for DiskIndex {
+    async fn add(&self, chunk: Chunk, embedding: Vec<f32>) -> anyhow::Result<()> {
+        let embedding = normalize(embedding)?;
+        check_dimension(self.header.dimension, &embedding)?;
+        let mut state = self.state.lock().unwrap();
+        let slot = state.slots(self.header.dimension);
+        let mut bytes = Vec::with_capacity(embedding.len() * 4);
+        write_vector(&mut bytes, &embedding)?;
+        state.vectors_file.write_all(&bytes)?;
+        // The slot is taken even if the log write below fails.
+        state.vectors.extend_from_slice(&embedding);
+        let mut record = Vec::new();
+        write_record(&mut record, &LogRecord::Add { slot, chunk: chunk.clone() })?;
+        state.log_file.write_all(&record)?;
+        state.live.insert(chunk.id.clone(), (slot, chunk));
+        Ok(())
+    }
+
+    async fn query(&self, embedding: Vec<f32>, k: usize) -> anyhow::Result<Vec<QueryResult>> {
+        let embedding = normalize(embedding)?;
+        let dimension = self.header.dimension;
+        check_dimension(dimension, &embedding)?;
+        let state = self.state.lock().unwrap();
+        let results = state
+            .live
+            .iter()
+            .map(|(id, (slot, _))| {
+                QueryResult::new(id.clone(), dot(&embedding, &state.vectors[slot * dimension..(slot + 1) * dimension]))
+            })
+            .collect();
+        Ok(top_k(results, k))
+    }
+
+    async fn remove(&self, chunk_id: &str) -> anyhow::Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        if !state.live.contains_key(chunk_id) {
+            return Ok(false);
+        }
+        let mut record = Vec::new();
+        write_record(&mut record, &LogRecord::Remove { id: chunk_id.to_string() })?;
+        state.log_file.write_all(&record)?;
+        state.live.remove(chunk_id);
+        Ok(true)
+    }
+
+    async fn get(&self, chunk_id: &str) -> anyhow::Result<Option<Chunk>> {
+        Ok(self.state.lock().unwrap().live.get(chunk_id).map(|(_, chunk)| chunk.clone()))
+    }
+
+    async fn len(&self) -> anyhow::Result<usize> {
+        Ok(self.state.lock().unwrap().live.len())
+    }
+}
+
+// Where a project's index lives: `<cache>/alfred/index/<name>-<hash>`, keyed
+// by the project's absolute path so nothing is written into the repository.
+pub fn project_cache_dir(root: &Path) -> Option<PathBuf> {
+    let root = dunce::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    let name = root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "root".to_string());
+    let hash = fnv1a(root.to_string_lossy().as_bytes());
+    Some(dirs::cache_dir()?.join("alfred").join("index").join(format!("{}-{:016x}", name, hash)))
+}
+
+// Two writers would append clashing slot numbers to the same log, and one's
+// cleanup could delete the other's current files.
+fn lock(dir: &Path) -> anyhow::Result<File> {
+    let path = dir.join(LOCK_FILE);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => anyhow::bail!(
+            "The index in {} is in use by another alfred process (a TUI or `alfred index`)",
+            dir.display()
+        ),
+        Err(TryLockError::Error(e)) => Err(e).with_context(|| format!("Failed to lock {}", path.display())),
+    }
+}
+
+fn vectors_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("vectors.{}.f32", generation))
+}
+
+fn log_path(dir: &Path, generation: u64) -> PathBuf {
+    dir.join(format!("chunks.{}.log", generation))
+}
+
+fn read_header(dir: &Path) -> Option<IndexHeader> {
+    let content = fs::read_to_string(dir.join(HEADER_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+// Written to a temp file and renamed over the old header.
+fn write_header(dir: &Path, header: &IndexHeader) -> anyhow::Result<()> {
+    let tmp = dir.join(format!("{}.tmp", HEADER_FILE));
+    let mut file = File::create(&tmp)?;
+    file.write_all(serde_json::to_string_pretty(header)?.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp, dir.join(HEADER_FILE))?;
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+// Data files from other generations are leftovers of a compaction or an
+// invalidation that was interrupted.
+fn remove_stale_files(dir: &Path, generation: u64) {
+    let keep = [vectors_path(dir, generation), log_path(dir, generation)];
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let data = name.starts_with("vectors.") || name.starts_with("chunks.");
+        if data && !keep.contains(&path) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn append(path: &Path) -> anyhow::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+fn load(dir: &Path, header: &IndexHeader) -> anyhow::Result<State> {
+    let dimension = header.dimension;
+    let record_bytes = dimension * 4;
+
+    let vectors_path = vectors_path(dir, header.generation);
+    let mut bytes = Vec::new();
+    if let Ok(mut file) = File::open(&vectors_path) {
+        file.read_to_end(&mut bytes)?;
+    }
+    let whole = bytes.len() / record_bytes * record_bytes;
+    if whole != bytes.len() {
+        // A torn vector write; nothing can refer to it yet.
+        OpenOptions::new().write(true).open(&vectors_path)?.set_len(whole as u64)?;
+        bytes.truncate(whole);
+    }
+    let vectors: Vec<f32> = bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    let slots = vectors.len() / dimension;
+
+    let log_path = log_path(dir, header.generation);
+    let mut live = HashMap::new();
+    let mut valid_len = 0u64;
+    if let Ok(file) = File::open(&log_path) {
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            let Ok(record) = serde_json::from_str::<LogRecord>(&line) else {
+                break;
+            };
+            match record {
+                LogRecord::Add { slot, chunk } if slot < slots => {
+                    live.insert(chunk.id.clone(), (slot, chunk));
+                }
+                LogRecord::Add { .. } => break,
+                LogRecord::Remove { id } => {
+                    live.remove(&id);
+                }
+            }
+            valid_len += read as u64;
+        }
+    }
+    let log_file = append(&log_path)?;
+    if log_file.metadata()?.len() > valid_len {
+        log_file.set_len(valid_len)?;
+    }
+
+    Ok(State {
+        live,
+        vectors,
+        vectors_file: append(&vectors_path)?,
+        log_file,
+        generation: header.generation,
+    })
+}
+
+fn write_vector(out: &mut impl Write, vector: &[f32]) -> std::io::Result<()> {
+    for value in vector {
+        out.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn write_record(out: &mut impl Write, record: &LogRecord) -> anyhow::Result<()> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    out.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &str) -> Chunk {
+        Chunk {
+            id: id.to_string(),
+            text: id.to_string(),
+            metadata: serde_json::json!({}),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_persists_across_opens() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let index = DiskIndex::open(dir.path(), "hash-v1", 2).unwrap();
+            assert!(!index.invalidated());
+            index.add(chunk("a"), vec![1.0, 0.0]).await.unwrap();
+            index.add(chunk("b"), vec![0.0, 1.0]).await.unwrap();
+            index.add(chunk("a"), vec![1.0, 1.0]).await.unwrap();
+            index.remove("b").await.unwrap();
+            assert!(index.add(chunk("c"), vec![1.0]).await.is_err());
+            index.sync().unwrap();
+        }
+
+        let index = DiskIndex::open(dir.path(), "hash-v1", 2).unwrap();
+        assert!(!index.invalidated());
+        assert_eq!(index.len().await.unwrap(), 1);
+        assert_eq!(index.dead_slots(), 2);
+        let results = index.query(vec![1.0, 1.0], 5).await.unwrap();
+        assert_eq!(results[0].chunk_id, "a");
+        assert!((results[0].score - 1.0).abs() < 1e-6);
+
+        index.compact().unwrap();
+        assert_eq!(index.dead_slots(), 0);
+        assert_eq!(index.header().generation, 1);
+        index.add(chunk("d"), vec![0.0, 1.0]).await.unwrap();
+        drop(index);
+        let index = DiskIndex::open(dir.path(), "hash-v1", 2).unwrap();
+        assert_eq!(index.len().await.unwrap(), 2);
+        assert_eq!(index.header().generation, 1);
+        assert_eq!(index.get("d").await.unwrap().unwrap().text, "d");
+        let files = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 4);
+
+        // A second writer is turned away until the first is gone.
+        let error = DiskIndex::open(dir.path(), "hash-v1", 2).err().unwrap();
+        assert!(error.to_string().contains("in use by another alfred process"));
+        drop(index);
+
+        // Another model means other embeddings.
+        let index = DiskIndex::open(dir.path(), "hash-v2", 2).unwrap();
+        assert!(index.invalidated());
+        assert!(index.is_empty().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_recovers_from_torn_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = DiskIndex::open(dir.path(), "m", 2).unwrap();
+        index.add(chunk("a"), vec![1.0, 0.0]).await.unwrap();
+        drop(index);
+
+        // Half a vector and half a log line, as if killed mid-`add`.
+        let generation = read_header(dir.path()).unwrap().generation;
+        let mut vectors = append(&vectors_path(dir.path(), generation)).unwrap();
+        vectors.write_all(&[0, 0, 128]).unwrap();
+        let mut log = append(&log_path(dir.path(), generation)).unwrap();
+        log.write_all(b"{\"op\":\"add\",\"slot\":1,\"chu").unwrap();
+
+        let index = DiskIndex::open(dir.path(), "m", 2).unwrap();
+        assert_eq!(index.len().await.unwrap(), 1);
+        index.add(chunk("b"), vec![0.0, 1.0]).await.unwrap();
+        drop(index);
+        let index = DiskIndex::open(dir.path(), "m", 2).unwrap();
+        assert_eq!(index.len().await.unwrap(), 2);
+        assert_eq!(index.query(vec![0.0, 1.0], 1).await.unwrap()[0].chunk_id, "b");
+    }
+}
@@ -0,0 +1,748 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::wordpiece::WordPiece;
+use crate::vector::normalize;
+use crate::{fnv1a, Embedder};
+
+// sentence-transformers models are trained on at most this many tokens.
+const DEFAULT_MAX_TOKENS: usize = 256;
+// Below this many multiply-adds a matmul isn't worth spreading over threads.
+const PARALLEL_MIN_WORK: usize = 1 << 18;
+
+#[derive(Debug, Clone, Deserialize)]
+struct BertConfig {
+    hidden_size: usize,
+    num_hidden_layers: usize,
+    num_attention_heads: usize,
+    intermediate_size: usize,
+    max_position_embeddings: usize,
+    #[serde(default = "default_layer_norm_eps")]
+    layer_norm_eps: f32,
+    #[serde(default = "default_hidden_act")]
+    hidden_act: String,
+}
+
+fn default_layer_norm_eps() -> f32 {
+    1e-12
+}
+
+fn default_hidden_act() -> String {
+    "gelu".to_string()
+}
+
+#[derive(Deserialize)]
+struct TensorInfo {
+    dtype: String,
+    shape: Vec<usize>,
+    data_offsets: (usize, usize),
+}
+
+// The tensors of a `.safetensors` file, converted to `f32`.
+struct SafeTensors {
+    tensors: HashMap<String, (Vec<usize>, Vec<f32>)>,
+    // Hash of the whole file.
+    fingerprint: u64,
+}
+
+impl SafeTensors {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&bytes).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    // An 8-byte little-endian header length, a JSON header of name ->
+    // dtype, shape and byte range, then the raw data.
+    fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        let length = u64::from_le_bytes(bytes.get(..8).context("file too short")?.try_into()?);
+        let end = usize::try_from(length)
+            .ok()
+            .and_then(|length| length.checked_add(8))
+            .context("header out of bounds")?;
+        let header = bytes.get(8..end).context("header out of bounds")?;
+        let data = &bytes[end..];
+        let mut header: HashMap<String, serde_json::Value> = serde_json::from_slice(header)?;
+        header.remove("__metadata__");
+
+        let mut tensors = HashMap::new();
+        for (name, info) in header {
+            let info: TensorInfo = serde_json::from_value(info)?;
+            let raw = data
+                .get(info.data_offsets.0..info.data_offsets.1)
+                .with_context(|| format!("{} out of bounds", name))?;
+            let values: Vec<f32> = match info.dtype.as_str() {
+                "F32" => raw.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+                "F16" => raw.chunks_exact(2).map(|b| f16_to_f32(u16::from_le_bytes([b[0], b[1]]))).collect(),
+                "BF16" => raw
+                    .chunks_exact(2)
+                    .map(|b| f32::from_bits((u16::from_le_bytes([b[0], b[1]]) as u32) << 16))
+                    .collect(),
+                // Integer tensors such as `position_ids` aren't needed.
+                _ => continue,
+            };
+            if values.len() != info.shape.iter().product::<usize>() {
+                bail!("{} has {} values for shape {:?}", name, values.len(), info.shape);
+            }
+            tensors.insert(name, (info.shape, values));
+        }
+        Ok(Self {
+            tensors,
+            fingerprint: fnv1a(bytes),
+        })
+    }
+
+    // Checkpoints saved from `BertModel` have bare names, ones saved from a
+    // task model prefix them with `bert.`, and old ones call the LayerNorm
+    // parameters gamma and beta.
+    fn has(&self, name: &str) -> bool {
+        self.tensors.contains_key(name) || self.tensors.contains_key(&format!("bert.{}", name))
+    }
+
+    fn take(&mut self, name: &str, shape: &[usize]) -> anyhow::Result<Vec<f32>> {
+        let legacy = name.replace("LayerNorm.weight", "LayerNorm.gamma").replace("LayerNorm.bias", "LayerNorm.beta");
+        let candidates = [name.to_string(), format!("bert.{}", name), legacy.clone(), format!("bert.{}", legacy)];
+        let Some((actual, values)) = candidates.iter().find_map(|name| self.tensors.remove(name)) else {
+            bail!("Model has no tensor {}", name);
+        };
+        if actual != shape {
+            bail!("{} has shape {:?}, expected {:?}", name, actual, shape);
+        }
+        Ok(values)
+    }
+
+    // The row count of the `[rows, columns]` matrix whose name ends with
+    // `suffix`, if there is one. The model indexes these by row, so an empty
+    // or oddly shaped one is an error rather than a panic later.
+    fn rows(&self, suffix: &str, columns: usize) -> anyhow::Result<Option<usize>> {
+        let Some((name, (shape, _))) = self.tensors.iter().find(|(name, _)| name.ends_with(suffix)) else {
+            return Ok(None);
+        };
+        match shape[..] {
+            [rows, actual] if rows > 0 && actual == columns => Ok(Some(rows)),
+            _ => bail!("{} has shape {:?}, expected [n, {}] with n > 0", name, shape, columns),
+        }
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    let magnitude = match (exponent, mantissa) {
+        (0, 0) => 0,
+        // Subnormal: renormalize into an f32 exponent.
+        (0, _) => {
+            let shift = mantissa.leading_zeros() - 21;
+            ((127 - 15 + 1 - shift) << 23) | ((mantissa << shift) & 0x3ff) << 13
+        }
+        (0x1f, _) => 0x7f80_0000 | (mantissa << 13),
+        _ => ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(sign | magnitude)
+}
+
+struct Linear {
+    // `output` rows of `input` weights, as PyTorch stores them.
+    weight: Vec<f32>,
+    bias: Vec<f32>,
+    input: usize,
+    output: usize,
+}
+
+impl Linear {
+    fn load(tensors: &mut SafeTensors, name: &str, input: usize, output: usize) -> anyhow::Result<Self> {
+        Ok(Self {
+            weight: tensors.take(&format!("{}.weight", name), &[output, input])?,
+            bias: tensors.take(&format!("{}.bias", name), &[output])?,
+            input,
+            output,
+        })
+    }
+
+    // `x` holds one `input`-wide row per token.
+    fn forward(&self, x: &[f32]) -> Vec<f32> {
+        let rows = x.len() / self.input;
+        let mut out = vec![0.0; rows * self.output];
+        let apply = |first_row: usize, out: &mut [f32]| {
+            for (offset, row) in out.chunks_mut(self.output).enumerate() {
+                let x = &x[(first_row + offset) * self.input..][..self.input];
+                for ((value, weights), bias) in row.iter_mut().zip(self.weight.chunks(self.input)).zip(&self.bias) {
+                    *value = bias + weights.iter().zip(x).map(|(w, x)| w * x).sum::<f32>();
+                }
+            }
+        };
+        let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(rows);
+        if threads <= 1 || rows * self.input * self.output < PARALLEL_MIN_WORK {
+            apply(0, &mut out);
+            return out;
+        }
+        let per_thread = rows.div_ceil(threads);
+        thread::scope(|scope| {
+            for (index, part) in out.chunks_mut(per_thread * self.output).enumerate() {
+                let apply = &apply;
+                scope.spawn(move || apply(index * per_thread, part));
+            }
+        });
+        out
+    }
+}
+
+struct LayerNorm {
+    weight: Vec<f32>,
+    bias: Vec<f32>,
+    eps: f32,
+}
+
+impl LayerNorm {
+    fn load(tensors: &mut SafeTensors, name: &str, size: usize, eps: f32) -> anyhow::Result<Self> {
+        Ok(Self {
+            weight: tensors.take(&format!("{}.weight", name), &[size])?,
+            bias: tensors.take(&format!("{}.bias", name), &[size])?,
+            eps,
+        })
+    }
+
+    fn apply(&self, x: &mut [f32]) {
+        let size = self.weight.len();
+        for row in x.chunks_mut(size) {
+            let mean = row.iter().sum::<f32>() / size as f32;
+            let variance = row.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / size as f32;
+            let scale = 1.0 / (variance + self.eps).sqrt();
+            for ((value, weight), bias) in row.iter_mut().zip(&self.weight).zip(&self.bias) {
+                *value = (*value - mean) * scale * weight + bias;
+            }
+        }
+    }
+}
+
+struct Layer {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    attention_output: Linear,
+    attention_norm: LayerNorm,
+    intermediate: Linear,
+    output: Linear,
+    output_norm: LayerNorm,
+}
+
+// A BERT encoder, used with mean pooling by sentence-transformers models
+// such as all-MiniLM-L6-v2 and with its classification head by
+// cross-encoders. Inference is plain `f32` on the CPU.
+pub struct BertModel {
+    config: BertConfig,
+    word_embeddings: Vec<f32>,
+    position_embeddings: Vec<f32>,
+    // Segment embeddings: type 0 for single texts and for the first text of
+    // a pair, type 1 for the second.
+    token_type_embeddings: Vec<f32>,
+    token_types: usize,
+    embedding_norm: LayerNorm,
+    layers: Vec<Layer>,
+    vocab_size: usize,
+    // The head of `BertForSequenceClassification` checkpoints, used by
+    // cross-encoders.
+    pooler: Option<Linear>,
+    classifier: Option<Linear>,
+    // Changes with `config.json` or any of the weights.
+    fingerprint: u64,
+}
+
+impl BertModel {
+    pub(crate) fn load(dir: &Path) -> anyhow::Result<Self> {
+        let config_path = dir.join("config.json");
+        let text = fs::read_to_string(&config_path).with_context(|| format!("Failed to read {}", config_path.display()))?;
+        let config: BertConfig = serde_json::from_str(&text)?;
+        if !matches!(config.hidden_act.as_str(), "gelu" | "gelu_new" | "gelu_pytorch_tanh" | "relu") {
+            bail!("Unsupported activation {}", config.hidden_act);
+        }
+        if config.num_attention_heads == 0 || !config.hidden_size.is_multiple_of(config.num_attention_heads) {
+            bail!("hidden_size must be a multiple of num_attention_heads");
+        }
+        let mut tensors = SafeTensors::load(&dir.join("model.safetensors"))?;
+        let fingerprint = fnv1a(&[fnv1a(text.as_bytes()).to_le_bytes(), tensors.fingerprint.to_le_bytes()].concat());
+        Ok(Self {
+            fingerprint,
+            ..Self::from_tensors(config, &mut tensors)?
+        })
+    }
+
+    fn from_tensors(config: BertConfig, tensors: &mut SafeTensors) -> anyhow::Result<Self> {
+        let hidden = config.hidden_size;
+        let eps = config.layer_norm_eps;
+        if hidden == 0 {
+            bail!("hidden_size must not be 0");
+        }
+        let vocab_size = tensors
+            .rows("embeddings.word_embeddings.weight", hidden)?
+            .context("Model has no word embeddings")?;
+        let token_types = tensors.rows("embeddings.token_type_embeddings.weight", hidden)?.unwrap_or(1);
+
+        let mut layers = Vec::with_capacity(config.num_hidden_layers);
+        for index in 0..config.num_hidden_layers {
+            let prefix = format!("encoder.layer.{}", index);
+            let name = |suffix: &str| format!("{}.{}", prefix, suffix);
+            layers.push(Layer {
+                query: Linear::load(tensors, &name("attention.self.query"), hidden, hidden)?,
+                key: Linear::load(tensors, &name("attention.self.key"), hidden, hidden)?,
+                value: Linear::load(tensors, &name("attention.self.value"), hidden, hidden)?,
+                attention_output: Linear::load(tensors, &name("attention.output.dense"), hidden, hidden)?,
+                attention_norm: LayerNorm::load(tensors, &name("attention.output.LayerNorm"), hidden, eps)?,
+                intermediate: Linear::load(tensors, &name("intermediate.dense"), hidden, config.intermediate_size)?,
+                output: Linear::load(tensors, &name("output.dense"), config.intermediate_size, hidden)?,
+                output_norm: LayerNorm::load(tensors, &name("output.LayerNorm"), hidden, eps)?,
+            });
+        }
+        let pooler = match tensors.has("pooler.dense.weight") {
+            true => Some(Linear::load(tensors, "pooler.dense", hidden, hidden)?),
+            false => None,
+        };
+        let labels = tensors.rows("classifier.weight", hidden)?;
+        let classifier = match labels {
+            Some(labels) => Some(Linear::load(tensors, "classifier", hidden, labels)?),
+            None => None,
+        };
+        Ok(Self {
+            word_embeddings: tensors.take("embeddings.word_embeddings.weight", &[vocab_size, hidden])?,
+            position_embeddings: tensors
+                .take("embeddings.position_embeddings.weight", &[config.max_position_embeddings, hidden])?,
+            token_type_embeddings: tensors.take("embeddings.token_type_embeddings.weight", &[token_types, hidden])?,
+            token_types,
+            embedding_norm: LayerNorm::load(tensors, "embeddings.LayerNorm", hidden, eps)?,
+            layers,
+            vocab_size,
+            pooler,
+            classifier,
+            config,
+            fingerprint: 0,
+        })
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.config.hidden_size
+    }
+
+    pub(crate) fn has_classifier(&self) -> bool {
+        self.pooler.is_some() && self.classifier.is_some()
+    }
+
+    pub(crate) fn max_tokens(&self) -> usize {
+        self.config.max_position_embeddings
+    }
+
+    // Encodes a batch of token sequences to unit-length sentence vectors.
+    pub fn forward(&self, batch: &[Vec<u32>]) -> Vec<Vec<f32>> {
+        let hidden = self.config.hidden_size;
+        let inputs: Vec<(&[u32], &[u32])> = batch.iter().map(|ids| (ids.as_slice(), &[][..])).collect();
+        let (x, spans) = self.encode(&inputs);
+        spans
+            .iter()
+            .map(|(start, length)| {
+                let mut pooled = vec![0.0; hidden];
+                for row in x[start * hidden..(start + length) * hidden].chunks(hidden) {
+                    pooled.iter_mut().zip(row).for_each(|(p, v)| *p += v);
+                }
+                pooled.iter_mut().for_each(|p| *p /= (*length).max(1) as f32);
+                normalize(pooled).unwrap_or_default()
+            })
+            .collect()
+    }
+
+    // Relevance scores for `(ids, token types)` pairs from the pooler and
+    // classifier head; see `relevance`.
+    pub(crate) fn classify(&self, batch: &[(&[u32], &[u32])]) -> anyhow::Result<Vec<f32>> {
+        let (Some(pooler), Some(classifier)) = (&self.pooler, &self.classifier) else {
+            bail!("The model has no classification head; is it a cross-encoder?");
+        };
+        let hidden = self.config.hidden_size;
+        let (x, spans) = self.encode(batch);
+        let cls: Vec<f32> = spans
+            .iter()
+            .flat_map(|(start, _)| x[start * hidden..][..hidden].iter().copied())
+            .collect();
+        let mut pooled = pooler.forward(&cls);
+        pooled.iter_mut().for_each(|v| *v = v.tanh());
+        let logits = classifier.forward(&pooled);
+        Ok(logits.chunks(classifier.output).map(relevance).collect())
+    }
+
+    // Runs the encoder over a batch of `(ids, token types)`, where empty
+    // types mean all 0. The sequences are stacked without padding: the dense
+    // layers see one matrix for the whole batch and attention runs per
+    // sequence. Returns the hidden states and each sequence's first row and
+    // length.
+    fn encode(&self, batch: &[(&[u32], &[u32])]) -> (Vec<f32>, Vec<(usize, usize)>) {
+        let hidden = self.config.hidden_size;
+        let mut spans = Vec::with_capacity(batch.len());
+        let mut x = Vec::new();
+        for (ids, types) in batch {
+            let ids = &ids[..ids.len().min(self.config.max_position_embeddings)];
+            spans.push((x.len() / hidden, ids.len()));
+            for (position, id) in ids.iter().enumerate() {
+                let id = (*id as usize).min(self.vocab_size - 1);
+                let token_type = types.get(position).map_or(0, |t| (*t as usize).min(self.token_types - 1));
+                let word = &self.word_embeddings[id * hidden..][..hidden];
+                let position = &self.position_embeddings[position * hidden..][..hidden];
+                let token_type = &self.token_type_embeddings[token_type * hidden..][..hidden];
+                x.extend(word.iter().zip(position).zip(token_type).map(|((w, p), t)| w + p + t));
+            }
+        }
+        self.embedding_norm.apply(&mut x);
+
+        for layer in &self.layers {
+            let context = self.attention(layer, &x, &spans);
+            let mut attended = layer.attention_output.forward(&context);
+            attended.iter_mut().zip(&x).for_each(|(a, x)| *a += x);
+            layer.attention_norm.apply(&mut attended);
+
+            let mut intermediate = layer.intermediate.forward(&attended);
+            intermediate.iter_mut().for_each(|v| *v = self.activate(*v));
+            let mut output = layer.output.forward(&intermediate);
+            output.iter_mut().zip(&attended).for_each(|(o, a)| *o += a);
+            layer.output_norm.apply(&mut output);
+            x = output;
+        }
+        (x, spans)
+    }
+
+    fn attention(&self, layer: &Layer, x: &[f32], spans: &[(usize, usize)]) -> Vec<f32> {
+        let hidden = self.config.hidden_size;
+        let heads = self.config.num_attention_heads;
+        let size = hidden / heads;
+        let scale = 1.0 / (size as f32).sqrt();
+        let (query, key, value) = (layer.query.forward(x), layer.key.forward(x), layer.value.forward(x));
+        let mut context = vec![0.0; x.len()];
+        let mut scores = Vec::new();
+        for &(start, length) in spans {
+            for head in 0..heads {
+                let column = head * size;
+                let at = |row: usize| (start + row) * hidden + column;
+                for i in 0..length {
+                    let q = &query[at(i)..][..size];
+                    scores.clear();
+                    scores.extend((0..length).map(|j| {
+                        let k = &key[at(j)..][..size];
+                        q.iter().zip(k).map(|(q, k)| q * k).sum::<f32>() * scale
+                    }));
+                    let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                    scores.iter_mut().for_each(|s| *s = (*s - max).exp());
+                    let total: f32 = scores.iter().sum();
+                    let out = at(i);
+                    for (j, weight) in scores.iter().enumerate() {
+                        let v = &value[at(j)..][..size];
+                        for (c, v) in context[out..out + size].iter_mut().zip(v) {
+                            *c += weight / total * v;
+                        }
+                    }
+                }
+            }
+        }
+        context
+    }
+
+    fn activate(&self, x: f32) -> f32 {
+        match self.config.hidden_act.as_str() {
+            "relu" => x.max(0.0),
+            "gelu" => 0.5 * x * (1.0 + erf(x / std::f32::consts::SQRT_2)),
+            _ => 0.5 * x * (1.0 + (0.797_884_6 * (x + 0.044_715 * x * x * x)).tanh()),
+        }
+    }
+}
+
+// Abramowitz and Stegun 7.1.26, accurate to about 1.5e-7.
+fn erf(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let polynomial = t * (0.254_829_6 + t * (-0.284_496_7 + t * (1.421_413_8 + t * (-1.453_152 + t * 1.061_405_4))));
+    let y = 1.0 - polynomial * (-x * x).exp();
+    y.copysign(x)
+}
+
+// Runs a sentence-embedding model from a local directory holding
+// `config.json`, `model.safetensors` and `tokenizer.json` or `vocab.txt`,
+// e.g. a download of sentence-transformers/all-MiniLM-L6-v2. Nothing
+// touches the network.
+#[derive(Clone)]
+pub struct LocalEmbedder {
+    model: Arc<BertModel>,
+    tokenizer: Arc<WordPiece>,
+    model_id: String,
+    max_tokens: usize,
+    batch_size: usize,
+}
+
+impl LocalEmbedder {
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let model = BertModel::load(dir)?;
+        let tokenizer = WordPiece::load(dir)?;
+        // sentence-transformers records the length it was trained on.
+        let max_tokens = fs::read_to_string(dir.join("sentence_bert_config.json"))
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .and_then(|config| config.get("max_seq_length").and_then(|value| value.as_u64()))
+            .map_or(DEFAULT_MAX_TOKENS, |length| length as usize)
+            .min(model.config.max_position_embeddings);
+        let name = dir
+            .file_name()
+            .map_or_else(|| "model".to_string(), |name| name.to_string_lossy().to_string());
+        // The fingerprint makes other weights in the same directory
+        // invalidate the index, even at the same dimension.
+        Ok(Self {
+            model_id: format!("local:{}@{:016x}", name, model.fingerprint),
+            model: Arc::new(model),
+            tokenizer: Arc::new(tokenizer),
+            max_tokens,
+            batch_size: 16,
+        })
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.model.dimension()
+    }
+
+    pub fn model_id(&self) -> &str {
+        &self.model_id
+    }
+
+    // Blocking; `Embedder` runs this on the blocking pool. Texts of similar
+    // length are batched together so short ones don't wait on long ones.
+    pub fn embed_texts(&self, texts: &[String]) -> Vec<Vec<f32>> {
+        let encoded: Vec<Vec<u32>> = texts
+            .iter()
+            .map(|text| self.tokenizer.encode(text, self.max_tokens))
+            .collect();
+        let mut order: Vec<usize> = (0..texts.len()).collect();
+        order.sort_by_key(|index| encoded[*index].len());
+
+        let mut embeddings = vec![Vec::new(); texts.len()];
+        for group in order.chunks(self.batch_size) {
+            let batch: Vec<Vec<u32>> = group.iter().map(|index| encoded[*index].clone()).collect();
+            for (index, embedding) in group.iter().zip(self.model.forward(&batch)) {
+                embeddings[*index] = embedding;
+            }
+        }
+        embeddings
+    }
+}
+
+#[async_trait]
+impl Embedder for LocalEmbedder {
+    async fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>> {
+        let mut embeddings = self.embed_batch(&[text.to_string()]).await?;
+        Ok(embeddings.remove(0))
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let embedder = self.clone();
+        let texts = texts.to_vec();
+        Ok(tokio::task::spawn_blocking(move || embedder.embed_texts(&texts)).await?)
+    }
+}
+
+// One label is a relevance logit already. With more, the second one is
+// "relevant" and the score is its log-odds against the rest, which orders
+// pairs by its probability; its logit alone doesn't.
+fn relevance(row: &[f32]) -> f32 {
+    let [_, relevant, ..] = row else {
+        return row[0];
+    };
+    let others = row.iter().enumerate().filter(|(label, _)| *label != 1).map(|(_, logit)| *logit);
+    let max = others.clone().fold(f32::NEG_INFINITY, f32::max);
+    relevant - (max + others.map(|logit| (logit - max).exp()).sum::<f32>().ln())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::vector::dot;
+
+    const HIDDEN: usize = 8;
+    const INTERMEDIATE: usize = 16;
+    const POSITIONS: usize = 16;
+    pub(crate) const VOCAB: &[&str] = &["[PAD]", "[UNK]", "[CLS]", "[SEP]", "load", "config", "file", "render", "widget", "##s"];
+
+    // Writes a tiny random BERT in the layout a real download has, with a
+    // one-label cross-encoder head if `head` is set.
+    pub(crate) fn write_model(dir: &Path, head: bool) {
+        let mut seed = 7u64;
+        let mut random = move |count: usize| -> Vec<f32> {
+            (0..count)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    ((seed >> 40) as f32 / (1u64 << 24) as f32 - 0.5) * 0.5
+                })
+                .collect()
+        };
+        let mut tensors: Vec<(String, Vec<usize>, Vec<f32>)> = Vec::new();
+        let mut add = |name: String, shape: Vec<usize>, values: Vec<f32>| tensors.push((name, shape, values));
+        add("embeddings.word_embeddings.weight".into(), vec![VOCAB.len(), HIDDEN], random(VOCAB.len() * HIDDEN));
+        add("embeddings.position_embeddings.weight".into(), vec![POSITIONS, HIDDEN], random(POSITIONS * HIDDEN));
+        add("embeddings.token_type_embeddings.weight".into(), vec![2, HIDDEN], random(2 * HIDDEN));
+        add("embeddings.LayerNorm.weight".into(), vec![HIDDEN], vec![1.0; HIDDEN]);
+        add("embeddings.LayerNorm.bias".into(), vec![HIDDEN], vec![0.0; HIDDEN]);
+        for layer in 0..2 {
+            let p = format!("encoder.layer.{}", layer);
+            for (name, input, output) in [
+                ("attention.self.query", HIDDEN, HIDDEN),
+                ("attention.self.key", HIDDEN, HIDDEN),
+                ("attention.self.value", HIDDEN, HIDDEN),
+                ("attention.output.dense", HIDDEN, HIDDEN),
+                ("intermediate.dense", HIDDEN, INTERMEDIATE),
+                ("output.dense", INTERMEDIATE, HIDDEN),
+            ] {
+                add(format!("{}.{}.weight", p, name), vec![output, input], random(output * input));
+                add(format!("{}.{}.bias", p, name), vec![output], random(output));
+            }
+            for name in ["attention.output.LayerNorm", "output.LayerNorm"] {
+                add(format!("{}.{}.weight", p, name), vec![HIDDEN], vec![1.0; HIDDEN]);
+                add(format!("{}.{}.bias", p, name), vec![HIDDEN], vec![0.0; HIDDEN]);
+            }
+        }
+        if head {
+            add("pooler.dense.weight".into(), vec![HIDDEN, HIDDEN], random(HIDDEN * HIDDEN));
+            add("pooler.dense.bias".into(), vec![HIDDEN], random(HIDDEN));
+            add("classifier.weight".into(), vec![1, HIDDEN], random(HIDDEN));
+            add("classifier.bias".into(), vec![1], vec![0.0]);
+        }
+
+        let mut header = serde_json::Map::new();
+        let mut data = Vec::new();
+        for (name, shape, values) in tensors {
+            let start = data.len();
+            data.extend(values.iter().flat_map(|v| v.to_le_bytes()));
+            header.insert(
+                name,
+                serde_json::json!({ "dtype": "F32", "shape": shape, "data_offsets": [start, data.len()] }),
+            );
+        }
+        let header = serde_json::to_vec(&header).unwrap();
+        let mut file = (header.len() as u64).to_le_bytes().to_vec();
+        file.extend(header);
+        file.extend(data);
+        fs::write(dir.join("model.safetensors"), file).unwrap();
+        fs::write(dir.join("vocab.txt"), VOCAB.join("\n")).unwrap();
+        let config = serde_json::json!({
+            "hidden_size": HIDDEN,
+            "num_hidden_layers": 2,
+            "num_attention_heads": 2,
+            "intermediate_size": INTERMEDIATE,
+            "max_position_embeddings": POSITIONS,
+            "hidden_act": "gelu",
+        });
+        fs::write(dir.join("config.json"), config.to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert!(f16_to_f32(0x7c00).is_infinite());
+        assert!((erf(0.5) - 0.520_499_9).abs() < 1e-6);
+        assert!((erf(-1.5) + 0.966_105_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_threaded_linear_matches_serial() {
+        let (input, output, rows) = (64, 96, 64);
+        assert!(rows * input * output >= PARALLEL_MIN_WORK);
+        let linear = Linear {
+            weight: (0..input * output).map(|i| (i % 7) as f32 - 3.0).collect(),
+            bias: (0..output).map(|i| i as f32).collect(),
+            input,
+            output,
+        };
+        let x: Vec<f32> = (0..rows * input).map(|i| (i % 5) as f32 * 0.5).collect();
+        let out = linear.forward(&x);
+        for (row, values) in out.chunks(output).enumerate() {
+            let x = &x[row * input..][..input];
+            for (o, value) in values.iter().enumerate() {
+                let expected = o as f32 + linear.weight[o * input..][..input].iter().zip(x).map(|(w, x)| w * x).sum::<f32>();
+                assert_eq!(*value, expected);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_embedder_batches_match_single() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path(), false);
+        let embedder = LocalEmbedder::load(dir.path()).unwrap().with_batch_size(2);
+        assert_eq!(embedder.dimension(), HIDDEN);
+
+        let texts: Vec<String> = ["load config file", "render widgets", "config", "load the config files"]
+            .iter()
+            .map(|text| text.to_string())
+            .collect();
+        let batched = embedder.embed_batch(&texts).await.unwrap();
+        assert_eq!(batched.len(), texts.len());
+        for (text, embedding) in texts.iter().zip(&batched) {
+            let single = embedder.embed(text).await.unwrap();
+            assert!(single.iter().zip(embedding).all(|(a, b)| (a - b).abs() < 1e-5));
+            assert!((dot(embedding, embedding) - 1.0).abs() < 1e-4);
+        }
+        assert!(dot(&batched[0], &batched[1]) < 0.9999);
+
+        // Inputs longer than the position table are truncated, not rejected.
+        let long = "config ".repeat(100);
+        assert_eq!(embedder.embed(&long).await.unwrap().len(), HIDDEN);
+
+        // Same directory and shapes, different weights: a different model.
+        let path = dir.path().join("model.safetensors");
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, bytes).unwrap();
+        let changed = LocalEmbedder::load(dir.path()).unwrap();
+        assert!(changed.model_id().starts_with("local:"));
+        assert_ne!(changed.model_id(), embedder.model_id());
+    }
+
+    #[test]
+    fn test_relevance_is_log_odds() {
+        assert_eq!(relevance(&[1.5]), 1.5);
+        assert_eq!(relevance(&[0.0, 1.0]), 1.0);
+        // P(relevant) is 0.27 for the first row and 0.73 for the second.
+        assert!(relevance(&[5.0, 4.0]) < relevance(&[0.0, 1.0]));
+        assert!((relevance(&[0.0, 0.0, 0.0]) + 2f32.ln()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_malformed_header_is_an_error() {
+        let mut bytes = u64::MAX.to_le_bytes().to_vec();
+        bytes.extend(b"{}");
+        assert!(SafeTensors::parse(&bytes).is_err());
+        assert!(SafeTensors::parse(&[1, 2, 3]).is_err());
+
+        // Well-formed files with shapes the model can't index.
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path(), true);
+        let config: BertConfig = serde_json::from_str(&fs::read_to_string(dir.path().join("config.json")).unwrap()).unwrap();
+        let load = |name: &str, shape: Vec<usize>| {
+            let mut tensors = SafeTensors::load(&dir.path().join("model.safetensors")).unwrap();
+            let values = vec![0.0; shape.iter().product()];
+            tensors.tensors.insert(name.to_string(), (shape, values));
+            BertModel::from_tensors(config.clone(), &mut tensors).err().unwrap().to_string()
+        };
+        assert!(load("embeddings.word_embeddings.weight", vec![]).contains("word_embeddings"));
+        assert!(load("embeddings.word_embeddings.weight", vec![HIDDEN]).contains("word_embeddings"));
+        assert!(load("embeddings.word_embeddings.weight", vec![0, HIDDEN]).contains("word_embeddings"));
+        assert!(load("embeddings.word_embeddings.weight", vec![VOCAB.len(), HIDDEN + 1]).contains("word_embeddings"));
+        assert!(load("embeddings.token_type_embeddings.weight", vec![0, HIDDEN]).contains("token_type"));
+        assert!(load("classifier.weight", vec![0, HIDDEN]).contains("classifier"));
+
+        let mut tensors = SafeTensors::load(&dir.path().join("model.safetensors")).unwrap();
+        let config = BertConfig { hidden_size: 0, ..config };
+        assert!(BertModel::from_tensors(config, &mut tensors).is_err());
+    }
+}
@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+
+use crate::lexical::tokenize;
+use crate::vector::normalize;
+use crate::{fnv1a, Embedder};
+
+// Feature hashing over `tokenize` terms: each term adds ±1 to one of
+// `dimension` buckets. Needs no model or network and is deterministic, so
+// indexing always works, but it only matches shared words, not meaning.
+#[derive(Debug, Clone)]
+pub struct HashEmbedder {
+    dimension: usize,
+}
+
+impl HashEmbedder {
+    pub fn new(dimension: usize) -> Self {
+        Self {
+            dimension: dimension.max(1),
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    // Recorded in index headers so a dimension change rebuilds the index.
+    pub fn model_id(&self) -> String {
+        format!("hash-{}", self.dimension)
+    }
+
+    pub fn embed_text(&self, text: &str) -> Vec<f32> {
+        let mut embedding = vec![0.0; self.dimension];
+        for token in tokenize(text) {
+            let hash = fnv1a(token.as_bytes());
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            embedding[(hash % self.dimension as u64) as usize] += sign;
+        }
+        normalize(embedding).unwrap_or_default()
+    }
+}
+
+impl Default for HashEmbedder {
+    fn default() -> Self {
+        Self::new(512)
+    }
+}
+
+#[async_trait]
+impl Embedder for HashEmbedder {
+    async fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>> {
+        Ok(self.embed_text(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::dot;
+
+    #[test]
+    fn test_hash_embedding_is_deterministic_and_normalized() {
+        let embedder = HashEmbedder::new(64);
+        let a = embedder.embed_text("fn load_config(path) reads the config file");
+        assert_eq!(a, embedder.embed_text("fn load_config(path) reads the config file"));
+        assert_eq!(a.len(), 64);
+        assert!((dot(&a, &a) - 1.0).abs() < 1e-5);
+
+        let near = embedder.embed_text("load config");
+        let far = embedder.embed_text("render the terminal widget");
+        assert!(dot(&a, &near) > dot(&a, &far));
+        assert!(embedder.embed_text("").iter().all(|x| *x == 0.0));
+    }
+}
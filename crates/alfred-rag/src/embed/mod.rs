@@ -0,0 +1,9 @@
+pub(crate) mod bert;
+mod hash;
+mod remote;
+mod wordpiece;
+
+pub use bert::LocalEmbedder;
+pub use hash::HashEmbedder;
+pub use remote::{RemoteEmbedder, RemoteEmbedderConfig};
+pub use wordpiece::WordPiece;
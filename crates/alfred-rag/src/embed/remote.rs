@@ -0,0 +1,407 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{Embedder, IndexError};
+
+// Longest wait we accept from a `Retry-After` header.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteEmbedderConfig {
+    // The API root, e.g. `https://api.openai.com/v1` or
+    // `http://localhost:11434/v1`; `/embeddings` is appended.
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    // Asked of models that can shorten their output, and checked against
+    // every embedding returned either way.
+    pub dimensions: Option<usize>,
+    pub batch_size: usize,
+    // Retries after a 429, a 5xx or a connection failure.
+    pub max_retries: u32,
+    pub timeout_ms: u64,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for RemoteEmbedderConfig {
+    fn default() -> Self {
+        Self {
+            base_url: String::new(),
+            api_key: None,
+            model: String::new(),
+            dimensions: None,
+            batch_size: 64,
+            max_retries: 3,
+            timeout_ms: 60_000,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    #[serde(default)]
+    index: Option<usize>,
+}
+
+enum Failure {
+    Retry(anyhow::Error, Option<Duration>),
+    Fatal(anyhow::Error),
+}
+
+// Calls an OpenAI-compatible `POST /embeddings` endpoint: OpenAI,
+// OpenRouter, Ollama, text-embeddings-inference and the like.
+pub struct RemoteEmbedder {
+    client: Client,
+    config: RemoteEmbedderConfig,
+    // The configured dimension, or the first one a response had.
+    dimension: Mutex<Option<usize>>,
+    // Base delay before the first retry; doubles after each one.
+    backoff: Duration,
+}
+
+impl RemoteEmbedder {
+    pub fn new(config: RemoteEmbedderConfig) -> anyhow::Result<Self> {
+        anyhow::ensure!(!config.base_url.is_empty(), "The embedding provider has no base_url");
+        anyhow::ensure!(!config.model.is_empty(), "No embedding model configured");
+        let client = Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()?;
+        Ok(Self {
+            client,
+            dimension: Mutex::new(config.dimensions),
+            config,
+            backoff: Duration::from_millis(500),
+        })
+    }
+
+    // `text-embedding-3-small@api.openai.com`, for index headers.
+    pub fn model_id(&self) -> String {
+        let host = reqwest::Url::parse(&self.config.base_url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| match url.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            }))
+            .unwrap_or_else(|| self.config.base_url.clone());
+        match self.config.dimensions {
+            Some(dimensions) => format!("{}/{}@{}", self.config.model, dimensions, host),
+            None => format!("{}@{}", self.config.model, host),
+        }
+    }
+
+    // The configured dimension, or one learned by embedding a probe text.
+    pub async fn dimension(&self) -> anyhow::Result<usize> {
+        if let Some(dimension) = *self.dimension.lock().unwrap() {
+            return Ok(dimension);
+        }
+        Ok(self.embed("dimension probe").await?.len())
+    }
+
+    async fn request(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let url = format!("{}/embeddings", self.config.base_url.trim_end_matches('/'));
+        let mut body = json!({ "model": self.config.model, "input": texts });
+        if let Some(dimensions) = self.config.dimensions {
+            body["dimensions"] = json!(dimensions);
+        }
+
+        let mut attempt = 0;
+        loop {
+            let failure = match self.send(&url, &body, texts.len()).await {
+                Ok(embeddings) => return Ok(embeddings),
+                Err(failure) => failure,
+            };
+            match failure {
+                Failure::Retry(e, retry_after) if attempt < self.config.max_retries => {
+                    let delay = retry_after.unwrap_or(self.backoff * 2u32.pow(attempt)).min(MAX_RETRY_DELAY);
+                    tracing::debug!("Embedding request failed ({:#}); retrying in {:?}", e, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Failure::Retry(e, _) | Failure::Fatal(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn send(&self, url: &str, body: &serde_json::Value, count: usize) -> Result<Vec<Vec<f32>>, Failure> {
+        let mut request = self.client.post(url).json(body);
+        if let Some(key) = self.config.api_key.as_deref().filter(|key| !key.is_empty()) {
+            request = request.bearer_auth(key);
+        }
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value);
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                let error = anyhow::Error::new(e).context(format!("Failed to reach {}", url));
+                return Err(Failure::Retry(error, None));
+            }
+        };
+
+        let status = response.status();
+        if !status.is_success() {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            let text = response.text().await.unwrap_or_default();
+            let error = anyhow::anyhow!("Embedding request failed with {}: {}", status, text.trim());
+            return Err(match status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                true => Failure::Retry(error, retry_after),
+                false => Failure::Fatal(error),
+            });
+        }
+
+        let parsed: EmbeddingsResponse = response
+            .json()
+            .await
+            .context("Malformed embeddings response")
+            .map_err(Failure::Fatal)?;
+        self.collect(parsed, count).map_err(Failure::Fatal)
+    }
+
+    // Puts the embeddings back in input order and checks their dimension.
+    fn collect(&self, response: EmbeddingsResponse, count: usize) -> anyhow::Result<Vec<Vec<f32>>> {
+        anyhow::ensure!(
+            response.data.len() == count,
+            "Asked for {} embeddings, got {}",
+            count,
+            response.data.len()
+        );
+        let mut embeddings = vec![Vec::new(); count];
+        for (position, data) in response.data.into_iter().enumerate() {
+            let index = data.index.unwrap_or(position);
+            let slot = embeddings.get_mut(index).context("Embedding index out of range")?;
+            *slot = data.embedding;
+        }
+
+        let mut dimension = self.dimension.lock().unwrap();
+        for embedding in &embeddings {
+            if embedding.is_empty() {
+                return Err(IndexError::EmptyEmbedding.into());
+            }
+            let expected = *dimension.get_or_insert(embedding.len());
+            if embedding.len() != expected {
+                return Err(IndexError::DimensionMismatch {
+                    expected,
+                    actual: embedding.len(),
+                }
+                .into());
+            }
+        }
+        Ok(embeddings)
+    }
+}
+
+#[async_trait]
+impl Embedder for RemoteEmbedder {
+    async fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>> {
+        let mut embeddings = self.request(&[text.to_string()]).await?;
+        Ok(embeddings.remove(0))
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.config.batch_size.max(1)) {
+            embeddings.extend(self.request(batch).await?);
+        }
+        Ok(embeddings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[derive(Default)]
+    struct Server {
+        requests: AtomicUsize,
+        // Answer this many requests with `failure` first.
+        failures: usize,
+        failure: &'static str,
+        dimension: usize,
+        bodies: Mutex<Vec<serde_json::Value>>,
+    }
+
+    // A fake `/embeddings` endpoint. Each input embeds as
+    // `[len, 1, 1, ...]`, and `data` comes back in reverse order with
+    // indexes, as servers are allowed to do.
+    async fn serve(server: Arc<Server>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let server = server.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    let (head_end, length) = loop {
+                        let Ok(read) = stream.read(&mut buf).await else { return };
+                        if read == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..read]);
+                        let text = String::from_utf8_lossy(&request).to_string();
+                        let Some(head_end) = text.find("\r\n\r\n") else { continue };
+                        let length = text[..head_end]
+                            .lines()
+                            .find_map(|line| {
+                                let line = line.to_ascii_lowercase();
+                                line.strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap_or(0))
+                            })
+                            .unwrap_or(0);
+                        if request.len() >= head_end + 4 + length {
+                            break (head_end, length);
+                        }
+                    };
+                    let head = String::from_utf8_lossy(&request[..head_end]).to_string();
+                    let body: serde_json::Value =
+                        serde_json::from_slice(&request[head_end + 4..head_end + 4 + length]).unwrap_or_default();
+                    let count = server.requests.fetch_add(1, Ordering::SeqCst);
+
+                    let (status, payload) = if count < server.failures {
+                        (server.failure, r#"{"error":"try later"}"#.to_string())
+                    } else if !head.contains("authorization: Bearer secret") {
+                        ("401 Unauthorized", r#"{"error":"bad key"}"#.to_string())
+                    } else {
+                        let inputs: Vec<String> = serde_json::from_value(body["input"].clone()).unwrap_or_default();
+                        let data: Vec<serde_json::Value> = inputs
+                            .iter()
+                            .enumerate()
+                            .rev()
+                            .map(|(index, input)| {
+                                let mut embedding = vec![1.0f32; server.dimension];
+                                embedding[0] = input.len() as f32;
+                                json!({ "object": "embedding", "index": index, "embedding": embedding })
+                            })
+                            .collect();
+                        ("200 OK", json!({ "object": "list", "data": data }).to_string())
+                    };
+                    server.bodies.lock().unwrap().push(body);
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nRetry-After: 0\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        payload.len(),
+                        payload
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://127.0.0.1:{}/v1", port)
+    }
+
+    fn remote(base_url: String, config: RemoteEmbedderConfig) -> RemoteEmbedder {
+        let mut embedder = RemoteEmbedder::new(RemoteEmbedderConfig {
+            base_url,
+            api_key: Some("secret".to_string()),
+            model: "test-embed".to_string(),
+            ..config
+        })
+        .unwrap();
+        embedder.backoff = Duration::from_millis(1);
+        embedder
+    }
+
+    fn texts(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_batches_keep_input_order() {
+        let server = Arc::new(Server {
+            dimension: 3,
+            ..Server::default()
+        });
+        let url = serve(server.clone()).await;
+        let config = RemoteEmbedderConfig {
+            batch_size: 2,
+            ..RemoteEmbedderConfig::default()
+        };
+        let embedder = remote(url, config);
+        let embeddings = embedder.embed_batch(&texts(&["a", "bb", "ccc", "dddd", "eeeee"])).await.unwrap();
+        let firsts: Vec<f32> = embeddings.iter().map(|embedding| embedding[0]).collect();
+        assert_eq!(firsts, [1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(server.requests.load(Ordering::SeqCst), 3);
+        let first = server.bodies.lock().unwrap()[0].clone();
+        assert_eq!(first["model"], "test-embed");
+        assert_eq!(first["input"], json!(["a", "bb"]));
+        assert!(first.get("dimensions").is_none());
+        assert_eq!(embedder.dimension().await.unwrap(), 3);
+        assert!(embedder.model_id().starts_with("test-embed@127.0.0.1:"));
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let server = Arc::new(Server {
+            failures: 2,
+            failure: "503 Service Unavailable",
+            dimension: 2,
+            ..Server::default()
+        });
+        let embedder = remote(serve(server.clone()).await, RemoteEmbedderConfig::default());
+        assert_eq!(embedder.embed("hi").await.unwrap(), [2.0, 1.0]);
+        assert_eq!(server.requests.load(Ordering::SeqCst), 3);
+
+        let server = Arc::new(Server {
+            failures: 10,
+            failure: "429 Too Many Requests",
+            dimension: 2,
+            ..Server::default()
+        });
+        let config = RemoteEmbedderConfig {
+            max_retries: 1,
+            ..RemoteEmbedderConfig::default()
+        };
+        let embedder = remote(serve(server.clone()).await, config);
+        let error = embedder.embed("hi").await.unwrap_err().to_string();
+        assert!(error.contains("429"), "{}", error);
+        assert_eq!(server.requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_bad_keys_and_dimensions() {
+        let server = Arc::new(Server {
+            dimension: 3,
+            ..Server::default()
+        });
+        let url = serve(server.clone()).await;
+        let mut unauthorized = remote(url.clone(), RemoteEmbedderConfig::default());
+        unauthorized.config.api_key = None;
+        assert!(unauthorized.embed("hi").await.unwrap_err().to_string().contains("401"));
+        assert_eq!(server.requests.load(Ordering::SeqCst), 1);
+
+        let config = RemoteEmbedderConfig {
+            dimensions: Some(4),
+            ..RemoteEmbedderConfig::default()
+        };
+        let embedder = remote(url, config);
+        let error = embedder.embed("hi").await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<IndexError>(),
+            Some(IndexError::DimensionMismatch { expected: 4, actual: 3 })
+        ));
+        assert_eq!(server.bodies.lock().unwrap()[1]["dimensions"], 4);
+    }
+}
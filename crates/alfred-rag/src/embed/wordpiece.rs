@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+// Longer words become a single `[UNK]`, as in BERT.
+const MAX_WORD_CHARS: usize = 100;
+
+#[derive(Deserialize)]
+struct TokenizerJson {
+    model: TokenizerModel,
+    #[serde(default)]
+    normalizer: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct TokenizerModel {
+    vocab: HashMap<String, u32>,
+}
+
+// BERT's tokenizer: whitespace and punctuation splitting, optional
+// lowercasing, then greedy longest-match WordPiece. Accents are not
+// stripped, so accented words in an uncased vocab may split into more
+// pieces than the reference tokenizer produces.
+pub struct WordPiece {
+    vocab: HashMap<String, u32>,
+    lowercase: bool,
+    unknown: u32,
+    cls: u32,
+    sep: u32,
+}
+
+impl WordPiece {
+    pub fn new(vocab: HashMap<String, u32>, lowercase: bool) -> anyhow::Result<Self> {
+        let id = |token: &str| vocab.get(token).copied().with_context(|| format!("Vocabulary has no {}", token));
+        Ok(Self {
+            unknown: id("[UNK]")?,
+            cls: id("[CLS]")?,
+            sep: id("[SEP]")?,
+            vocab,
+            lowercase,
+        })
+    }
+
+    // Reads `tokenizer.json`, or `vocab.txt` with `do_lower_case` from
+    // `tokenizer_config.json`.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let json_path = dir.join("tokenizer.json");
+        if json_path.exists() {
+            let text = fs::read_to_string(&json_path)?;
+            let tokenizer: TokenizerJson =
+                serde_json::from_str(&text).with_context(|| format!("Failed to parse {}", json_path.display()))?;
+            let lowercase = tokenizer
+                .normalizer
+                .as_ref()
+                .and_then(|normalizer| normalizer.get("lowercase"))
+                .and_then(|lowercase| lowercase.as_bool())
+                .unwrap_or(true);
+            return Self::new(tokenizer.model.vocab, lowercase);
+        }
+
+        let vocab_path = dir.join("vocab.txt");
+        let text = fs::read_to_string(&vocab_path).with_context(|| format!("Failed to read {}", vocab_path.display()))?;
+        let vocab = text
+            .lines()
+            .enumerate()
+            .map(|(id, token)| (token.to_string(), id as u32))
+            .collect();
+        let lowercase = fs::read_to_string(dir.join("tokenizer_config.json"))
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .and_then(|config| config.get("do_lower_case").and_then(|value| value.as_bool()))
+            .unwrap_or(true);
+        Self::new(vocab, lowercase)
+    }
+
+    // `[CLS] pieces… [SEP]`, at most `max_tokens` long.
+    pub fn encode(&self, text: &str, max_tokens: usize) -> Vec<u32> {
+        let mut ids = vec![self.cls];
+        let limit = max_tokens.max(2) - 1;
+        'words: for word in self.words(text) {
+            for id in self.pieces(&word) {
+                if ids.len() == limit {
+                    break 'words;
+                }
+                ids.push(id);
+            }
+        }
+        ids.push(self.sep);
+        ids
+    }
+
+    // `[CLS] first [SEP] second [SEP]` with token types 0 for the first part
+    // and 1 for the second, as cross-encoders take a query and a passage.
+    // The longer text loses tokens until the pair fits in `max_tokens`.
+    pub fn encode_pair(&self, first: &str, second: &str, max_tokens: usize) -> (Vec<u32>, Vec<u32>) {
+        let pieces = |text: &str| -> Vec<u32> { self.words(text).iter().flat_map(|word| self.pieces(word)).collect() };
+        let (mut first, mut second) = (pieces(first), pieces(second));
+        while first.len() + second.len() + 3 > max_tokens.max(3) {
+            match first.len() > second.len() {
+                true => first.pop(),
+                false => second.pop(),
+            };
+        }
+        let mut ids = vec![self.cls];
+        ids.extend(&first);
+        ids.push(self.sep);
+        let mut types = vec![0; ids.len()];
+        ids.extend(&second);
+        ids.push(self.sep);
+        types.resize(ids.len(), 1);
+        (ids, types)
+    }
+
+    fn words(&self, text: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut current = String::new();
+        for c in text.chars() {
+            if c.is_whitespace() || c.is_control() {
+                words.extend((!current.is_empty()).then(|| std::mem::take(&mut current)));
+            } else if is_punctuation(c) || is_cjk(c) {
+                words.extend((!current.is_empty()).then(|| std::mem::take(&mut current)));
+                words.push(c.to_string());
+            } else if self.lowercase {
+                current.extend(c.to_lowercase());
+            } else {
+                current.push(c);
+            }
+        }
+        words.extend((!current.is_empty()).then_some(current));
+        words
+    }
+
+    fn pieces(&self, word: &str) -> Vec<u32> {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() > MAX_WORD_CHARS {
+            return vec![self.unknown];
+        }
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let mut end = chars.len();
+            let mut found = None;
+            while start < end {
+                let mut piece: String = chars[start..end].iter().collect();
+                if start > 0 {
+                    piece.insert_str(0, "##");
+                }
+                if let Some(id) = self.vocab.get(&piece) {
+                    found = Some(*id);
+                    break;
+                }
+                end -= 1;
+            }
+            let Some(id) = found else {
+                return vec![self.unknown];
+            };
+            pieces.push(id);
+            start = end;
+        }
+        pieces
+    }
+}
+
+// ASCII symbols count as punctuation, as in BERT, so `fn(x)` splits.
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || (!c.is_ascii() && !c.is_alphanumeric() && !c.is_whitespace())
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x4E00..=0x9FFF | 0x3400..=0x4DBF | 0x20000..=0x2A6DF | 0x2A700..=0x2B73F
+        | 0x2B740..=0x2B81F | 0x2B820..=0x2CEAF | 0xF900..=0xFAFF | 0x2F800..=0x2FA1F)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vocab(tokens: &[&str]) -> HashMap<String, u32> {
+        tokens.iter().enumerate().map(|(id, token)| (token.to_string(), id as u32)).collect()
+    }
+
+    #[test]
+    fn test_wordpiece_encoding() {
+        let tokens = ["[PAD]", "[UNK]", "[CLS]", "[SEP]", "hello", ",", "un", "##aff", "##able", "world", "!", "中"];
+        let tokenizer = WordPiece::new(vocab(&tokens), true).unwrap();
+        assert_eq!(
+            tokenizer.encode("Hello, UNAFFABLE world!", 64),
+            [2, 4, 5, 6, 7, 8, 9, 10, 3]
+        );
+        // Unknown words are one `[UNK]`, CJK characters are words of their own.
+        assert_eq!(tokenizer.encode("hello xyz 中中", 64), [2, 4, 1, 11, 11, 3]);
+        assert_eq!(tokenizer.encode("hello world hello", 4), [2, 4, 9, 3]);
+        assert_eq!(tokenizer.encode("", 8), [2, 3]);
+
+        let (ids, types) = tokenizer.encode_pair("hello world", "un world world world", 8);
+        assert_eq!(ids, [2, 4, 9, 3, 6, 9, 9, 3]);
+        assert_eq!(types, [0, 0, 0, 0, 1, 1, 1, 1]);
+    }
+}
@@ -0,0 +1,619 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::RwLock;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::vector::{check_dimension, dot, normalize, top_k};
+use crate::{Chunk, Index, QueryResult};
+
+const MAGIC: &[u8; 8] = b"ALFHNSW1";
+const MAX_LEVEL: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HnswConfig {
+    // Links per node on the upper layers; layer 0 gets twice as many.
+    pub m: usize,
+    // Candidate list size while inserting. Higher builds a better graph,
+    // slower.
+    pub ef_construction: usize,
+    // Candidate list size while querying, raised to `top_k` if smaller.
+    pub ef_search: usize,
+    // Seeds the level generator, so the same inserts build the same graph.
+    pub seed: u64,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
+            seed: 0x5eed,
+        }
+    }
+}
+
+struct Node {
+    chunk: Chunk,
+    vector: Vec<f32>,
+    // Neighbor ids per layer, `0..=level`.
+    links: Vec<Vec<u32>>,
+    // Removed or replaced. The node still routes searches but is never
+    // returned; `compact` drops it.
+    deleted: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Scored {
+    similarity: f32,
+    id: u32,
+}
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.similarity
+            .total_cmp(&other.similarity)
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+struct Graph {
+    config: HnswConfig,
+    dimension: Option<usize>,
+    nodes: Vec<Node>,
+    // Live chunk id -> node.
+    ids: HashMap<String, u32>,
+    entry: Option<u32>,
+    rng: u64,
+}
+
+impl Graph {
+    fn new(config: HnswConfig) -> Self {
+        Self {
+            config,
+            dimension: None,
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            entry: None,
+            rng: config.seed,
+        }
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.config.m * 2
+        } else {
+            self.config.m
+        }
+    }
+
+    fn similarity(&self, query: &[f32], id: u32) -> f32 {
+        dot(query, &self.nodes[id as usize].vector)
+    }
+
+    fn random_level(&mut self) -> usize {
+        // splitmix64
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        let uniform = ((z >> 11) as f64 + 1.0) / (1u64 << 53) as f64;
+        let scale = 1.0 / (self.config.m.max(2) as f64).ln();
+        ((-uniform.ln() * scale) as usize).min(MAX_LEVEL)
+    }
+
+    fn level(&self, id: u32) -> usize {
+        self.nodes[id as usize].links.len() - 1
+    }
+
+    // The `ef` nodes most similar to `query` reachable on `layer`, best first.
+    fn search_layer(&self, query: &[f32], entries: &[Scored], ef: usize, layer: usize) -> Vec<Scored> {
+        let mut visited: HashSet<u32> = entries.iter().map(|s| s.id).collect();
+        let mut candidates: BinaryHeap<Scored> = entries.iter().copied().collect();
+        let mut found: BinaryHeap<std::cmp::Reverse<Scored>> = entries.iter().map(|s| std::cmp::Reverse(*s)).collect();
+        while let Some(candidate) = candidates.pop() {
+            let worst = found.peek().map(|s| s.0.similarity).unwrap_or(f32::MIN);
+            if found.len() >= ef && candidate.similarity < worst {
+                break;
+            }
+            for &neighbor in &self.nodes[candidate.id as usize].links[layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let scored = Scored {
+                    similarity: self.similarity(query, neighbor),
+                    id: neighbor,
+                };
+                let worst = found.peek().map(|s| s.0.similarity).unwrap_or(f32::MIN);
+                if found.len() < ef || scored.similarity > worst {
+                    candidates.push(scored);
+                    found.push(std::cmp::Reverse(scored));
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        let mut found: Vec<Scored> = found.into_iter().map(|s| s.0).collect();
+        found.sort_by(|a, b| b.cmp(a));
+        found
+    }
+
+    // The neighbor selection heuristic from the HNSW paper: prefer
+    // candidates closer to the base than to any already picked, which keeps
+    // links spread across clusters, then top up with the rest.
+    fn select_neighbors(&self, candidates: &[Scored], count: usize) -> Vec<u32> {
+        let mut picked: Vec<Scored> = Vec::with_capacity(count);
+        let mut skipped = Vec::new();
+        for &candidate in candidates {
+            if picked.len() >= count {
+                break;
+            }
+            let vector = &self.nodes[candidate.id as usize].vector;
+            let diverse = picked
+                .iter()
+                .all(|p| self.similarity(vector, p.id) < candidate.similarity);
+            if diverse {
+                picked.push(candidate);
+            } else {
+                skipped.push(candidate);
+            }
+        }
+        for candidate in skipped {
+            if picked.len() >= count {
+                break;
+            }
+            picked.push(candidate);
+        }
+        picked.into_iter().map(|s| s.id).collect()
+    }
+
+    fn descend(&self, query: &[f32], entry: u32, from: usize, to: usize) -> Scored {
+        let mut best = Scored {
+            similarity: self.similarity(query, entry),
+            id: entry,
+        };
+        for layer in (to..=from).rev() {
+            best = self.search_layer(query, &[best], 1, layer)[0];
+        }
+        best
+    }
+
+    fn insert(&mut self, chunk: Chunk, vector: Vec<f32>) {
+        if let Some(old) = self.ids.remove(&chunk.id) {
+            self.nodes[old as usize].deleted = true;
+        }
+        let level = self.random_level();
+        let id = self.nodes.len() as u32;
+        self.ids.insert(chunk.id.clone(), id);
+        self.nodes.push(Node {
+            chunk,
+            vector,
+            links: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        let Some(entry) = self.entry else {
+            self.entry = Some(id);
+            return;
+        };
+
+        let query = self.nodes[id as usize].vector.clone();
+        let top = self.level(entry);
+        let mut entries = vec![if top > level {
+            self.descend(&query, entry, top, level + 1)
+        } else {
+            Scored {
+                similarity: self.similarity(&query, entry),
+                id: entry,
+            }
+        }];
+        for layer in (0..=level.min(top)).rev() {
+            let candidates = self.search_layer(&query, &entries, self.config.ef_construction, layer);
+            let neighbors = self.select_neighbors(&candidates, self.config.m);
+            for &neighbor in &neighbors {
+                self.link(neighbor, id, layer);
+            }
+            self.nodes[id as usize].links[layer] = neighbors;
+            entries = candidates;
+        }
+        if level > top {
+            self.entry = Some(id);
+        }
+    }
+
+    // Adds `to` to `from`'s links on `layer`, re-selecting when full.
+    fn link(&mut self, from: u32, to: u32, layer: usize) {
+        let max = self.max_links(layer);
+        let links = &mut self.nodes[from as usize].links[layer];
+        links.push(to);
+        if links.len() <= max {
+            return;
+        }
+        let base = self.nodes[from as usize].vector.clone();
+        let mut candidates: Vec<Scored> = self.nodes[from as usize].links[layer]
+            .iter()
+            .map(|&id| Scored {
+                similarity: self.similarity(&base, id),
+                id,
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.cmp(a));
+        self.nodes[from as usize].links[layer] = self.select_neighbors(&candidates, max);
+    }
+
+    fn search(&self, query: &[f32], k: usize) -> Vec<QueryResult> {
+        let Some(entry) = self.entry else {
+            return Vec::new();
+        };
+        let start = self.descend(query, entry, self.level(entry), 1);
+        // Tombstones take up room in the candidate list, so widen it until
+        // it holds `k` live nodes or covers the whole graph.
+        let deleted = self.nodes.len() - self.ids.len();
+        let wanted = k.min(self.ids.len());
+        let mut ef = self.config.ef_search.max(k) + deleted.min(k * 4);
+        loop {
+            let found = self.search_layer(query, &[start], ef, 0);
+            let live: Vec<QueryResult> = found
+                .into_iter()
+                .filter(|s| !self.nodes[s.id as usize].deleted)
+                .map(|s| QueryResult::new(self.nodes[s.id as usize].chunk.id.clone(), s.similarity))
+                .collect();
+            if live.len() >= wanted || ef >= self.nodes.len() {
+                return top_k(live, k);
+            }
+            ef *= 2;
+        }
+    }
+}
+
+// Approximate nearest neighbor search with a hierarchical navigable small
+// world graph (Malkov & Yashunin). Removals leave tombstones that keep the
+// graph connected; `compact` rebuilds it from the live chunks.
+pub struct HnswIndex {
+    graph: RwLock<Graph>,
+}
+
+impl Default for HnswIndex {
+    fn default() -> Self {
+        Self::new(HnswConfig::default())
+    }
+}
+
+impl HnswIndex {
+    pub fn new(config: HnswConfig) -> Self {
+        Self {
+            graph: RwLock::new(Graph::new(config)),
+        }
+    }
+
+    pub fn config(&self) -> HnswConfig {
+        self.graph.read().unwrap().config
+    }
+
+    // Only affects later queries.
+    pub fn set_ef_search(&self, ef_search: usize) {
+        self.graph.write().unwrap().config.ef_search = ef_search;
+    }
+
+    pub fn tombstones(&self) -> usize {
+        let graph = self.graph.read().unwrap();
+        graph.nodes.len() - graph.ids.len()
+    }
+
+    pub fn compact(&self) {
+        let mut graph = self.graph.write().unwrap();
+        let mut fresh = Graph::new(graph.config);
+        fresh.dimension = graph.dimension;
+        fresh.rng = graph.rng;
+        for node in std::mem::take(&mut graph.nodes) {
+            if !node.deleted {
+                fresh.insert(node.chunk, node.vector);
+            }
+        }
+        *graph = fresh;
+    }
+
+    // Writes the whole graph to `path`, replacing it atomically.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let graph = self.graph.read().unwrap();
+        let tmp = path.with_extension("tmp");
+        {
+            let mut out = BufWriter::new(File::create(&tmp).with_context(|| format!("Failed to create {}", tmp.display()))?);
+            let meta = Meta {
+                config: graph.config,
+                dimension: graph.dimension.unwrap_or(0),
+                entry: graph.entry,
+                rng: graph.rng,
+                chunks: graph.nodes.iter().map(|node| &node.chunk).collect(),
+            };
+            let meta = serde_json::to_vec(&meta)?;
+            out.write_all(MAGIC)?;
+            out.write_all(&(meta.len() as u64).to_le_bytes())?;
+            out.write_all(&meta)?;
+            for node in &graph.nodes {
+                out.write_all(&[node.deleted as u8, node.links.len() as u8])?;
+                for value in &node.vector {
+                    out.write_all(&value.to_le_bytes())?;
+                }
+                for links in &node.links {
+                    out.write_all(&(links.len() as u32).to_le_bytes())?;
+                    for link in links {
+                        out.write_all(&link.to_le_bytes())?;
+                    }
+                }
+            }
+            out.into_inner()?.sync_all()?;
+        }
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut input = BufReader::new(File::open(path).with_context(|| format!("Failed to open {}", path.display()))?);
+        let mut magic = [0u8; 8];
+        input.read_exact(&mut magic)?;
+        anyhow::ensure!(&magic == MAGIC, "{} is not an HNSW index", path.display());
+        let mut meta = vec![0u8; read_u64(&mut input)? as usize];
+        input.read_exact(&mut meta)?;
+        let meta: OwnedMeta = serde_json::from_slice(&meta)?;
+
+        let mut graph = Graph::new(meta.config);
+        graph.dimension = (meta.dimension > 0).then_some(meta.dimension);
+        graph.entry = meta.entry;
+        graph.rng = meta.rng;
+        let count = meta.chunks.len() as u32;
+        for chunk in meta.chunks {
+            let mut flags = [0u8; 2];
+            input.read_exact(&mut flags)?;
+            let mut vector = vec![0f32; meta.dimension];
+            for value in &mut vector {
+                let mut bytes = [0u8; 4];
+                input.read_exact(&mut bytes)?;
+                *value = f32::from_le_bytes(bytes);
+            }
+            let mut links = Vec::with_capacity(flags[1] as usize);
+            for _ in 0..flags[1] {
+                let len = read_u32(&mut input)?;
+                let layer = (0..len).map(|_| read_u32(&mut input)).collect::<std::io::Result<Vec<u32>>>()?;
+                anyhow::ensure!(layer.iter().all(|&id| id < count), "corrupt link in {}", path.display());
+                links.push(layer);
+            }
+            anyhow::ensure!(!links.is_empty(), "corrupt node in {}", path.display());
+            let deleted = flags[0] != 0;
+            if !deleted {
+                graph.ids.insert(chunk.id.clone(), graph.nodes.len() as u32);
+            }
+            graph.nodes.push(Node {
+                chunk,
+                vector,
+                links,
+                deleted,
+            });
+        }
+        // A search follows a layer's links into the linked node's links on
+        // that layer and starts at `entry`, so both must exist.
+        let levels: Vec<usize> = graph.nodes.iter().map(|node| node.links.len()).collect();
+        let links_fit = graph.nodes.iter().all(|node| {
+            node.links
+                .iter()
+                .enumerate()
+                .all(|(layer, links)| links.iter().all(|&id| levels[id as usize] > layer))
+        });
+        anyhow::ensure!(links_fit, "corrupt link in {}", path.display());
+        anyhow::ensure!(
+            graph.entry.is_none_or(|entry| entry < count) && graph.entry.is_some() == (count > 0),
+            "corrupt entry point in {}",
+            path.display()
+        );
+        Ok(Self {
+            graph: RwLock::new(graph),
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Meta<'a> {
+    config: HnswConfig,
+    dimension: usize,
+    entry: Option<u32>,
+    rng: u64,
+    chunks: Vec<&'a Chunk>,
+}
+
+#[derive(Deserialize)]
+struct OwnedMeta {
+    config: HnswConfig,
+    dimension: usize,
+    entry: Option<u32>,
+    rng: u64,
+    chunks: Vec<Chunk>,
+}
+
+fn read_u32(input: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> std::io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[async_trait]
+impl Index for HnswIndex {
+    async fn add(&self, chunk: Chunk, embedding: Vec<f32>) -> anyhow::Result<()> {
+        let embedding = normalize(embedding)?;
+        let mut graph = self.graph.write().unwrap();
+        if let Some(dimension) = graph.dimension {
+            check_dimension(dimension, &embedding)?;
+        }
+        graph.dimension = Some(embedding.len());
+        graph.insert(chunk, embedding);
+        Ok(())
+    }
+
+    async fn query(&self, embedding: Vec<f32>, k: usize) -> anyhow::Result<Vec<QueryResult>> {
+        let embedding = normalize(embedding)?;
+        let graph = self.graph.read().unwrap();
+        let Some(dimension) = graph.dimension else {
+            return Ok(Vec::new());
+        };
+        check_dimension(dimension, &embedding)?;
+        Ok(graph.search(&embedding, k))
+    }
+
+    async fn remove(&self, chunk_id: &str) -> anyhow::Result<bool> {
+        let mut graph = self.graph.write().unwrap();
+        let Some(id) = graph.ids.remove(chunk_id) else {
+            return Ok(false);
+        };
+        graph.nodes[id as usize].deleted = true;
+        Ok(true)
+    }
+
+    async fn get(&self, chunk_id: &str) -> anyhow::Result<Option<Chunk>> {
+        let graph = self.graph.read().unwrap();
+        Ok(graph.ids.get(chunk_id).map(|&id| graph.nodes[id as usize].chunk.clone()))
+    }
+
+    async fn len(&self) -> anyhow::Result<usize> {
+        Ok(self.graph.read().unwrap().ids.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InMemoryIndex;
+
+    fn chunk(id: usize) -> Chunk {
+        Chunk {
+            id: format!("c{}", id),
+            text: String::new(),
+            metadata: serde_json::Value::Null,
+        }
+    }
+
+    // Clustered points, closer to real embeddings than uniform noise.
+    fn vectors(count: usize, dimension: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut state = seed;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 10_000) as f32 / 5_000.0 - 1.0
+        };
+        let centers: Vec<Vec<f32>> = (0..20).map(|_| (0..dimension).map(|_| next()).collect()).collect();
+        (0..count)
+            .map(|i| centers[i % centers.len()].iter().map(|c| c + next() * 0.4).collect())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_recall_against_exact_search() {
+        let config = HnswConfig {
+            m: 12,
+            ef_construction: 100,
+            ef_search: 80,
+            ..HnswConfig::default()
+        };
+        let hnsw = HnswIndex::new(config);
+        let exact = InMemoryIndex::new();
+        for (i, vector) in vectors(2000, 24, 7).into_iter().enumerate() {
+            hnsw.add(chunk(i), vector.clone()).await.unwrap();
+            exact.add(chunk(i), vector).await.unwrap();
+        }
+
+        let k = 10;
+        let queries = vectors(50, 24, 99);
+        let mut hits = 0;
+        for query in &queries {
+            let truth: HashSet<String> = exact.query(query.clone(), k).await.unwrap().into_iter().map(|r| r.chunk_id).collect();
+            let found = hnsw.query(query.clone(), k).await.unwrap();
+            assert_eq!(found.len(), k);
+            hits += found.iter().filter(|r| truth.contains(&r.chunk_id)).count();
+        }
+        let recall = hits as f64 / (queries.len() * k) as f64;
+        assert!(recall >= 0.95, "recall@{} = {:.3}", k, recall);
+    }
+
+    #[tokio::test]
+    async fn test_tombstones_compaction_and_persistence() {
+        let index = HnswIndex::new(HnswConfig {
+            m: 8,
+            ef_construction: 64,
+            ..HnswConfig::default()
+        });
+        let data = vectors(300, 8, 3);
+        for (i, vector) in data.iter().enumerate() {
+            index.add(chunk(i), vector.clone()).await.unwrap();
+        }
+        assert!(index.add(chunk(0), vec![1.0; 3]).await.is_err());
+
+        for i in 0..100 {
+            assert!(index.remove(&format!("c{}", i)).await.unwrap());
+        }
+        assert_eq!(index.len().await.unwrap(), 200);
+        assert_eq!(index.tombstones(), 100);
+        let results = index.query(data[5].clone(), 5).await.unwrap();
+        assert_eq!(results.len(), 5);
+        assert!(results.iter().all(|r| r.chunk_id[1..].parse::<usize>().unwrap() >= 100));
+        assert_eq!(index.query(data[150].clone(), 1).await.unwrap()[0].chunk_id, "c150");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.hnsw");
+        index.save(&path).unwrap();
+        let loaded = HnswIndex::load(&path).unwrap();
+        assert_eq!(loaded.len().await.unwrap(), 200);
+        assert_eq!(loaded.tombstones(), 100);
+        assert_eq!(
+            loaded.query(data[150].clone(), 5).await.unwrap()[0].chunk_id,
+            index.query(data[150].clone(), 5).await.unwrap()[0].chunk_id
+        );
+        assert!(loaded.get("c3").await.unwrap().is_none());
+
+        loaded.compact();
+        assert_eq!(loaded.tombstones(), 0);
+        assert_eq!(loaded.len().await.unwrap(), 200);
+        assert_eq!(loaded.query(data[150].clone(), 1).await.unwrap()[0].chunk_id, "c150");
+
+        // Mostly tombstones: queries still find every live node they ask for.
+        for i in 100..290 {
+            index.remove(&format!("c{}", i)).await.unwrap();
+        }
+        assert_eq!(index.query(data[5].clone(), 10).await.unwrap().len(), 10);
+        assert_eq!(index.query(data[5].clone(), 50).await.unwrap().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_load_rejects_a_bad_entry_point() {
+        let index = HnswIndex::default();
+        for (i, vector) in vectors(5, 4, 1).into_iter().enumerate() {
+            index.add(chunk(i), vector).await.unwrap();
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.hnsw");
+        index.graph.write().unwrap().entry = Some(9);
+        index.save(&path).unwrap();
+        let error = HnswIndex::load(&path).err().unwrap();
+        assert!(error.to_string().contains("corrupt entry point"));
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::lexical::Bm25Index;
+use crate::vector::top_k;
+use crate::{Embedder, Index, QueryResult, SourceScore};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HybridConfig {
+    // Multiplies each source's reciprocal rank; 0 turns a source off.
+    pub lexical_weight: f32,
+    pub vector_weight: f32,
+    // The RRF damping constant; larger values flatten the rank curve.
+    pub rrf_k: f32,
+    // How many results to take from each source before fusing.
+    pub candidates: usize,
+}
+
+impl Default for HybridConfig {
+    fn default() -> Self {
+        Self {
+            lexical_weight: 1.0,
+            vector_weight: 1.0,
+            rrf_k: 60.0,
+            candidates: 50,
+        }
+    }
+}
+
+// One retriever's results, best first.
+pub struct RankedList {
+    pub source: String,
+    pub weight: f32,
+    pub results: Vec<QueryResult>,
+}
+
+// Reciprocal rank fusion: a chunk scores `Σ weight / (k + rank)` over the
+// lists it appears in. Each fused result lists its per-source ranks and
+// original scores in `sources`.
+pub fn reciprocal_rank_fusion(lists: &[RankedList], k: f32, limit: usize) -> Vec<QueryResult> {
+    let mut fused: HashMap<&str, QueryResult> = HashMap::new();
+    for list in lists.iter().filter(|list| list.weight > 0.0) {
+        for (index, result) in list.results.iter().enumerate() {
+            let rank = index + 1;
+            let entry = fused
+                .entry(&result.chunk_id)
+                .or_insert_with(|| QueryResult::new(result.chunk_id.clone(), 0.0));
+            entry.score += list.weight / (k + rank as f32);
+            entry.sources.push(SourceScore {
+                source: list.source.clone(),
+                rank,
+                score: result.score,
+            });
+        }
+    }
+    top_k(fused.into_values().collect(), limit)
+}
+
+// Runs a query through BM25 and the vector index and fuses the rankings.
+pub struct HybridRetriever {
+    pub lexical: Arc<Bm25Index>,
+    pub vectors: Arc<dyn Index>,
+    pub embedder: Arc<dyn Embedder>,
+    pub config: HybridConfig,
+}
+
+impl HybridRetriever {
+    pub async fn search(&self, query: &str, limit: usize) -> anyhow::Result<Vec<QueryResult>> {
+        let lexical = match self.config.lexical_weight > 0.0 {
+            true => self.lexical.query(query, self.config.candidates),
+            false => Vec::new(),
+        };
+        let vector = match self.config.vector_weight > 0.0 {
+            true => {
+                let embedding = self.embedder.embed(query).await?;
+                self.vectors.query(embedding, self.config.candidates).await?
+            }
+            false => Vec::new(),
+        };
+        let lists = [
+            RankedList {
+                source: "lexical".to_string(),
+                weight: self.config.lexical_weight,
+                results: lexical,
+            },
+            RankedList {
+                source: "vector".to_string(),
+                weight: self.config.vector_weight,
+                results: vector,
+            },
+        ];
+        Ok(reciprocal_rank_fusion(&lists, self.config.rrf_k, limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chunk, InMemoryIndex};
+    use async_trait::async_trait;
+
+    fn list(source: &str, weight: f32, ids: &[&str]) -> RankedList {
+        RankedList {
+            source: source.to_string(),
+            weight,
+            results: ids.iter().map(|id| QueryResult::new(*id, 1.0)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_rrf_combines_and_weights() {
+        let lists = [list("lexical", 1.0, &["a", "b", "c"]), list("vector", 1.0, &["c", "d", "a"])];
+        let fused = reciprocal_rank_fusion(&lists, 60.0, 10);
+        let ids: Vec<&str> = fused.iter().map(|r| r.chunk_id.as_str()).collect();
+        // `a` is 1st and 3rd, `c` 3rd and 1st: a tie broken by id.
+        assert_eq!(ids, ["a", "c", "b", "d"]);
+        assert!((fused[0].score - (1.0 / 61.0 + 1.0 / 63.0)).abs() < 1e-6);
+        assert_eq!(fused[0].sources.len(), 2);
+        assert_eq!(fused[0].sources[1].rank, 3);
+
+        let lists = [list("lexical", 0.2, &["a", "b"]), list("vector", 1.0, &["b", "a"])];
+        assert_eq!(reciprocal_rank_fusion(&lists, 60.0, 1)[0].chunk_id, "b");
+        let lists = [list("lexical", 0.0, &["a"]), list("vector", 1.0, &["b"])];
+        assert_eq!(reciprocal_rank_fusion(&lists, 60.0, 5).len(), 1);
+    }
+
+    // Embeds a text as counts of a few keywords.
+    struct Keywords;
+
+    #[async_trait]
+    impl Embedder for Keywords {
+        async fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>> {
+            let text = text.to_lowercase();
+            Ok(["network", "shell", "config"]
+                .iter()
+                .map(|word| text.matches(word).count() as f32 + 0.01)
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search() {
+        let lexical = Arc::new(Bm25Index::default());
+        let vectors = Arc::new(InMemoryIndex::new());
+        let chunks = [
+            ("allowlist", "fn is_allowed(host) checks the network allowlist"),
+            ("sandbox", "shell commands run in a sandbox without network"),
+            ("config", "load the config file"),
+        ];
+        for (id, text) in chunks {
+            let chunk = Chunk {
+                id: id.to_string(),
+                text: text.to_string(),
+                metadata: serde_json::Value::Null,
+            };
+            lexical.add(&chunk);
+            vectors.add(chunk, Keywords.embed(text).await.unwrap()).await.unwrap();
+        }
+        let retriever = HybridRetriever {
+            lexical,
+            vectors,
+            embedder: Arc::new(Keywords),
+            config: HybridConfig::default(),
+        };
+        let results = retriever.search("is_allowed network", 3).await.unwrap();
+        assert_eq!(results[0].chunk_id, "allowlist");
+        let sources: Vec<&str> = results[0].sources.iter().map(|s| s.source.as_str()).collect();
+        assert_eq!(sources, ["lexical", "vector"]);
+    }
+}
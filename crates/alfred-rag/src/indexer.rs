@@ -0,0 +1,684 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use anyhow::Context;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::chunk::{chunk_file, ChunkMetadata, ChunkOptions};
+use crate::{fnv1a, Bm25Index, Chunk, Embedder, HybridConfig, HybridRetriever, Index};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+// Directories that are never indexed, hidden-file settings aside.
+const SKIP_DIRS: &[&str] = &[".git", ".alfred"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexerConfig {
+    // Globs relative to the root, e.g. `src/**` or `**/*.md`. An empty
+    // include list takes every file the ignore rules allow.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub max_file_bytes: u64,
+    pub chunk: ChunkOptions,
+    // Chunks per `Embedder::embed_batch` call.
+    pub batch_size: usize,
+}
+
+impl Default for IndexerConfig {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_file_bytes: 1024 * 1024,
+            chunk: ChunkOptions::default(),
+            batch_size: 32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexStats {
+    // Files the walk matched.
+    pub files: usize,
+    pub indexed: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+    pub skipped: usize,
+    pub chunks_added: usize,
+    pub chunks_removed: usize,
+    pub duration_ms: u64,
+}
+
+impl IndexStats {
+    pub fn changed(&self) -> bool {
+        self.indexed + self.removed > 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IndexEvent {
+    Scanned { files: usize },
+    // `done` of `total` files have been looked at.
+    Indexed { path: String, chunks: usize, done: usize, total: usize },
+    Removed { path: String, chunks: usize },
+    Skipped { path: String, reason: String },
+    Finished(IndexStats),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    // The `model` the indexer was created with. The files listed are only
+    // in the index if it still matches.
+    #[serde(default)]
+    model: String,
+    // Path relative to the root, `/`-separated.
+    files: BTreeMap<String, FileRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FileRecord {
+    hash: u64,
+    size: u64,
+    modified_ns: Option<u64>,
+    // Empty for files that were skipped, so they aren't retried until they
+    // change.
+    chunks: Vec<String>,
+}
+
+// A changed file whose chunks are waiting to be embedded.
+struct Pending {
+    path: String,
+    done: usize,
+    record: FileRecord,
+    old_chunks: Vec<String>,
+    chunks: Vec<Chunk>,
+}
+
+struct Candidate {
+    path: String,
+    size: u64,
+    modified_ns: Option<u64>,
+}
+
+struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    fn new(config: &IndexerConfig) -> anyhow::Result<Self> {
+        let include = match config.include.is_empty() {
+            true => None,
+            false => Some(glob_set(&config.include)?),
+        };
+        Ok(Self {
+            include,
+            exclude: glob_set(&config.exclude)?,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        self.include.as_ref().is_none_or(|include| include.is_match(path)) && !self.exclude.is_match(path)
+    }
+}
+
+fn glob_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).with_context(|| format!("Invalid index glob `{}`", pattern))?);
+    }
+    Ok(builder.build()?)
+}
+
+// Keeps a `Index` and a `Bm25Index` in step with the files under `root`.
+// A manifest in `state_dir` records each file's size, mtime, content hash
+// and chunk ids: files whose size and mtime are unchanged aren't read, files
+// whose hash is unchanged aren't re-chunked, and only changed files are
+// re-embedded. A file counts as changed while any of its recorded chunks is
+// missing from the index, e.g. after a crash lost writes the manifest had
+// recorded. Chunks of deleted files are removed. `model` identifies the
+// embeddings, like the index header does; a manifest written for another
+// model is discarded so everything is embedded again.
+pub struct RepoIndexer {
+    root: PathBuf,
+    manifest_path: PathBuf,
+    config: IndexerConfig,
+    filter: Arc<PathFilter>,
+    index: Arc<dyn Index>,
+    lexical: Arc<Bm25Index>,
+    embedder: Arc<dyn Embedder>,
+    // Also serializes runs.
+    manifest: Mutex<Manifest>,
+}
+
+impl RepoIndexer {
+    pub fn new(
+        root: &Path,
+        state_dir: &Path,
+        index: Arc<dyn Index>,
+        embedder: Arc<dyn Embedder>,
+        model: &str,
+        config: IndexerConfig,
+    ) -> anyhow::Result<Self> {
+        let manifest_path = state_dir.join(MANIFEST_FILE);
+        let fresh = || Manifest {
+            version: MANIFEST_VERSION,
+            model: model.to_string(),
+            files: BTreeMap::new(),
+        };
+        let manifest = match fs::read_to_string(&manifest_path) {
+            Ok(text) => match serde_json::from_str::<Manifest>(&text) {
+                Ok(manifest) if manifest.version == MANIFEST_VERSION && manifest.model == model => manifest,
+                Ok(manifest) if manifest.version == MANIFEST_VERSION => {
+                    tracing::info!("Embedding model changed from `{}`; reindexing everything", manifest.model);
+                    fresh()
+                }
+                _ => {
+                    tracing::warn!("Discarding unreadable index manifest {}", manifest_path.display());
+                    fresh()
+                }
+            },
+            Err(_) => fresh(),
+        };
+        Ok(Self {
+            root: root.to_path_buf(),
+            manifest_path,
+            filter: Arc::new(PathFilter::new(&config)?),
+            config,
+            index,
+            lexical: Arc::new(Bm25Index::default()),
+            embedder,
+            manifest: Mutex::new(manifest),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn index(&self) -> Arc<dyn Index> {
+        self.index.clone()
+    }
+
+    // Filled by `run`, including chunks of files that didn't change.
+    pub fn lexical(&self) -> Arc<Bm25Index> {
+        self.lexical.clone()
+    }
+
+    pub fn retriever(&self, config: HybridConfig) -> HybridRetriever {
+        HybridRetriever {
+            lexical: self.lexical.clone(),
+            vectors: self.index.clone(),
+            embedder: self.embedder.clone(),
+            config,
+        }
+    }
+
+    // One incremental pass over the workspace. The manifest is saved even if
+    // the pass fails part way, so finished files aren't redone.
+    pub async fn run(&self, progress: Option<&mpsc::Sender<IndexEvent>>) -> anyhow::Result<IndexStats> {
+        let started = Instant::now();
+        let mut manifest = self.manifest.lock().await;
+
+        let root = self.root.clone();
+        let filter = self.filter.clone();
+        let files = tokio::task::spawn_blocking(move || scan(&root, &filter)).await??;
+        emit(progress, IndexEvent::Scanned { files: files.len() }).await;
+
+        let mut stats = IndexStats {
+            files: files.len(),
+            ..IndexStats::default()
+        };
+        let result = self.update(&mut manifest, &files, &mut stats, progress).await;
+        self.save(&manifest).await?;
+        result?;
+
+        stats.duration_ms = started.elapsed().as_millis() as u64;
+        emit(progress, IndexEvent::Finished(stats.clone())).await;
+        Ok(stats)
+    }
+
+    // Re-runs `run` every `interval` until the task is dropped. Each pass
+    // only stats files, so an idle workspace costs little.
+    pub async fn watch(&self, interval: Duration, progress: Option<&mpsc::Sender<IndexEvent>>) {
+        loop {
+            if let Err(e) = self.run(progress).await {
+                tracing::warn!("Indexing {} failed: {:#}", self.root.display(), e);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    async fn update(
+        &self,
+        manifest: &mut Manifest,
+        files: &[Candidate],
+        stats: &mut IndexStats,
+        progress: Option<&mpsc::Sender<IndexEvent>>,
+    ) -> anyhow::Result<()> {
+        let total = files.len();
+        // Changed files wait here until there are enough chunks for a batch.
+        let mut pending = Vec::new();
+        let mut pending_chunks = 0;
+        for (position, file) in files.iter().enumerate() {
+            let previous = manifest.files.get(&file.path).cloned();
+            if let Some(record) = &previous {
+                if file.modified_ns.is_some()
+                    && record.size == file.size
+                    && record.modified_ns == file.modified_ns
+                    && self.load_lexical(&record.chunks).await?
+                {
+                    stats.unchanged += 1;
+                    continue;
+                }
+            }
+
+            let old_chunks = previous.as_ref().map(|record| record.chunks.clone()).unwrap_or_default();
+            let mut record = FileRecord {
+                hash: 0,
+                size: file.size,
+                modified_ns: file.modified_ns,
+                chunks: Vec::new(),
+            };
+            let text = match self.read(file).await {
+                Ok(bytes) => {
+                    record.hash = fnv1a(&bytes);
+                    if let Some(previous) = previous.as_ref().filter(|previous| previous.hash == record.hash) {
+                        if self.load_lexical(&previous.chunks).await? {
+                            record.chunks = previous.chunks.clone();
+                            manifest.files.insert(file.path.clone(), record);
+                            stats.unchanged += 1;
+                            continue;
+                        }
+                    }
+                    decode(bytes)
+                }
+                Err(reason) => Err(reason),
+            };
+            let text = match text {
+                Ok(text) => text,
+                Err(reason) => {
+                    stats.chunks_removed += self.remove_chunks(&old_chunks).await?;
+                    manifest.files.insert(file.path.clone(), record);
+                    stats.skipped += 1;
+                    emit(progress, IndexEvent::Skipped { path: file.path.clone(), reason }).await;
+                    continue;
+                }
+            };
+
+            let chunks = chunk_file(&file.path, &text, &self.config.chunk);
+            pending_chunks += chunks.len();
+            pending.push(Pending {
+                path: file.path.clone(),
+                done: position + 1,
+                record,
+                old_chunks,
+                chunks,
+            });
+            if pending_chunks >= self.config.batch_size {
+                self.flush(std::mem::take(&mut pending), manifest, stats, progress, total).await?;
+                pending_chunks = 0;
+            }
+        }
+        self.flush(pending, manifest, stats, progress, total).await?;
+
+        let present: HashSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
+        let deleted: Vec<String> = manifest
+            .files
+            .keys()
+            .filter(|path| !present.contains(path.as_str()))
+            .cloned()
+            .collect();
+        for path in deleted {
+            let Some(record) = manifest.files.remove(&path) else {
+                continue;
+            };
+            let chunks = self.remove_chunks(&record.chunks).await?;
+            stats.chunks_removed += chunks;
+            stats.removed += 1;
+            emit(progress, IndexEvent::Removed { path, chunks }).await;
+        }
+        Ok(())
+    }
+
+    // Embeds the chunks of `pending` in `batch_size` batches, then swaps
+    // each file's old chunks for the new ones. Nothing is written until the
+    // embedder has succeeded, so a failure leaves the old chunks in place.
+    async fn flush(
+        &self,
+        pending: Vec<Pending>,
+        manifest: &mut Manifest,
+        stats: &mut IndexStats,
+        progress: Option<&mpsc::Sender<IndexEvent>>,
+        total: usize,
+    ) -> anyhow::Result<()> {
+        let texts: Vec<String> = pending
+            .iter()
+            .flat_map(|file| file.chunks.iter().map(embedding_text))
+            .collect();
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.config.batch_size.max(1)) {
+            let embedded = self.embedder.embed_batch(batch).await.context("Failed to embed chunks")?;
+            anyhow::ensure!(
+                embedded.len() == batch.len(),
+                "Embedder returned {} embeddings for {} texts",
+                embedded.len(),
+                batch.len()
+            );
+            embeddings.extend(embedded);
+        }
+
+        let mut embeddings = embeddings.into_iter();
+        for file in pending {
+            let Pending {
+                path,
+                done,
+                mut record,
+                old_chunks,
+                chunks,
+            } = file;
+            let ids: HashSet<&str> = chunks.iter().map(|chunk| chunk.id.as_str()).collect();
+            let stale: Vec<String> = old_chunks.into_iter().filter(|id| !ids.contains(id.as_str())).collect();
+            stats.chunks_removed += self.remove_chunks(&stale).await?;
+            for (chunk, embedding) in chunks.into_iter().zip(embeddings.by_ref()) {
+                self.lexical.add(&chunk);
+                record.chunks.push(chunk.id.clone());
+                self.index.add(chunk, embedding).await?;
+            }
+
+            stats.indexed += 1;
+            stats.chunks_added += record.chunks.len();
+            let chunks = record.chunks.len();
+            manifest.files.insert(path.clone(), record);
+            emit(progress, IndexEvent::Indexed { path, chunks, done, total }).await;
+        }
+        Ok(())
+    }
+
+    async fn read(&self, file: &Candidate) -> Result<Vec<u8>, String> {
+        if file.size > self.config.max_file_bytes {
+            return Err(format!("larger than {} bytes", self.config.max_file_bytes));
+        }
+        tokio::fs::read(self.root.join(&file.path))
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    // Adds an unchanged file's chunks to the lexical index. Returns false if
+    // any of them is missing from the index, so the file is indexed again.
+    async fn load_lexical(&self, chunk_ids: &[String]) -> anyhow::Result<bool> {
+        for id in chunk_ids {
+            let Some(chunk) = self.index.get(id).await? else {
+                return Ok(false);
+            };
+            if !self.lexical.contains(id) {
+                self.lexical.add(&chunk);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn remove_chunks(&self, chunk_ids: &[String]) -> anyhow::Result<usize> {
+        let mut removed = 0;
+        for id in chunk_ids {
+            self.lexical.remove(id);
+            if self.index.remove(id).await? {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    async fn save(&self, manifest: &Manifest) -> anyhow::Result<()> {
+        let text = serde_json::to_string(manifest)?;
+        if let Some(parent) = self.manifest_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let temp = self.manifest_path.with_extension("json.tmp");
+        tokio::fs::write(&temp, text).await?;
+        tokio::fs::rename(&temp, &self.manifest_path)
+            .await
+            .with_context(|| format!("Failed to write {}", self.manifest_path.display()))
+    }
+}
+
+async fn emit(progress: Option<&mpsc::Sender<IndexEvent>>, event: IndexEvent) {
+    if let Some(progress) = progress {
+        let _ = progress.send(event).await;
+    }
+}
+
+fn decode(bytes: Vec<u8>) -> Result<String, String> {
+    if bytes.iter().take(8192).any(|byte| *byte == 0) {
+        return Err("binary".to_string());
+    }
+    String::from_utf8(bytes).map_err(|_| "not UTF-8".to_string())
+}
+
+// What gets embedded: the chunk prefixed with its citation, so the path and
+// symbol count towards similarity.
+fn embedding_text(chunk: &Chunk) -> String {
+    match ChunkMetadata::from_chunk(chunk) {
+        Some(metadata) => format!("{}\n{}", metadata.citation(), chunk.text),
+        None => chunk.text.clone(),
+    }
+}
+
+// Files under `root` allowed by `.gitignore`, `.ignore`, `.alfredignore` and
+// the configured globs, sorted by path.
+fn scan(root: &Path, filter: &PathFilter) -> anyhow::Result<Vec<Candidate>> {
+    let mut walker = WalkBuilder::new(root);
+    walker
+        .require_git(false)
+        .add_custom_ignore_filename(".alfredignore")
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .filter_entry(|entry| !SKIP_DIRS.iter().any(|name| entry.file_name() == *name));
+
+    let mut files = Vec::new();
+    for entry in walker.build() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::debug!("Skipping unreadable entry: {}", e);
+                continue;
+            }
+        };
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+            continue;
+        }
+        let Ok(relative) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        let parts: Option<Vec<&str>> = relative.components().map(|part| part.as_os_str().to_str()).collect();
+        let Some(parts) = parts else {
+            continue;
+        };
+        let path = parts.join("/");
+        if !filter.matches(&path) {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        files.push(Candidate {
+            path,
+            size: metadata.len(),
+            modified_ns: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_nanos() as u64),
+        });
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HashEmbedder, InMemoryIndex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+
+    // Counts calls so tests can see what was re-embedded.
+    #[derive(Default)]
+    struct Counting {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Embedder for Counting {
+        async fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            HashEmbedder::new(32).embed(text).await
+        }
+    }
+
+    fn write(root: &Path, path: &str, text: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    fn indexer(root: &Path, state: &Path, index: Arc<InMemoryIndex>, embedder: Arc<Counting>) -> RepoIndexer {
+        let config = IndexerConfig {
+            exclude: vec!["docs/private/**".to_string()],
+            ..IndexerConfig::default()
+        };
+        RepoIndexer::new(root, state, index, embedder, "counting", config).unwrap()
+    }
+
+    fn indexer_with_calls(root: &Path, state: &Path, index: Arc<InMemoryIndex>) -> (RepoIndexer, Arc<Counting>) {
+        let embedder = Arc::new(Counting::default());
+        (indexer(root, state, index, embedder.clone()), embedder)
+    }
+
+    #[tokio::test]
+    async fn test_incremental_runs() {
+        let workspace = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        let root = workspace.path();
+        write(root, ".gitignore", "target/\n");
+        write(root, "src/lib.rs", "pub fn load_config() {}\n\npub fn save_config() {}\n");
+        write(root, "src/main.rs", "fn main() {}\n");
+        write(root, "README.md", "# Alfred\n\nAn agent.\n");
+        write(root, "target/debug/out.rs", "fn generated() {}\n");
+        write(root, "docs/private/notes.md", "# Secret\n");
+        write(root, ".alfred/config.toml", "[index]\n");
+        write(root, "logo.png", "\u{0}PNG");
+
+        let index = Arc::new(InMemoryIndex::new());
+        let embedder = Arc::new(Counting::default());
+        let indexer = indexer(root, state.path(), index.clone(), embedder.clone());
+        let (tx, mut rx) = mpsc::channel(64);
+        let stats = indexer.run(Some(&tx)).await.unwrap();
+        drop(tx);
+        assert_eq!((stats.files, stats.indexed, stats.skipped), (4, 3, 1));
+        let embedded = embedder.calls.load(Ordering::SeqCst);
+        assert_eq!(index.len().await.unwrap(), embedded);
+        assert!(index.get("src/lib.rs#load_config").await.unwrap().is_some());
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        assert_eq!(events[0], IndexEvent::Scanned { files: 4 });
+        assert!(events.contains(&IndexEvent::Skipped {
+            path: "logo.png".to_string(),
+            reason: "binary".to_string()
+        }));
+        assert!(matches!(events.last(), Some(IndexEvent::Finished(_))));
+
+        // Nothing changed: nothing is read or embedded again.
+        let stats = indexer.run(None).await.unwrap();
+        assert_eq!((stats.indexed, stats.unchanged, stats.skipped), (0, 4, 0));
+        assert_eq!(embedder.calls.load(Ordering::SeqCst), embedded);
+
+        // A new indexer over the same state rebuilds BM25 from the index
+        // without embedding anything.
+        let reopened = indexer_with_calls(root, state.path(), index.clone());
+        let stats = reopened.0.run(None).await.unwrap();
+        assert!(!stats.changed());
+        assert_eq!(reopened.1.calls.load(Ordering::SeqCst), 0);
+        assert_eq!(reopened.0.lexical().query("load_config", 1)[0].chunk_id, "src/lib.rs#load_config");
+
+        // Edit one file, delete another.
+        write(root, "src/lib.rs", "pub fn load_config() {}\n");
+        fs::remove_file(root.join("src/main.rs")).unwrap();
+        let stats = indexer.run(None).await.unwrap();
+        assert_eq!((stats.indexed, stats.removed), (1, 1));
+        assert_eq!(stats.chunks_removed, 2);
+        assert!(index.get("src/lib.rs#save_config").await.unwrap().is_none());
+        assert!(index.get("src/main.rs#main").await.unwrap().is_none());
+        assert!(!indexer.lexical().contains("src/lib.rs#save_config"));
+        assert_eq!(index.len().await.unwrap(), indexer.lexical().len());
+    }
+
+    #[tokio::test]
+    async fn test_missing_chunks_are_restored() {
+        let workspace = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        write(workspace.path(), "src/lib.rs", "pub fn load_config() {}\n\npub fn save_config() {}\n");
+        write(workspace.path(), "src/main.rs", "fn main() {}\n");
+        let index = Arc::new(InMemoryIndex::new());
+        let (indexer, embedder) = indexer_with_calls(workspace.path(), state.path(), index.clone());
+        indexer.run(None).await.unwrap();
+        let embedded = embedder.calls.load(Ordering::SeqCst);
+
+        // As if the write had been lost: the manifest still lists the chunk.
+        assert!(index.remove("src/lib.rs#save_config").await.unwrap());
+        let stats = indexer.run(None).await.unwrap();
+        assert_eq!((stats.indexed, stats.unchanged), (1, 1));
+        assert!(index.get("src/lib.rs#save_config").await.unwrap().is_some());
+        assert_eq!(embedder.calls.load(Ordering::SeqCst), embedded + 2);
+
+        // Same for a file rewritten with the same content, in a new indexer.
+        index.remove("src/main.rs#main").await.unwrap();
+        write(workspace.path(), "src/main.rs", "fn main() {}\n");
+        let reopened = indexer_with_calls(workspace.path(), state.path(), index.clone()).0;
+        assert_eq!(reopened.run(None).await.unwrap().indexed, 1);
+        assert!(index.get("src/main.rs#main").await.unwrap().is_some());
+        assert_eq!(index.len().await.unwrap(), reopened.lexical().len());
+    }
+
+    #[tokio::test]
+    async fn test_model_change_resets_manifest() {
+        let workspace = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        write(workspace.path(), "src/lib.rs", "pub fn a() {}\n");
+        let (first, _) = indexer_with_calls(workspace.path(), state.path(), Arc::new(InMemoryIndex::new()));
+        assert_eq!(first.run(None).await.unwrap().indexed, 1);
+
+        // Same manifest, another model: as after the index was invalidated.
+        let index = Arc::new(InMemoryIndex::new());
+        let embedder = Arc::new(Counting::default());
+        let config = IndexerConfig::default();
+        let second = RepoIndexer::new(workspace.path(), state.path(), index.clone(), embedder.clone(), "other", config).unwrap();
+        assert_eq!(second.run(None).await.unwrap().indexed, 1);
+        assert_eq!(embedder.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(index.len().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_nothing_to_embed_is_remembered() {
+        let workspace = tempfile::tempdir().unwrap();
+        let state = tempfile::tempdir().unwrap();
+        write(workspace.path(), "logo.png", "\u{0}PNG");
+        let index = Arc::new(InMemoryIndex::new());
+        let (indexer, _) = indexer_with_calls(workspace.path(), state.path(), index.clone());
+        assert_eq!(indexer.run(None).await.unwrap().skipped, 1);
+
+        // The index stays empty, but the skipped file isn't read again.
+        let stats = indexer.run(None).await.unwrap();
+        assert_eq!((stats.skipped, stats.unchanged), (0, 1));
+        assert!(index.is_empty().await.unwrap());
+    }
+}
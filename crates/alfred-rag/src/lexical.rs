@@ -0,0 +1,267 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::vector::top_k;
+use crate::{Chunk, QueryResult};
+
+const STOPWORDS: &[&str] = &[
+    "an", "and", "are", "as", "at", "be", "by", "for", "from", "how", "in", "is", "it", "of", "on", "or", "that",
+    "the", "this", "to", "was", "what", "where", "which", "with",
+];
+
+// Splits text into lowercase search terms. Identifiers are split at
+// `camelCase`, `PascalCase` and `snake_case` boundaries and also kept whole,
+// and path-like words (`src/config.rs`) are kept whole as well, so both
+// `load config` and `load_config` find `fn load_config`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let is_word = |c: char| c.is_alphanumeric() || matches!(c, '_' | '.' | '/' | '-' | ':');
+    for word in text.split(|c: char| !is_word(c)) {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        if word.is_empty() {
+            continue;
+        }
+        if word.contains(['/', '.']) {
+            push(&mut tokens, word.to_lowercase());
+        }
+        for segment in word.split(['/', '.', ':', '-']).filter(|s| !s.is_empty()) {
+            let parts: Vec<String> = segment
+                .split('_')
+                .flat_map(split_case)
+                .map(|part| part.to_lowercase())
+                .collect();
+            if parts.len() > 1 {
+                push(&mut tokens, segment.to_lowercase());
+            }
+            for part in parts {
+                push(&mut tokens, part);
+            }
+        }
+    }
+    tokens
+}
+
+fn push(tokens: &mut Vec<String>, token: String) {
+    let short = token.chars().count() < 2 && !token.chars().all(|c| c.is_ascii_digit());
+    if !short && !STOPWORDS.contains(&token.as_str()) {
+        tokens.push(token);
+    }
+}
+
+// `parseHTTPRequest` -> `parse`, `HTTP`, `Request`.
+fn split_case(word: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for i in 1..chars.len() {
+        let (index, c) = chars[i];
+        let previous = chars[i - 1].1;
+        let next_lower = chars.get(i + 1).is_some_and(|(_, n)| n.is_lowercase());
+        let boundary = (previous.is_lowercase() && c.is_uppercase())
+            || (previous.is_uppercase() && c.is_uppercase() && next_lower);
+        if boundary {
+            parts.push(&word[start..index]);
+            start = index;
+        }
+    }
+    if start < word.len() {
+        parts.push(&word[start..]);
+    }
+    parts
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bm25Config {
+    // Term frequency saturation.
+    pub k1: f32,
+    // Length normalization: 0 ignores document length, 1 fully normalizes.
+    pub b: f32,
+}
+
+impl Default for Bm25Config {
+    fn default() -> Self {
+        Self { k1: 1.2, b: 0.75 }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // Chunk id -> token count and distinct terms.
+    documents: HashMap<String, (usize, Vec<String>)>,
+    // Term -> chunk id -> term frequency.
+    postings: HashMap<String, HashMap<String, u32>>,
+    total_length: usize,
+}
+
+impl State {
+    fn remove(&mut self, chunk_id: &str) -> bool {
+        let Some((length, terms)) = self.documents.remove(chunk_id) else {
+            return false;
+        };
+        self.total_length -= length;
+        for term in terms {
+            if let Some(documents) = self.postings.get_mut(&term) {
+                documents.remove(chunk_id);
+                if documents.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+        true
+    }
+}
+
+// An inverted index scored with Okapi BM25. The chunk's path, symbol and
+// headings from its metadata are indexed along with its text.
+#[derive(Default)]
+pub struct Bm25Index {
+    config: Bm25Config,
+    state: RwLock<State>,
+}
+
+impl Bm25Index {
+    pub fn new(config: Bm25Config) -> Self {
+        Self {
+            config,
+            state: RwLock::default(),
+        }
+    }
+
+    // Replaces any chunk with the same id.
+    pub fn add(&self, chunk: &Chunk) {
+        let mut text = chunk.text.clone();
+        for key in ["path", "symbol"] {
+            if let Some(value) = chunk.metadata.get(key).and_then(|value| value.as_str()) {
+                text.push('\n');
+                text.push_str(value);
+            }
+        }
+        if let Some(headings) = chunk.metadata.get("headings").and_then(|value| value.as_array()) {
+            for heading in headings.iter().filter_map(|heading| heading.as_str()) {
+                text.push('\n');
+                text.push_str(heading);
+            }
+        }
+        let tokens = tokenize(&text);
+
+        let mut state = self.state.write().unwrap();
+        state.remove(&chunk.id);
+        state.total_length += tokens.len();
+        let length = tokens.len();
+        let mut terms = Vec::new();
+        for token in tokens {
+            let frequency = state.postings.entry(token.clone()).or_default().entry(chunk.id.clone()).or_default();
+            if *frequency == 0 {
+                terms.push(token);
+            }
+            *frequency += 1;
+        }
+        state.documents.insert(chunk.id.clone(), (length, terms));
+    }
+
+    pub fn remove(&self, chunk_id: &str) -> bool {
+        self.state.write().unwrap().remove(chunk_id)
+    }
+
+    pub fn contains(&self, chunk_id: &str) -> bool {
+        self.state.read().unwrap().documents.contains_key(chunk_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.read().unwrap().documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn query(&self, query: &str, k: usize) -> Vec<QueryResult> {
+        let state = self.state.read().unwrap();
+        let count = state.documents.len() as f32;
+        if count == 0.0 {
+            return Vec::new();
+        }
+        let average = (state.total_length as f32 / count).max(1.0);
+        let terms: HashSet<String> = tokenize(query).into_iter().collect();
+
+        let mut scores: HashMap<&str, f32> = HashMap::new();
+        for term in &terms {
+            let Some(documents) = state.postings.get(term) else {
+                continue;
+            };
+            let frequency = documents.len() as f32;
+            let idf = (1.0 + (count - frequency + 0.5) / (frequency + 0.5)).ln();
+            for (chunk_id, tf) in documents {
+                let tf = *tf as f32;
+                let length = state.documents[chunk_id].0 as f32;
+                let norm = self.config.k1 * (1.0 - self.config.b + self.config.b * length / average);
+                *scores.entry(chunk_id).or_default() += idf * tf * (self.config.k1 + 1.0) / (tf + norm);
+            }
+        }
+        let results = scores
+            .into_iter()
+            .map(|(chunk_id, score)| QueryResult::new(chunk_id, score))
+            .collect();
+        top_k(results, k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &str, text: &str, path: &str) -> Chunk {
+        Chunk {
+            id: id.to_string(),
+            text: text.to_string(),
+            metadata: serde_json::json!({ "path": path }),
+        }
+    }
+
+    #[test]
+    fn test_tokenize_code() {
+        assert_eq!(
+            tokenize("fn parseHTTPRequest(load_config: &str) in src/config.rs"),
+            [
+                "fn",
+                "parsehttprequest",
+                "parse",
+                "http",
+                "request",
+                "load_config",
+                "load",
+                "config",
+                "str",
+                "src/config.rs",
+                "src",
+                "config",
+                "rs"
+            ]
+        );
+        assert_eq!(tokenize("The API v2 is OK"), ["api", "v2", "ok"]);
+    }
+
+    #[test]
+    fn test_bm25_ranking() {
+        let index = Bm25Index::default();
+        index.add(&chunk("config", "pub fn load_config(path: &Path) -> Config", "src/config.rs"));
+        index.add(&chunk("shell", "run the shell command and capture output", "src/shell.rs"));
+        index.add(&chunk("docs", "Configuration lives in config.toml; load it once.", "docs/config.md"));
+
+        let results = index.query("loadConfig", 10);
+        assert_eq!(results[0].chunk_id, "config");
+        assert_eq!(results.len(), 2);
+        assert_eq!(index.query("shell.rs", 1)[0].chunk_id, "shell");
+        assert!(index.query("nothing matches", 5).is_empty());
+
+        index.add(&chunk("config", "unrelated now", "src/other.rs"));
+        assert_eq!(index.len(), 3);
+        assert!(index.query("load_config", 10).iter().all(|result| result.chunk_id != "config"));
+        assert!(index.remove("docs"));
+        assert!(!index.remove("docs"));
+        assert!(index.query("configuration", 5).is_empty());
+    }
+}
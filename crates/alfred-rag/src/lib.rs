@@ -1,7 +1,28 @@
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub mod chunk;
+mod disk;
+mod embed;
+mod hnsw;
+mod hybrid;
+mod indexer;
+mod lexical;
+mod memory;
+mod rerank;
+pub mod vector;
+
+pub use disk::{project_cache_dir, DiskIndex, IndexHeader};
+pub use embed::{HashEmbedder, LocalEmbedder, RemoteEmbedder, RemoteEmbedderConfig, WordPiece};
+pub use hnsw::{HnswConfig, HnswIndex};
+pub use hybrid::{reciprocal_rank_fusion, HybridConfig, HybridRetriever, RankedList};
+pub use indexer::{IndexEvent, IndexStats, IndexerConfig, RepoIndexer};
+pub use lexical::{tokenize, Bm25Config, Bm25Index};
+pub use memory::InMemoryIndex;
+pub use rerank::{candidates, mmr, CrossEncoderReranker, LlmReranker, MmrConfig, RerankCandidate, Reranker};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Chunk {
     pub id: String,
     pub text: String,
@@ -12,28 +33,73 @@ pub struct Chunk {
 pub struct QueryResult {
     pub chunk_id: String,
     pub score: f32,
+    // How each retriever ranked the chunk, for fused results.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<SourceScore>,
+}
+
+impl QueryResult {
+    pub fn new(chunk_id: impl Into<String>, score: f32) -> Self {
+        Self {
+            chunk_id: chunk_id.into(),
+            score,
+            sources: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceScore {
+    // `lexical`, `vector` or `rerank`.
+    pub source: String,
+    // 1-based position in that retriever's results.
+    pub rank: usize,
+    // The retriever's own score: BM25, cosine similarity or the reranker's.
+    pub score: f32,
+}
+
+#[derive(Debug, Error)]
+pub enum IndexError {
+    #[error("embedding has {actual} dimensions, the index expects {expected}")]
+    DimensionMismatch { expected: usize, actual: usize },
+    #[error("embedding is empty")]
+    EmptyEmbedding,
 }
 
 #[async_trait]
 pub trait Embedder: Send + Sync {
     async fn embed(&self, text: &str) -> anyhow::Result<Vec<f32>>;
+
+    // One embedding per text, in order. Override when the backend can do a
+    // batch in one call.
+    async fn embed_batch(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for text in texts {
+            embeddings.push(self.embed(text).await?);
+        }
+        Ok(embeddings)
+    }
+}
+
+// 64-bit FNV-1a: stable across runs and platforms, unlike `DefaultHasher`.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100_0000_01b3))
 }
 
 #[async_trait]
 pub trait Index: Send + Sync {
+    // Adding a chunk whose id is already present replaces it.
     async fn add(&self, chunk: Chunk, embedding: Vec<f32>) -> anyhow::Result<()>;
+    // Best matches first; equal scores are ordered by chunk id.
     async fn query(&self, embedding: Vec<f32>, top_k: usize) -> anyhow::Result<Vec<QueryResult>>;
-}
-
-pub struct InMemoryIndex;
-
-#[async_trait]
-impl Index for InMemoryIndex {
-    async fn add(&self, _chunk: Chunk, _embedding: Vec<f32>) -> anyhow::Result<()> {
-        Ok(())
-    }
+    // Returns whether the chunk was present.
+    async fn remove(&self, chunk_id: &str) -> anyhow::Result<bool>;
+    async fn get(&self, chunk_id: &str) -> anyhow::Result<Option<Chunk>>;
+    async fn len(&self) -> anyhow::Result<usize>;
 
-    async fn query(&self, _embedding: Vec<f32>, _top_k: usize) -> anyhow::Result<Vec<QueryResult>> {
-        Ok(Vec::new())
+    async fn is_empty(&self) -> anyhow::Result<bool> {
+        Ok(self.len().await? == 0)
     }
 }
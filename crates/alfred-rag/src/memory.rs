@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+
+use crate::vector::{check_dimension, dot, normalize, top_k};
+use crate::{Chunk, Index, QueryResult};
+
+struct Entry {
+    chunk: Chunk,
+    embedding: Vec<f32>,
+}
+
+#[derive(Default)]
+struct State {
+    // Fixed by the first embedding added.
+    dimension: Option<usize>,
+    entries: HashMap<String, Entry>,
+}
+
+// Exact cosine search over every stored embedding. Fine for a few tens of
+// thousands of chunks; beyond that use an approximate index.
+#[derive(Default)]
+pub struct InMemoryIndex {
+    state: RwLock<State>,
+}
+
+impl InMemoryIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn dimension(&self) -> Option<usize> {
+        self.state.read().unwrap().dimension
+    }
+}
+
+#[async_trait]
+impl Index for InMemoryIndex {
+    async fn add(&self, chunk: Chunk, embedding: Vec<f32>) -> anyhow::Result<()> {
+        let embedding = normalize(embedding)?;
+        let mut state = self.state.write().unwrap();
+        if let Some(dimension) = state.dimension {
+            check_dimension(dimension, &embedding)?;
+        }
+        state.dimension = Some(embedding.len());
+        state.entries.insert(chunk.id.clone(), Entry { chunk, embedding });
+        Ok(())
+    }
+
+    async fn query(&self, embedding: Vec<f32>, k: usize) -> anyhow::Result<Vec<QueryResult>> {
+        let embedding = normalize(embedding)?;
+        let state = self.state.read().unwrap();
+        let Some(dimension) = state.dimension else {
+            return Ok(Vec::new());
+        };
+        check_dimension(dimension, &embedding)?;
+        let results = state
+            .entries
+            .values()
+            .map(|entry| QueryResult::new(entry.chunk.id.clone(), dot(&embedding, &entry.embedding)))
+            .collect();
+        Ok(top_k(results, k))
+    }
+
+    async fn remove(&self, chunk_id: &str) -> anyhow::Result<bool> {
+        let mut state = self.state.write().unwrap();
+        let removed = state.entries.remove(chunk_id).is_some();
+        if state.entries.is_empty() {
+            state.dimension = None;
+        }
+        Ok(removed)
+    }
+
+    async fn get(&self, chunk_id: &str) -> anyhow::Result<Option<Chunk>> {
+        Ok(self.state.read().unwrap().entries.get(chunk_id).map(|entry| entry.chunk.clone()))
+    }
+
+    async fn len(&self) -> anyhow::Result<usize> {
+        Ok(self.state.read().unwrap().entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IndexError;
+
+    fn chunk(id: &str) -> Chunk {
+        Chunk {
+            id: id.to_string(),
+            text: format!("text of {}", id),
+            metadata: serde_json::json!({ "path": format!("{}.rs", id) }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_ranks_by_cosine() {
+        let index = InMemoryIndex::new();
+        index.add(chunk("a"), vec![1.0, 0.0]).await.unwrap();
+        index.add(chunk("b"), vec![10.0, 10.0]).await.unwrap();
+        index.add(chunk("c"), vec![0.0, 3.0]).await.unwrap();
+        // Same direction as `a`, so it ties and sorts after it by id.
+        index.add(chunk("d"), vec![2.0, 0.0]).await.unwrap();
+
+        let results = index.query(vec![5.0, 0.5], 3).await.unwrap();
+        let ids: Vec<&str> = results.iter().map(|r| r.chunk_id.as_str()).collect();
+        assert_eq!(ids, ["a", "d", "b"]);
+        assert!((results[0].score - results[1].score).abs() < 1e-6);
+        assert!(results[0].score <= 1.0 && results[2].score < results[0].score);
+        assert_eq!(index.query(vec![1.0, 0.0], 10).await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_update_remove_and_dimensions() {
+        let index = InMemoryIndex::new();
+        assert!(index.query(vec![1.0], 5).await.unwrap().is_empty());
+        index.add(chunk("a"), vec![1.0, 0.0]).await.unwrap();
+        index.add(chunk("b"), vec![0.0, 1.0]).await.unwrap();
+
+        let err = index.add(chunk("c"), vec![1.0, 0.0, 0.0]).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<IndexError>(),
+            Some(IndexError::DimensionMismatch { expected: 2, actual: 3 })
+        ));
+        assert!(index.query(vec![1.0], 1).await.is_err());
+
+        let mut updated = chunk("a");
+        updated.text = "new text".to_string();
+        index.add(updated, vec![0.0, 1.0]).await.unwrap();
+        assert_eq!(index.len().await.unwrap(), 2);
+        assert_eq!(index.get("a").await.unwrap().unwrap().text, "new text");
+        assert_eq!(index.query(vec![1.0, 0.0], 1).await.unwrap()[0].score, 0.0);
+
+        assert!(index.remove("a").await.unwrap());
+        assert!(!index.remove("a").await.unwrap());
+        assert!(index.get("a").await.unwrap().is_none());
+        assert!(index.remove("b").await.unwrap());
+        // An empty index accepts a new dimension.
+        index.add(chunk("c"), vec![1.0, 0.0, 0.0]).await.unwrap();
+        assert_eq!(index.dimension(), Some(3));
+    }
+}
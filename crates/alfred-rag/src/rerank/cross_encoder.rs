@@ -0,0 +1,116 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::bail;
+use async_trait::async_trait;
+
+use super::{apply_scores, passage, RerankCandidate, Reranker};
+use crate::embed::bert::BertModel;
+use crate::WordPiece;
+
+// Passages are cut to this many characters before tokenizing; the model
+// sees at most `max_position_embeddings` tokens anyway.
+const MAX_PASSAGE_CHARS: usize = 4000;
+
+// Scores each (query, chunk) pair with a local cross-encoder, e.g. a
+// download of cross-encoder/ms-marco-MiniLM-L-6-v2: a BERT checkpoint with
+// a classification head, in the same directory layout `LocalEmbedder`
+// reads. Slower than a bi-encoder, so it only sees first-stage results.
+#[derive(Clone)]
+pub struct CrossEncoderReranker {
+    model: Arc<BertModel>,
+    tokenizer: Arc<WordPiece>,
+    max_tokens: usize,
+    batch_size: usize,
+}
+
+impl CrossEncoderReranker {
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let model = BertModel::load(dir)?;
+        let tokenizer = WordPiece::load(dir)?;
+        if !model.has_classifier() {
+            bail!("{} has no classification head; is it a cross-encoder?", dir.display());
+        }
+        Ok(Self {
+            max_tokens: model.max_tokens(),
+            model: Arc::new(model),
+            tokenizer: Arc::new(tokenizer),
+            batch_size: 16,
+        })
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    // Blocking; one relevance log-odds per text, higher is more relevant.
+    pub fn score(&self, query: &str, texts: &[String]) -> anyhow::Result<Vec<f32>> {
+        let encoded: Vec<(Vec<u32>, Vec<u32>)> = texts
+            .iter()
+            .map(|text| self.tokenizer.encode_pair(query, text, self.max_tokens))
+            .collect();
+        let mut scores = Vec::with_capacity(texts.len());
+        for group in encoded.chunks(self.batch_size) {
+            let batch: Vec<(&[u32], &[u32])> = group.iter().map(|(ids, types)| (ids.as_slice(), types.as_slice())).collect();
+            scores.extend(self.model.classify(&batch)?);
+        }
+        Ok(scores)
+    }
+}
+
+#[async_trait]
+impl Reranker for CrossEncoderReranker {
+    async fn rerank(
+        &self,
+        query: &str,
+        candidates: Vec<RerankCandidate>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<RerankCandidate>> {
+        let texts: Vec<String> = candidates
+            .iter()
+            .map(|candidate| passage(&candidate.chunk, MAX_PASSAGE_CHARS))
+            .collect();
+        let reranker = self.clone();
+        let query = query.to_string();
+        let scores = tokio::task::spawn_blocking(move || reranker.score(&query, &texts)).await??;
+        Ok(apply_scores(candidates, scores, limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embed::bert::tests::write_model;
+    use crate::rerank::tests::candidate;
+
+    #[tokio::test]
+    async fn test_cross_encoder_reranks() {
+        let dir = tempfile::tempdir().unwrap();
+        write_model(dir.path(), false);
+        let error = CrossEncoderReranker::load(dir.path()).err().unwrap();
+        assert!(error.to_string().contains("no classification head"));
+
+        write_model(dir.path(), true);
+        let reranker = CrossEncoderReranker::load(dir.path()).unwrap().with_batch_size(2);
+        let texts: Vec<String> = ["load config file", "render widgets", "config", "widget file"]
+            .iter()
+            .map(|text| text.to_string())
+            .collect();
+        let scores = reranker.score("load config", &texts).unwrap();
+        assert_eq!(scores.len(), texts.len());
+        assert!(scores.iter().all(|score| score.is_finite()));
+        let single = reranker.score("load config", &texts[..1]).unwrap();
+        assert!((single[0] - scores[0]).abs() < 1e-5);
+
+        let candidates: Vec<RerankCandidate> = texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| candidate(&index.to_string(), "src/a.rs", text, 0.0))
+            .collect();
+        let ranked = reranker.rerank("load config", candidates, 3).await.unwrap();
+        assert_eq!(ranked.len(), 3);
+        assert!(ranked.windows(2).all(|pair| pair[0].result.score >= pair[1].result.score));
+        assert_eq!(ranked[0].result.sources[0].source, "rerank");
+    }
+}
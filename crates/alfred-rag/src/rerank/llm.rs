@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use alfred_core::{AgentEvent, AgentRouter, Message, Role};
+use async_trait::async_trait;
+
+use super::{apply_scores, passage, RerankCandidate, Reranker};
+
+const SYSTEM_PROMPT: &str = "You rank search results for a code assistant. Given a query and numbered passages, \
+                             order the passages from most to least useful for answering the query. Reply with a \
+                             JSON array of passage numbers only, e.g. [3, 1, 2], with no commentary.";
+
+// Listwise reranking with a chat model: the first `window` candidates go
+// into one prompt and the model replies with their order. Candidates past
+// the window keep their first-stage order after the ranked ones. If the
+// model can't be reached, everything keeps its first-stage order.
+pub struct LlmReranker {
+    router: Arc<dyn AgentRouter>,
+    window: usize,
+    max_chars: usize,
+}
+
+impl LlmReranker {
+    pub fn new(router: Arc<dyn AgentRouter>) -> Self {
+        Self {
+            router,
+            window: 20,
+            max_chars: 1200,
+        }
+    }
+
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    // Each passage is cut to this many characters in the prompt.
+    pub fn with_max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = max_chars;
+        self
+    }
+
+    fn messages(&self, query: &str, candidates: &[RerankCandidate]) -> Vec<Message> {
+        let mut prompt = format!("Query: {}\n\n", query);
+        for (index, candidate) in candidates.iter().enumerate() {
+            prompt.push_str(&format!("[{}] {}\n\n", index + 1, passage(&candidate.chunk, self.max_chars)));
+        }
+        prompt.push_str(&format!("Rank all {} passages.", candidates.len()));
+        vec![
+            Message::new(Role::System, SYSTEM_PROMPT.to_string()),
+            Message::new(Role::User, prompt),
+        ]
+    }
+}
+
+#[async_trait]
+impl Reranker for LlmReranker {
+    async fn rerank(
+        &self,
+        query: &str,
+        candidates: Vec<RerankCandidate>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<RerankCandidate>> {
+        let window = candidates.len().min(self.window);
+        if window < 2 {
+            let scores = vec![1.0; candidates.len()];
+            return Ok(apply_scores(candidates, scores, limit));
+        }
+        let mut reply = String::new();
+        match self.router.respond(&self.messages(query, &candidates[..window])).await {
+            Ok(events) => {
+                for event in events {
+                    if let AgentEvent::MessageDelta(content) = event {
+                        reply.push_str(&content);
+                    }
+                }
+            }
+            // An empty reply ranks nothing, so the first-stage order stays.
+            Err(e) => tracing::warn!("LLM reranking failed, keeping first-stage order: {:#}", e),
+        }
+        let order = parse_ranking(&reply, window);
+        let mut scores = vec![0.0; candidates.len()];
+        for (position, index) in order.into_iter().enumerate() {
+            scores[index] = (window - position) as f32 / window as f32;
+        }
+        Ok(apply_scores(candidates, scores, limit))
+    }
+}
+
+// Reads the 1-based passage numbers from the model's reply: the first JSON
+// array if there is one, otherwise every number in it. Returns a 0-based
+// permutation of `count`; repeats and numbers out of range are dropped and
+// passages the model left out follow in their original order.
+fn parse_ranking(reply: &str, count: usize) -> Vec<usize> {
+    let array = reply.find('[').and_then(|start| {
+        let end = start + reply[start..].find(']')?;
+        serde_json::from_str::<Vec<usize>>(&reply[start..=end]).ok()
+    });
+    let numbers = array.unwrap_or_else(|| {
+        reply
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|number| number.parse().ok())
+            .collect()
+    });
+    let mut seen = vec![false; count];
+    let mut order = Vec::with_capacity(count);
+    for number in numbers {
+        if (1..=count).contains(&number) && !seen[number - 1] {
+            seen[number - 1] = true;
+            order.push(number - 1);
+        }
+    }
+    order.extend((0..count).filter(|index| !seen[*index]));
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rerank::tests::candidate;
+    use std::sync::Mutex;
+
+    struct Canned {
+        reply: &'static str,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl AgentRouter for Canned {
+        async fn respond(&self, messages: &[Message]) -> anyhow::Result<Vec<AgentEvent>> {
+            self.prompts.lock().unwrap().push(messages[1].content.clone());
+            Ok(vec![AgentEvent::MessageDelta(self.reply.to_string()), AgentEvent::Done])
+        }
+    }
+
+    struct Unreachable;
+
+    #[async_trait]
+    impl AgentRouter for Unreachable {
+        async fn respond(&self, _messages: &[Message]) -> anyhow::Result<Vec<AgentEvent>> {
+            anyhow::bail!("connection refused")
+        }
+    }
+
+    #[test]
+    fn test_parse_ranking() {
+        assert_eq!(parse_ranking("[3, 1, 2]", 3), [2, 0, 1]);
+        assert_eq!(parse_ranking("Ranking: ```json\n[2, 2, 9]\n```", 3), [1, 0, 2]);
+        assert_eq!(parse_ranking("3 > 1", 3), [2, 0, 1]);
+        assert_eq!(parse_ranking("no idea", 2), [0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_llm_reranker_orders_window() {
+        let router = Arc::new(Canned {
+            reply: "[2, 1]",
+            prompts: Mutex::new(Vec::new()),
+        });
+        let reranker = LlmReranker::new(router.clone()).with_window(2);
+        let candidates = vec![
+            candidate("a", "src/a.rs", "fn a() {}", 0.9),
+            candidate("b", "src/b.rs", "fn b() {}", 0.8),
+            candidate("c", "src/c.rs", "fn c() {}", 0.7),
+        ];
+        let ranked = reranker.rerank("where is b", candidates, 10).await.unwrap();
+        let ids: Vec<&str> = ranked.iter().map(|candidate| candidate.chunk.id.as_str()).collect();
+        assert_eq!(ids, ["b", "a", "c"]);
+        assert_eq!(ranked[0].result.score, 1.0);
+        assert_eq!(ranked[2].result.score, 0.0);
+
+        let prompt = router.prompts.lock().unwrap()[0].clone();
+        assert!(prompt.contains("Query: where is b"));
+        assert!(prompt.contains("[2] src/b.rs:1-1\nfn b() {}"));
+        assert!(!prompt.contains("src/c.rs"));
+
+        let reranker = LlmReranker::new(Arc::new(Unreachable));
+        let candidates = vec![candidate("a", "src/a.rs", "", 0.9), candidate("b", "src/b.rs", "", 0.8)];
+        let ranked = reranker.rerank("where is b", candidates, 10).await.unwrap();
+        let ids: Vec<&str> = ranked.iter().map(|candidate| candidate.chunk.id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+    }
+}
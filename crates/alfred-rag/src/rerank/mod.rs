@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::chunk::ChunkMetadata;
+use crate::lexical::tokenize;
+use crate::{Chunk, Index, QueryResult, SourceScore};
+
+mod cross_encoder;
+mod llm;
+
+pub use cross_encoder::CrossEncoderReranker;
+pub use llm::LlmReranker;
+
+// A first-stage result with the chunk it points at.
+#[derive(Debug, Clone)]
+pub struct RerankCandidate {
+    pub result: QueryResult,
+    pub chunk: Chunk,
+}
+
+// Looks up the chunks behind `results`, dropping any the index no longer has.
+pub async fn candidates(index: &dyn Index, results: Vec<QueryResult>) -> anyhow::Result<Vec<RerankCandidate>> {
+    let mut candidates = Vec::with_capacity(results.len());
+    for result in results {
+        if let Some(chunk) = index.get(&result.chunk_id).await? {
+            candidates.push(RerankCandidate { result, chunk });
+        }
+    }
+    Ok(candidates)
+}
+
+#[async_trait]
+pub trait Reranker: Send + Sync {
+    // Reorders first-stage `candidates` (best first) for `query` and keeps
+    // at most `limit`. Each result's score becomes the reranker's, and the
+    // reranker is added to its `sources` as `rerank`.
+    async fn rerank(
+        &self,
+        query: &str,
+        candidates: Vec<RerankCandidate>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<RerankCandidate>>;
+}
+
+// Sorts by `scores` (one per candidate), keeping the incoming order for ties,
+// and records the new ranks.
+pub(crate) fn apply_scores(candidates: Vec<RerankCandidate>, scores: Vec<f32>, limit: usize) -> Vec<RerankCandidate> {
+    let mut scored: Vec<(f32, RerankCandidate)> = scores.into_iter().zip(candidates).collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored
+        .into_iter()
+        .take(limit)
+        .enumerate()
+        .map(|(index, (score, mut candidate))| {
+            candidate.result.score = score;
+            candidate.result.sources.push(SourceScore {
+                source: "rerank".to_string(),
+                rank: index + 1,
+                score,
+            });
+            candidate
+        })
+        .collect()
+}
+
+// The text a reranker reads for a chunk: its citation, then the chunk.
+pub(crate) fn passage(chunk: &Chunk, max_chars: usize) -> String {
+    let text: String = chunk.text.chars().take(max_chars).collect();
+    match ChunkMetadata::from_chunk(chunk) {
+        Some(metadata) => format!("{}\n{}", metadata.citation(), text),
+        None => text,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MmrConfig {
+    // 1 ranks by relevance alone; lower values trade relevance for variety.
+    pub lambda: f32,
+    // Chunks of the same file count as at least this similar, so a file
+    // can't take every slot with distinct-looking chunks.
+    pub same_file: f32,
+}
+
+impl Default for MmrConfig {
+    fn default() -> Self {
+        Self {
+            lambda: 0.7,
+            same_file: 0.5,
+        }
+    }
+}
+
+// Maximal marginal relevance: repeatedly picks the candidate maximizing
+// `lambda * relevance - (1 - lambda) * max similarity to those picked`.
+// Relevance is the candidate's score scaled to [0, 1] across `candidates`;
+// similarity is the cosine of their term counts, raised to `same_file` for
+// chunks of one file.
+pub fn mmr(candidates: Vec<RerankCandidate>, config: &MmrConfig, limit: usize) -> Vec<RerankCandidate> {
+    let (low, high) = candidates.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), candidate| {
+        (low.min(candidate.result.score), high.max(candidate.result.score))
+    });
+    let relevance: Vec<f32> = candidates
+        .iter()
+        .map(|candidate| match high > low {
+            true => (candidate.result.score - low) / (high - low),
+            false => 1.0,
+        })
+        .collect();
+    let terms: Vec<HashMap<String, f32>> = candidates.iter().map(|candidate| term_counts(&candidate.chunk)).collect();
+    let paths: Vec<Option<String>> = candidates
+        .iter()
+        .map(|candidate| ChunkMetadata::from_chunk(&candidate.chunk).map(|metadata| metadata.path))
+        .collect();
+    let similarity = |a: usize, b: usize| {
+        let text = cosine(&terms[a], &terms[b]);
+        match (&paths[a], &paths[b]) {
+            (Some(a), Some(b)) if a == b => text.max(config.same_file),
+            _ => text,
+        }
+    };
+
+    let mut remaining: Vec<usize> = (0..candidates.len()).collect();
+    let mut picked: Vec<usize> = Vec::new();
+    while picked.len() < limit && !remaining.is_empty() {
+        let mut best = 0;
+        let mut best_score = f32::NEG_INFINITY;
+        for (position, &candidate) in remaining.iter().enumerate() {
+            let redundancy = picked.iter().map(|&other| similarity(candidate, other)).fold(0.0, f32::max);
+            let score = config.lambda * relevance[candidate] - (1.0 - config.lambda) * redundancy;
+            if score > best_score {
+                best = position;
+                best_score = score;
+            }
+        }
+        picked.push(remaining.remove(best));
+    }
+
+    let mut candidates: Vec<Option<RerankCandidate>> = candidates.into_iter().map(Some).collect();
+    picked.into_iter().filter_map(|index| candidates[index].take()).collect()
+}
+
+fn term_counts(chunk: &Chunk) -> HashMap<String, f32> {
+    let mut counts = HashMap::new();
+    for token in tokenize(&chunk.text) {
+        *counts.entry(token).or_default() += 1.0;
+    }
+    counts
+}
+
+fn cosine(a: &HashMap<String, f32>, b: &HashMap<String, f32>) -> f32 {
+    let dot: f32 = a.iter().filter_map(|(term, x)| b.get(term).map(|y| x * y)).sum();
+    let norm = |counts: &HashMap<String, f32>| counts.values().map(|x| x * x).sum::<f32>().sqrt();
+    match norm(a) * norm(b) {
+        0.0 => 0.0,
+        product => dot / product,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::InMemoryIndex;
+
+    pub(crate) fn candidate(id: &str, path: &str, text: &str, score: f32) -> RerankCandidate {
+        let metadata = ChunkMetadata {
+            path: path.to_string(),
+            language: None,
+            symbol: None,
+            kind: "code".to_string(),
+            start_line: 1,
+            end_line: 1,
+            headings: Vec::new(),
+        };
+        RerankCandidate {
+            result: QueryResult::new(id, score),
+            chunk: Chunk {
+                id: id.to_string(),
+                text: text.to_string(),
+                metadata: serde_json::to_value(metadata).unwrap(),
+            },
+        }
+    }
+
+    fn ids(candidates: &[RerankCandidate]) -> Vec<&str> {
+        candidates.iter().map(|candidate| candidate.chunk.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_mmr_spreads_across_files() {
+        let candidates = vec![
+            candidate("a1", "src/a.rs", "fn load_config reads the config file", 0.9),
+            candidate("a2", "src/a.rs", "fn load_config reads the config file again", 0.88),
+            candidate("a3", "src/a.rs", "fn save_config writes it back", 0.86),
+            candidate("b1", "src/b.rs", "parse toml into a struct", 0.8),
+            candidate("c1", "docs/config.md", "Configuration reference", 0.5),
+        ];
+        let relevance_only = MmrConfig {
+            lambda: 1.0,
+            ..MmrConfig::default()
+        };
+        assert_eq!(ids(&mmr(candidates.clone(), &relevance_only, 3)), ["a1", "a2", "a3"]);
+        // The near-copy of a1 drops out and b1 moves ahead of a3 from a1's file.
+        assert_eq!(ids(&mmr(candidates.clone(), &MmrConfig::default(), 3)), ["a1", "b1", "a3"]);
+        assert_eq!(mmr(candidates, &MmrConfig::default(), 10).len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_candidates_and_scores() {
+        let index = InMemoryIndex::new();
+        let chunk = candidate("kept", "src/a.rs", "text", 1.0).chunk;
+        index.add(chunk, vec![1.0, 0.0]).await.unwrap();
+        let results = vec![QueryResult::new("gone", 2.0), QueryResult::new("kept", 1.0)];
+        let found = candidates(&index, results).await.unwrap();
+        assert_eq!(ids(&found), ["kept"]);
+
+        let found = vec![candidate("x", "a", "", 0.0), candidate("y", "a", "", 0.0), candidate("z", "a", "", 0.0)];
+        let ranked = apply_scores(found, vec![0.1, 0.7, 0.1], 2);
+        assert_eq!(ids(&ranked), ["y", "x"]);
+        assert_eq!(ranked[1].result.score, 0.1);
+        assert_eq!(ranked[1].result.sources[0].rank, 2);
+    }
+}
@@ -0,0 +1,45 @@
+use std::cmp::Ordering;
+
+use crate::{IndexError, QueryResult};
+
+// Scales `embedding` to unit length so a dot product is the cosine
+// similarity. A zero vector stays zero and matches nothing.
+pub fn normalize(mut embedding: Vec<f32>) -> Result<Vec<f32>, IndexError> {
+    if embedding.is_empty() {
+        return Err(IndexError::EmptyEmbedding);
+    }
+    let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 && norm.is_finite() {
+        embedding.iter_mut().for_each(|x| *x /= norm);
+    }
+    Ok(embedding)
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+pub fn check_dimension(expected: usize, embedding: &[f32]) -> Result<(), IndexError> {
+    if expected != embedding.len() {
+        return Err(IndexError::DimensionMismatch {
+            expected,
+            actual: embedding.len(),
+        });
+    }
+    Ok(())
+}
+
+// Highest score first, then by chunk id so results are stable.
+pub fn rank(a: &QueryResult, b: &QueryResult) -> Ordering {
+    b.score.total_cmp(&a.score).then_with(|| a.chunk_id.cmp(&b.chunk_id))
+}
+
+// Keeps the `top_k` best results in `rank` order.
+pub fn top_k(mut results: Vec<QueryResult>, top_k: usize) -> Vec<QueryResult> {
+    if results.len() > top_k {
+        results.select_nth_unstable_by(top_k, rank);
+        results.truncate(top_k);
+    }
+    results.sort_by(rank);
+    results
+}
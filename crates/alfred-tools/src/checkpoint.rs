@@ -0,0 +1,354 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::git::{GitError, GitTool};
+
+// Checkpoints form a chain of commits under this ref, newest first. Nothing
+// here touches the user's index, HEAD or branches.
+pub const CHECKPOINT_REF: &str = "refs/alfred/checkpoints";
+
+const CHECKPOINT_SUBJECT: &str = "alfred checkpoint";
+const CHECKPOINT_FORMAT: &str = "--format=%H%x1f%T%x1f%at%x1f%b%x1e";
+// Checkpoint commits are authored by alfred so they work without a
+// configured git identity.
+const IDENTITY: &[(&str, &str)] = &[
+    ("GIT_AUTHOR_NAME", "alfred"),
+    ("GIT_AUTHOR_EMAIL", "alfred@localhost"),
+    ("GIT_COMMITTER_NAME", "alfred"),
+    ("GIT_COMMITTER_EMAIL", "alfred@localhost"),
+];
+
+// `checkout-index` takes paths on the command line; stay well under the
+// argument limit.
+const PATHS_PER_CHECKOUT: usize = 500;
+
+static NEXT_INDEX: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error(transparent)]
+    Git(#[from] GitError),
+    #[error("no single checkpoint matches `{0}`")]
+    Unknown(String),
+    #[error("checkpoint I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type CheckpointResult<T> = Result<T, CheckpointError>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub id: String,
+    pub tree: String,
+    // Seconds since the Unix epoch.
+    pub timestamp: i64,
+    // The prompt (or command) that was about to run.
+    pub prompt: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RewindOutcome {
+    pub restored: Checkpoint,
+    // Snapshot of the working tree just before the rewind, to undo it.
+    pub safety: Checkpoint,
+    pub written: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+// Snapshots of the working tree (tracked and untracked, minus ignored files)
+// taken before agent turns, so edits can be rolled back.
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoints {
+    git: GitTool,
+}
+
+impl Checkpoints {
+    pub fn new(git: GitTool) -> Self {
+        Self { git }
+    }
+
+    // Records the working tree unless it matches the latest checkpoint, in
+    // which case that one is returned.
+    pub fn create(&self, cwd: &str, prompt: &str) -> CheckpointResult<Checkpoint> {
+        let root = self.root(cwd)?;
+        let tree = self.snapshot_tree(&root)?;
+        let latest = self.latest(&root)?;
+        if let Some(latest) = latest.as_ref().filter(|latest| latest.tree == tree) {
+            return Ok(latest.clone());
+        }
+
+        let message = format!("{}\n\n{}", CHECKPOINT_SUBJECT, prompt);
+        let mut args = vec!["commit-tree", tree.as_str(), "-m", message.as_str()];
+        if let Some(latest) = &latest {
+            args.extend(["-p", latest.id.as_str()]);
+        }
+        let id = self.git.run_with_env(&root, IDENTITY, args)?.trim().to_string();
+        // Compare-and-swap so a concurrent checkpoint is never lost.
+        let old = latest.as_ref().map_or("", |latest| latest.id.as_str());
+        self.git.run(&root, ["update-ref", CHECKPOINT_REF, id.as_str(), old])?;
+        self.get(&root, &id)
+    }
+
+    pub fn list(&self, cwd: &str) -> CheckpointResult<Vec<Checkpoint>> {
+        let root = self.root(cwd)?;
+        if self.resolve_ref(&root)?.is_none() {
+            return Ok(Vec::new());
+        }
+        parse_checkpoints(&self.git.run(&root, ["log", CHECKPOINT_FORMAT, CHECKPOINT_REF, "--"])?)
+    }
+
+    // Accepts a full or abbreviated checkpoint id.
+    pub fn get(&self, cwd: &str, id: &str) -> CheckpointResult<Checkpoint> {
+        let root = self.root(cwd)?;
+        let matches = self
+            .list(&root)?
+            .into_iter()
+            .filter(|checkpoint| !id.is_empty() && checkpoint.id.starts_with(id))
+            .collect::<Vec<_>>();
+        match &matches[..] {
+            [checkpoint] => Ok(checkpoint.clone()),
+            _ => Err(CheckpointError::Unknown(id.to_string())),
+        }
+    }
+
+    // Diff from checkpoint `from` to `to`, or to the current working tree.
+    pub fn diff(&self, cwd: &str, from: &str, to: Option<&str>) -> CheckpointResult<String> {
+        let root = self.root(cwd)?;
+        let from = self.get(&root, from)?.tree;
+        let to = match to {
+            Some(to) => self.get(&root, to)?.tree,
+            None => self.snapshot_tree(&root)?,
+        };
+        Ok(self.git.run(&root, ["diff", from.as_str(), to.as_str()])?)
+    }
+
+    // Makes the working tree match checkpoint `id`: files are rewritten and
+    // files created since are deleted. The index and ignored files are left
+    // alone, and a safety checkpoint is taken first so the rewind itself can
+    // be undone.
+    pub fn rewind(&self, cwd: &str, id: &str) -> CheckpointResult<RewindOutcome> {
+        let root = self.root(cwd)?;
+        let target = self.get(&root, id)?;
+        let safety = self.create(&root, &format!("before rewind to {}", short(&target.id)))?;
+
+        let written = nul_separated(&self.git.run(
+            &root,
+            ["diff", "--name-only", "-z", "--no-renames", "--diff-filter=DMT", &target.tree, &safety.tree],
+        )?);
+        let removed = nul_separated(&self.git.run(
+            &root,
+            ["diff", "--name-only", "-z", "--no-renames", "--diff-filter=A", &target.tree, &safety.tree],
+        )?);
+
+        // Only the paths that differ are written, so unchanged files keep
+        // their mtimes and builds don't see them as modified.
+        self.with_temp_index(&root, |index| {
+            let env = [("GIT_INDEX_FILE", index)];
+            self.git.run_with_env(&root, &env, ["read-tree", target.tree.as_str()])?;
+            for paths in written.chunks(PATHS_PER_CHECKOUT) {
+                let mut args = vec!["checkout-index", "--force", "--"];
+                args.extend(paths.iter().map(String::as_str));
+                self.git.run_with_env(&root, &env, args)?;
+            }
+            Ok(())
+        })?;
+        for path in &removed {
+            let path = Path::new(&root).join(path);
+            std::fs::remove_file(&path)?;
+            remove_empty_parents(&path, Path::new(&root));
+        }
+
+        Ok(RewindOutcome {
+            restored: target,
+            safety,
+            written,
+            removed,
+        })
+    }
+
+    fn root(&self, cwd: &str) -> CheckpointResult<String> {
+        Ok(self.git.run(cwd, ["rev-parse", "--show-toplevel"])?.trim().to_string())
+    }
+
+    fn resolve_ref(&self, root: &str) -> CheckpointResult<Option<String>> {
+        let output = self.git.run(root, ["for-each-ref", "--format=%(objectname)", CHECKPOINT_REF])?;
+        Ok(output.lines().next().map(str::to_string))
+    }
+
+    fn latest(&self, root: &str) -> CheckpointResult<Option<Checkpoint>> {
+        if self.resolve_ref(root)?.is_none() {
+            return Ok(None);
+        }
+        let output = self.git.run(root, ["log", "--max-count=1", CHECKPOINT_FORMAT, CHECKPOINT_REF, "--"])?;
+        Ok(parse_checkpoints(&output)?.into_iter().next())
+    }
+
+    // Writes the working tree as a tree object via a throwaway index seeded
+    // from the real one (for its stat cache), so `git add` never touches
+    // what the user staged.
+    fn snapshot_tree(&self, root: &str) -> CheckpointResult<String> {
+        let real_index = self.git_path(root, "index")?;
+        self.with_temp_index(root, |index| {
+            if Path::new(&real_index).exists() {
+                std::fs::copy(&real_index, index)?;
+            }
+            let env = [("GIT_INDEX_FILE", index)];
+            self.git.run_with_env(root, &env, ["add", "--all", "--", "."])?;
+            Ok(self.git.run_with_env(root, &env, ["write-tree"])?.trim().to_string())
+        })
+    }
+
+    fn with_temp_index<T>(&self, root: &str, f: impl FnOnce(&str) -> CheckpointResult<T>) -> CheckpointResult<T> {
+        let name = format!(
+            "alfred-checkpoint-{}-{}.index",
+            std::process::id(),
+            NEXT_INDEX.fetch_add(1, Ordering::Relaxed)
+        );
+        let index = self.git_path(root, &name)?;
+        let result = f(&index);
+        let _ = std::fs::remove_file(&index);
+        result
+    }
+
+    fn git_path(&self, root: &str, name: &str) -> CheckpointResult<String> {
+        let path = self.git.run(root, ["rev-parse", "--git-path", name])?.trim().to_string();
+        Ok(Path::new(root).join(path).to_string_lossy().to_string())
+    }
+}
+
+pub fn short(id: &str) -> &str {
+    &id[..id.len().min(10)]
+}
+
+fn parse_checkpoints(output: &str) -> CheckpointResult<Vec<Checkpoint>> {
+    output
+        .split('\x1e')
+        .map(|record| record.trim_start_matches('\n'))
+        .filter(|record| !record.is_empty())
+        .map(|record| {
+            let fields: Vec<&str> = record.splitn(4, '\x1f').collect();
+            let [id, tree, timestamp, prompt] = fields[..] else {
+                return Err(GitError::Parse(record.to_string()).into());
+            };
+            Ok(Checkpoint {
+                id: id.to_string(),
+                tree: tree.to_string(),
+                timestamp: timestamp.parse().map_err(|_| GitError::Parse(record.to_string()))?,
+                prompt: prompt.trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+fn nul_separated(output: &str) -> Vec<String> {
+    output
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == root || std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo() -> (tempfile::TempDir, GitTool, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path().to_string_lossy().to_string();
+        let git = GitTool::default();
+        git.run(&cwd, ["init", "--quiet"]).unwrap();
+        git.run(&cwd, ["config", "user.name", "Test"]).unwrap();
+        git.run(&cwd, ["config", "user.email", "test@example.com"]).unwrap();
+        (dir, git, cwd)
+    }
+
+    fn write(cwd: &str, path: &str, contents: &str) {
+        let path = Path::new(cwd).join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    fn read(cwd: &str, path: &str) -> Option<String> {
+        std::fs::read_to_string(Path::new(cwd).join(path)).ok()
+    }
+
+    #[test]
+    fn test_checkpoints_leave_user_state_alone() {
+        let (_dir, git, cwd) = repo();
+        write(&cwd, "tracked.txt", "v1\n");
+        git.add(&cwd, &["tracked.txt".to_string()]).unwrap();
+        git.commit(&cwd, "initial").unwrap();
+        write(&cwd, ".gitignore", "target/\n");
+        write(&cwd, "target/build.log", "ignored\n");
+        write(&cwd, "tracked.txt", "v2 staged\n");
+        git.add(&cwd, &["tracked.txt".to_string()]).unwrap();
+        write(&cwd, "tracked.txt", "v3 unstaged\n");
+        let before = git.status(&cwd).unwrap();
+
+        let checkpoints = Checkpoints::new(git.clone());
+        let first = checkpoints.create(&cwd, "fix the bug").unwrap();
+        assert_eq!(first.prompt, "fix the bug");
+        // Nothing changed, so no new checkpoint.
+        assert_eq!(checkpoints.create(&cwd, "again").unwrap(), first);
+
+        let after = git.status(&cwd).unwrap();
+        assert_eq!(before.entries, after.entries);
+        assert_eq!(git.log(&cwd, &Default::default()).unwrap().len(), 1);
+        assert!(git.diff(&cwd, &crate::git::DiffTarget::Staged).unwrap().contains("+v2 staged"));
+
+        let files = git.run(&cwd, ["ls-tree", "-r", "--name-only", first.tree.as_str()]).unwrap();
+        assert_eq!(files, ".gitignore\ntracked.txt\n");
+    }
+
+    #[test]
+    fn test_rewind_restores_and_can_be_undone() {
+        let (_dir, git, cwd) = repo();
+        write(&cwd, "keep.txt", "user work\n");
+        write(&cwd, "src/lib.rs", "fn a() {}\n");
+        write(&cwd, "untouched.txt", "same\n");
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        let untouched = Path::new(&cwd).join("untouched.txt");
+        std::fs::File::options().write(true).open(&untouched).unwrap().set_modified(old).unwrap();
+        let checkpoints = Checkpoints::new(git.clone());
+        let before = checkpoints.create(&cwd, "add b").unwrap();
+
+        // The "agent" edits one file, deletes another and creates a third.
+        write(&cwd, "src/lib.rs", "fn a() {}\nfn b() {}\n");
+        std::fs::remove_file(Path::new(&cwd).join("keep.txt")).unwrap();
+        write(&cwd, "src/new/mod.rs", "mod b;\n");
+        let after = checkpoints.create(&cwd, "next").unwrap();
+        assert_eq!(checkpoints.list(&cwd).unwrap(), vec![after.clone(), before.clone()]);
+        assert!(checkpoints.diff(&cwd, &before.id, Some(&after.id)).unwrap().contains("+fn b() {}"));
+        assert!(checkpoints.diff(&cwd, &after.id, None).unwrap().is_empty());
+        assert!(matches!(checkpoints.get(&cwd, "0000"), Err(CheckpointError::Unknown(_))));
+
+        let outcome = checkpoints.rewind(&cwd, short(&before.id)).unwrap();
+        assert_eq!(outcome.safety, after);
+        assert_eq!(outcome.written, vec!["keep.txt", "src/lib.rs"]);
+        assert_eq!(outcome.removed, vec!["src/new/mod.rs"]);
+        assert_eq!(read(&cwd, "src/lib.rs").as_deref(), Some("fn a() {}\n"));
+        assert_eq!(read(&cwd, "keep.txt").as_deref(), Some("user work\n"));
+        assert!(!Path::new(&cwd).join("src/new").exists());
+        // Files the rewind didn't need to write keep their mtime.
+        assert_eq!(std::fs::metadata(&untouched).unwrap().modified().unwrap(), old);
+        // Untracked files stay untracked.
+        assert!(git.run(&cwd, ["ls-files"]).unwrap().is_empty());
+
+        checkpoints.rewind(&cwd, &outcome.safety.id).unwrap();
+        assert_eq!(read(&cwd, "src/new/mod.rs").as_deref(), Some("mod b;\n"));
+        assert!(read(&cwd, "keep.txt").is_none());
+    }
+}
@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use tokio::fs;
@@ -6,9 +7,237 @@ use tokio::fs;
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
     pub openrouter_api_key: Option<String>,
+    #[serde(default)]
+    pub shell: ShellConfig,
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+    #[serde(default)]
+    pub env: EnvConfig,
+    #[serde(default)]
+    pub network: NetworkConfig,
+    #[serde(default)]
+    pub index: IndexConfig,
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    #[serde(default)]
+    pub providers: BTreeMap<String, ProviderProfile>,
+}
+
+// An OpenAI-compatible API (OpenAI, OpenRouter, Ollama, ...), referred to by
+// name from other sections, e.g. `index.embedder`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
+pub struct ProviderProfile {
+    // The API root, e.g. `https://api.openai.com/v1`.
+    pub base_url: String,
+    pub api_key: Option<String>,
+    // Takes precedence over `api_key` when the variable is set.
+    pub api_key_env: Option<String>,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl ProviderProfile {
+    pub fn resolve_api_key(&self) -> Option<String> {
+        self.api_key_env
+            .as_deref()
+            .and_then(|name| std::env::var(name).ok())
+            .filter(|key| !key.is_empty())
+            .or_else(|| self.api_key.clone())
+    }
+}
+
+// The retrieval index built by `alfred index`. Globs are relative to the
+// workspace root and apply on top of `.gitignore`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct IndexConfig {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // Index the workspace when the TUI starts.
+    pub background: bool,
+    // Keep the index current while the TUI runs.
+    pub watch: bool,
+    // `hash` (the default) needs nothing; `local` runs the sentence-embedding
+    // model in `model_path` on the CPU; any other name is a `providers`
+    // profile whose `/embeddings` endpoint serves `embedding_model`.
+    pub embedder: Option<String>,
+    pub model_path: Option<PathBuf>,
+    pub embedding_model: Option<String>,
+    // Requested from remote models that support shortened embeddings.
+    pub dimensions: Option<usize>,
+}
+
+// Outbound HTTP from tools is denied unless the host matches `allow_hosts`:
+// `docs.rs`, `*.example.com` or `localhost:8080`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct NetworkConfig {
+    pub allow_hosts: Vec<String>,
+}
+
+// What `.alfred/tools` templates may read through `${env:NAME}` and
+// `${secret:NAME}`, each with the host patterns its value may be sent to:
+//
+//     [tools.secrets]
+//     TICKETS_TOKEN = ["tickets.internal"]
+//
+// Only honoured in the global config: a cloned repository can't grant its
+// own templates the user's credentials.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ToolsConfig {
+    pub env: BTreeMap<String, Vec<String>>,
+    pub secrets: BTreeMap<String, Vec<String>>,
+}
+
+// Environment for spawned processes, on top of the built-in allow/deny
+// lists in `crate::env`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct EnvConfig {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    // Injected as-is, even if a deny pattern matches the name. Only read
+    // from the global config: `LD_PRELOAD` or `GIT_SSH_COMMAND` from a
+    // cloned repository would run its code.
+    pub set: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShellConfig {
+    // Shell used to run shell strings; the script is passed as the last arg.
+    pub program: String,
+    pub args: Vec<String>,
+    // Command prefixes such as `cargo` or `git push*`. When `allow` is
+    // non-empty, anything not matching it needs confirmation.
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+    pub confirm: Vec<String>,
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        Self {
+            program: "bash".to_string(),
+            args: vec!["-lc".to_string()],
+            allow: Vec::new(),
+            deny: Vec::new(),
+            confirm: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxConfig {
+    // Name of the active profile: `off`, one of the built-ins
+    // (`workspace-write`, `workspace-write-network`) or a key of `profiles`.
+    pub profile: String,
+    pub profiles: BTreeMap<String, SandboxProfile>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            profile: "off".to_string(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+impl SandboxConfig {
+    pub fn active_profile(&self) -> Result<Option<SandboxProfile>> {
+        if let Some(profile) = self.profiles.get(&self.profile) {
+            return Ok(Some(profile.clone()));
+        }
+        match self.profile.as_str() {
+            "off" => Ok(None),
+            "workspace-write" => Ok(Some(SandboxProfile::default())),
+            "workspace-write-network" => Ok(Some(SandboxProfile {
+                network: true,
+                ..SandboxProfile::default()
+            })),
+            other => anyhow::bail!("Unknown sandbox profile `{}`", other),
+        }
+    }
+
+    // Whether this config's active profile confines commands at least as
+    // much as `other`'s. An unknown profile counts as strict, since no
+    // command runs under it.
+    fn is_at_least_as_strict_as(&self, other: &SandboxConfig) -> bool {
+        match (self.active_profile(), other.active_profile()) {
+            (Err(_), _) => true,
+            (_, Err(_)) => false,
+            (_, Ok(None)) => true,
+            (Ok(None), Ok(Some(_))) => false,
+            (Ok(Some(mine)), Ok(Some(theirs))) => mine.is_at_least_as_strict_as(&theirs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SandboxProfile {
+    // Writable in addition to the workspace and temp directories.
+    pub writable_paths: Vec<PathBuf>,
+    pub network: bool,
+    pub seccomp: bool,
+    pub cpu_seconds: Option<u64>,
+    pub memory_mb: Option<u64>,
+    pub max_processes: Option<u64>,
+}
+
+impl SandboxProfile {
+    fn is_at_least_as_strict_as(&self, other: &SandboxProfile) -> bool {
+        let limit = |mine: Option<u64>, theirs: Option<u64>| match (mine, theirs) {
+            (_, None) => true,
+            (Some(mine), Some(theirs)) => mine <= theirs,
+            (None, Some(_)) => false,
+        };
+        (!self.network || other.network)
+            && (self.seccomp || !other.seccomp)
+            && self.writable_paths.iter().all(|path| other.writable_paths.contains(path))
+            && limit(self.cpu_seconds, other.cpu_seconds)
+            && limit(self.memory_mb, other.memory_mb)
+            && limit(self.max_processes, other.max_processes)
+    }
+}
+
+impl Default for SandboxProfile {
+    fn default() -> Self {
+        Self {
+            writable_paths: Vec::new(),
+            network: false,
+            seccomp: true,
+            cpu_seconds: None,
+            memory_mb: None,
+            max_processes: None,
+        }
+    }
 }
 
 impl Config {
+    // A `[providers.<name>]` profile. `openrouter` is always available and
+    // falls back to `openrouter_api_key` and `OPENROUTER_API_KEY`.
+    pub fn provider(&self, name: &str) -> Option<ProviderProfile> {
+        let mut profile = match (self.providers.get(name), name) {
+            (Some(profile), _) => profile.clone(),
+            (None, "openrouter") => ProviderProfile::default(),
+            (None, _) => return None,
+        };
+        if name == "openrouter" {
+            if profile.base_url.is_empty() {
+                profile.base_url = "https://openrouter.ai/api/v1".to_string();
+            }
+            profile.api_key_env.get_or_insert_with(|| "OPENROUTER_API_KEY".to_string());
+            if profile.api_key.is_none() {
+                profile.api_key = self.openrouter_api_key.clone();
+            }
+        }
+        Some(profile)
+    }
+
     pub async fn load() -> Result<Self> {
         let config_path = get_config_path()?;
         if !config_path.exists() {
@@ -25,6 +254,28 @@ impl Config {
         Ok(config)
     }
 
+    // Global config overlaid with `<root>/.alfred/config.toml`. Tables are
+    // merged key by key and each setting combines as `merge_rule` says; the
+    // project file comes with the repository, so it can tighten the user's
+    // rules but not loosen them. Don't `save` the result, it would leak
+    // project settings into the global file.
+    pub async fn load_for_project(root: &Path) -> Result<Self> {
+        let config_path = get_config_path()?;
+        let global = match fs::read_to_string(&config_path).await {
+            Ok(content) => toml::from_str::<toml::Table>(&content)
+                .context("Failed to parse config file")?,
+            Err(_) => toml::Table::new(),
+        };
+
+        let project_path = project_config_path(root);
+        let project = match fs::read_to_string(&project_path).await {
+            Ok(content) => toml::from_str::<toml::Table>(&content)
+                .with_context(|| format!("Failed to parse {}", project_path.display()))?,
+            Err(_) => toml::Table::new(),
+        };
+        overlay(global, project)
+    }
+
     pub async fn save(&self) -> Result<()> {
         let config_path = get_config_path()?;
         if let Some(parent) = config_path.parent() {
@@ -44,6 +295,100 @@ impl Config {
     }
 }
 
+fn overlay(global: toml::Table, project: toml::Table) -> Result<Config> {
+    let mut merged = global.clone();
+    merge_tables(&mut merged, project, "");
+    let mut config: Config = toml::Value::Table(merged)
+        .try_into()
+        .context("Failed to parse merged config")?;
+    let global: Config = toml::Value::Table(global)
+        .try_into()
+        .context("Failed to parse config file")?;
+    // A project may pick a sandbox profile, but not one that lets commands
+    // do more than the user's own choice.
+    if !config.sandbox.is_at_least_as_strict_as(&global.sandbox) {
+        tracing::warn!(
+            "ignoring project sandbox settings: profile `{}` is weaker than `{}`",
+            config.sandbox.profile,
+            global.sandbox.profile
+        );
+        config.sandbox = global.sandbox;
+    }
+    Ok(config)
+}
+
+// How a project value combines with the global one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Merge {
+    Replace,
+    // Rule lists that only add restrictions.
+    Append,
+    // An allowlist where empty means everything: the project's list replaces
+    // an empty global one and otherwise only entries in both are kept.
+    Narrow,
+    // An allowlist where empty means nothing: only entries in both are kept.
+    Intersect,
+    // Decides what runs, so a repository doesn't get a say.
+    GlobalOnly,
+}
+
+fn merge_rule(path: &str) -> Merge {
+    match path {
+        "shell.program" | "shell.args" | "env.set" | "tools" => Merge::GlobalOnly,
+        "shell.allow" => Merge::Narrow,
+        "network.allow_hosts" => Merge::Intersect,
+        "shell.deny" | "shell.confirm" | "env.allow" | "env.deny" | "index.exclude" => Merge::Append,
+        _ => Merge::Replace,
+    }
+}
+
+// Overlays `overlay` on `base`; `path` is the dotted key of `base`.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table, path: &str) {
+    for (key, value) in overlay {
+        let path = match path {
+            "" => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        };
+        match (merge_rule(&path), base.get_mut(&key), value) {
+            (Merge::GlobalOnly, _, _) => {}
+            (_, Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge_tables(base, overlay, &path),
+            // Go through the rules even where the global file has no table.
+            (_, None, toml::Value::Table(overlay)) => {
+                let mut table = toml::Table::new();
+                merge_tables(&mut table, overlay, &path);
+                base.insert(key, toml::Value::Table(table));
+            }
+            (Merge::Append, Some(toml::Value::Array(base)), toml::Value::Array(overlay)) => {
+                for item in overlay {
+                    if !base.contains(&item) {
+                        base.push(item);
+                    }
+                }
+            }
+            (Merge::Narrow, Some(toml::Value::Array(base)), toml::Value::Array(overlay)) if !base.is_empty() => {
+                // Sharing nothing would leave an empty list, which allows
+                // everything; keep the global one instead.
+                let shared: Vec<toml::Value> = base.iter().filter(|item| overlay.contains(item)).cloned().collect();
+                if !shared.is_empty() {
+                    *base = shared;
+                }
+            }
+            (Merge::Intersect, base, toml::Value::Array(overlay)) => {
+                if let Some(toml::Value::Array(base)) = base {
+                    base.retain(|item| overlay.contains(item));
+                }
+            }
+            (_, _, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+pub fn project_config_path(root: &Path) -> PathBuf {
+    root.join(".alfred").join("config.toml")
+}
+
 fn get_config_path() -> Result<PathBuf> {
     let home = dirs::home_dir().context("Could not determine home directory")?;
     Ok(home.join(".config").join("alfred").join("config.toml"))
@@ -54,6 +399,17 @@ pub fn get_prompts_dir() -> Result<PathBuf> {
     Ok(home.join(".config").join("alfred").join("prompts"))
 }
 
+// Values for `${secret:NAME}` in tool templates. Only the user's own
+// `~/.config/alfred/secrets.toml` is read, never a project file.
+pub async fn load_secrets() -> Result<BTreeMap<String, String>> {
+    let home = dirs::home_dir().context("Could not determine home directory")?;
+    let path = home.join(".config").join("alfred").join("secrets.toml");
+    match fs::read_to_string(&path).await {
+        Ok(content) => toml::from_str(&content).with_context(|| format!("Failed to parse {}", path.display())),
+        Err(_) => Ok(BTreeMap::new()),
+    }
+}
+
 pub async fn load_system_prompt() -> Option<String> {
     if let Ok(prompts_dir) = get_prompts_dir() {
         let soul_path = prompts_dir.join("SOUL.md");
@@ -63,3 +419,157 @@ pub async fn load_system_prompt() -> Option<String> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_tables_overlays_project_settings() {
+        let mut global: toml::Table = toml::from_str(
+            r#"
+            openrouter_api_key = "key"
+            [shell]
+            deny = ["git push*"]
+            [sandbox.profiles.strict]
+            network = false
+            "#,
+        )
+        .unwrap();
+        let project: toml::Table = toml::from_str(
+            r#"
+            [shell]
+            deny = ["rm -rf*"]
+            args = ["-c"]
+            [sandbox]
+            profile = "strict"
+            [sandbox.profiles.strict]
+            cpu_seconds = 30
+            "#,
+        )
+        .unwrap();
+        merge_tables(&mut global, project, "");
+
+        let config: Config = toml::Value::Table(global).try_into().unwrap();
+        assert_eq!(config.openrouter_api_key.as_deref(), Some("key"));
+        assert_eq!(config.shell.deny, vec!["git push*", "rm -rf*"]);
+        assert_eq!(config.shell.args, vec!["-lc"]);
+        let profile = config.sandbox.active_profile().unwrap().unwrap();
+        assert_eq!(profile.cpu_seconds, Some(30));
+        assert!(!profile.network);
+    }
+
+    fn merged(global: &str, project: &str) -> Config {
+        overlay(toml::from_str(global).unwrap(), toml::from_str(project).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_project_cannot_widen_shell_rules() {
+        let project = r#"
+            [shell]
+            allow = ["cargo", "*"]
+            program = "./scripts/shell.sh"
+            args = []
+            "#;
+        let config = merged("[shell]\nallow = [\"cargo\", \"git status\"]", project);
+        assert_eq!(config.shell.allow, vec!["cargo"]);
+        assert_eq!(config.shell.program, "bash");
+        assert_eq!(config.shell.args, vec!["-lc"]);
+
+        // An empty global list allows everything, so any project list narrows it.
+        assert_eq!(merged("", project).shell.allow, vec!["cargo", "*"]);
+        assert_eq!(merged("", project).shell.program, "bash");
+        let config = merged("[shell]\nallow = [\"cargo\"]", "[shell]\nallow = [\"npm\"]");
+        assert_eq!(config.shell.allow, vec!["cargo"]);
+    }
+
+    #[test]
+    fn test_project_env_only_adds_restrictions() {
+        let global = r#"
+            [env]
+            deny = ["NPM_*"]
+            set = { CI = "1" }
+            "#;
+        let project = r#"
+            [env]
+            allow = ["NODE_*", "NPM_CONFIG_REGISTRY"]
+            deny = ["NODE_AUTH"]
+            set = { LD_PRELOAD = "./x.so", CI = "0" }
+            "#;
+        let config = merged(global, project);
+        assert_eq!(config.env.set, BTreeMap::from([("CI".to_string(), "1".to_string())]));
+        assert_eq!(config.env.deny, vec!["NPM_*", "NODE_AUTH"]);
+        let policy = crate::EnvPolicy::from_config(&config.env).unwrap();
+        assert!(policy.is_allowed("NODE_ENV"));
+        // Deny patterns still apply to names the project allows.
+        assert!(!policy.is_allowed("NPM_CONFIG_REGISTRY"));
+        assert!(!policy.is_allowed("NODE_AUTH"));
+        assert!(merged("", project).env.set.is_empty());
+    }
+
+    #[test]
+    fn test_project_cannot_loosen_sandbox() {
+        let global = r#"
+            [sandbox]
+            profile = "strict"
+            [sandbox.profiles.strict]
+            writable_paths = ["/var/cache/build"]
+            cpu_seconds = 60
+            [network]
+            allow_hosts = ["docs.rs", "crates.io"]
+            "#;
+        let strict = |config: &Config| config.sandbox.active_profile().unwrap().unwrap();
+        assert_eq!(merged(global, "[sandbox]\nprofile = \"off\"").sandbox.profile, "strict");
+        let config = merged(global, "[sandbox.profiles.strict]\nnetwork = true");
+        assert!(!strict(&config).network);
+        let config = merged(global, "[sandbox.profiles.strict]\nwritable_paths = [\"/\"]");
+        assert_eq!(strict(&config).writable_paths, vec![PathBuf::from("/var/cache/build")]);
+        let config = merged(global, "[sandbox.profiles.strict]\ncpu_seconds = 600");
+        assert_eq!(strict(&config).cpu_seconds, Some(60));
+
+        // Tightening is fine.
+        let config = merged(global, "[sandbox.profiles.strict]\ncpu_seconds = 10\nwritable_paths = []");
+        assert_eq!(strict(&config).cpu_seconds, Some(10));
+        assert!(strict(&config).writable_paths.is_empty());
+        let config = merged("", "[sandbox]\nprofile = \"workspace-write\"");
+        assert!(config.sandbox.active_profile().unwrap().is_some());
+
+        let config = merged(global, "[network]\nallow_hosts = [\"docs.rs\", \"attacker.example\"]");
+        assert_eq!(config.network.allow_hosts, vec!["docs.rs"]);
+        assert!(merged("", "[network]\nallow_hosts = [\"attacker.example\"]").network.allow_hosts.is_empty());
+    }
+
+    #[test]
+    fn test_provider_profiles() {
+        let config: Config = toml::from_str(
+            r#"
+            openrouter_api_key = "or-key"
+            [providers.ollama]
+            base_url = "http://localhost:11434/v1"
+            [providers.openai]
+            base_url = "https://api.openai.com/v1"
+            api_key = "fallback"
+            api_key_env = "ALFRED_TEST_UNSET_KEY"
+            "#,
+        )
+        .unwrap();
+        let ollama = config.provider("ollama").unwrap();
+        assert_eq!(ollama.base_url, "http://localhost:11434/v1");
+        assert_eq!(ollama.resolve_api_key(), None);
+        assert_eq!(config.provider("openai").unwrap().resolve_api_key().as_deref(), Some("fallback"));
+        let openrouter = config.provider("openrouter").unwrap();
+        assert_eq!(openrouter.base_url, "https://openrouter.ai/api/v1");
+        assert_eq!(openrouter.api_key.as_deref(), Some("or-key"));
+        assert!(config.provider("missing").is_none());
+    }
+
+    #[test]
+    fn test_builtin_sandbox_profiles() {
+        let mut sandbox = SandboxConfig::default();
+        assert!(sandbox.active_profile().unwrap().is_none());
+        sandbox.profile = "workspace-write-network".to_string();
+        assert!(sandbox.active_profile().unwrap().unwrap().network);
+        sandbox.profile = "nope".to_string();
+        assert!(sandbox.active_profile().is_err());
+    }
+}
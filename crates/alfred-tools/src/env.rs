@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::config::EnvConfig;
+
+// Enough for shells, compilers and package managers to find their way.
+pub const DEFAULT_ALLOW: &[&str] = &[
+    "PATH", "HOME", "USER", "LOGNAME", "SHELL", "PWD", "LANG", "LANGUAGE", "LC_*", "TERM",
+    "COLORTERM", "NO_COLOR", "TZ", "TMPDIR", "EDITOR", "VISUAL", "GIT_EDITOR", "PAGER", "XDG_*",
+    "CARGO_HOME", "RUSTUP_HOME", "RUSTUP_TOOLCHAIN", "GOPATH", "GOROOT", "JAVA_HOME", "NVM_DIR",
+    "PYENV_ROOT", "VIRTUAL_ENV", "GIT_AUTHOR_*", "GIT_COMMITTER_*", "SSH_AUTH_SOCK",
+];
+
+// Deny wins over allow, so `XDG_*` can't smuggle in `XDG_SECRET_TOKEN`.
+pub const DEFAULT_DENY: &[&str] = &[
+    "*_KEY", "*_KEY_*", "*_TOKEN", "*_TOKEN_*", "*_SECRET", "*_SECRET_*", "*PASSWORD*",
+    "*CREDENTIALS*", "AWS_*", "AZURE_*", "GOOGLE_APPLICATION_CREDENTIALS", "OPENROUTER_*",
+];
+
+#[derive(Debug, Clone)]
+pub struct EnvPolicy {
+    allow: GlobSet,
+    deny: GlobSet,
+    set: BTreeMap<String, String>,
+}
+
+impl Default for EnvPolicy {
+    fn default() -> Self {
+        Self::from_config(&EnvConfig::default()).expect("default env patterns are valid")
+    }
+}
+
+impl EnvPolicy {
+    pub fn from_config(config: &EnvConfig) -> anyhow::Result<Self> {
+        let allow = DEFAULT_ALLOW.iter().copied().chain(config.allow.iter().map(String::as_str));
+        let deny = DEFAULT_DENY.iter().copied().chain(config.deny.iter().map(String::as_str));
+        Ok(Self {
+            allow: build_set(allow)?,
+            deny: build_set(deny)?,
+            set: config.set.clone(),
+        })
+    }
+
+    pub fn is_allowed(&self, name: &str) -> bool {
+        self.allow.is_match(name) && !self.deny.is_match(name)
+    }
+
+    // The environment a spawned process should see: allowed variables from
+    // `parent` plus the configured injections, which bypass the deny list.
+    pub fn effective<I>(&self, parent: I) -> BTreeMap<String, String>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut env: BTreeMap<String, String> = parent
+            .into_iter()
+            .filter(|(name, _)| self.is_allowed(name))
+            .collect();
+        env.extend(self.set.clone());
+        env
+    }
+
+    pub fn effective_from_process(&self) -> BTreeMap<String, String> {
+        self.effective(std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }))
+    }
+}
+
+fn build_set<'a>(patterns: impl Iterator<Item = &'a str>) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern)?);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_default_policy_strips_secrets() {
+        let policy = EnvPolicy::default();
+        let env = policy.effective(vars(&[
+            ("PATH", "/usr/bin"),
+            ("LC_ALL", "C"),
+            ("SSH_AUTH_SOCK", "/run/agent.sock"),
+            ("OPENROUTER_API_KEY", "sk-or"),
+            ("AWS_PROFILE", "prod"),
+            ("XDG_SESSION_TOKEN", "t"),
+            ("RANDOM_THING", "x"),
+        ]));
+        assert_eq!(env.keys().collect::<Vec<_>>(), vec!["LC_ALL", "PATH", "SSH_AUTH_SOCK"]);
+    }
+
+    #[test]
+    fn test_config_additions_and_injections() {
+        let mut config = EnvConfig::default();
+        config.allow.push("NODE_*".to_string());
+        config.deny.push("NODE_AUTH".to_string());
+        config.set.insert("CI".to_string(), "1".to_string());
+        config.set.insert("DEPLOY_TOKEN".to_string(), "explicit".to_string());
+        let policy = EnvPolicy::from_config(&config).unwrap();
+
+        let env = policy.effective(vars(&[("NODE_ENV", "test"), ("NODE_AUTH", "x")]));
+        assert_eq!(env.get("NODE_ENV").map(String::as_str), Some("test"));
+        assert!(!env.contains_key("NODE_AUTH"));
+        assert_eq!(env.get("CI").map(String::as_str), Some("1"));
+        assert_eq!(env.get("DEPLOY_TOKEN").map(String::as_str), Some("explicit"));
+    }
+}
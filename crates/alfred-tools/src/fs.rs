@@ -1,12 +1,60 @@
 use std::fs;
+use std::time::UNIX_EPOCH;
 
 use camino::{Utf8Path, Utf8PathBuf};
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::WalkBuilder;
 use serde::{Deserialize, Serialize};
 
+pub const ALFRED_IGNORE_FILE: &str = ".alfredignore";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileEntry {
     pub path: Utf8PathBuf,
     pub is_dir: bool,
+    pub is_symlink: bool,
+    pub size: u64,
+    // Seconds since the Unix epoch, if the platform reports it.
+    pub modified: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    #[default]
+    Name,
+    Modified,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListOptions {
+    // Depth 1 lists only the direct children of the root; `None` is unlimited.
+    pub max_depth: Option<usize>,
+    pub glob: Option<String>,
+    pub respect_ignore: bool,
+    pub include_hidden: bool,
+    pub sort: SortBy,
+    pub limit: usize,
+}
+
+impl Default for ListOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: Some(1),
+            glob: None,
+            respect_ignore: true,
+            include_hidden: true,
+            sort: SortBy::Name,
+            limit: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileListing {
+    pub entries: Vec<FileEntry>,
+    pub truncated: bool,
 }
 
 #[derive(Debug, Default)]
@@ -25,18 +73,185 @@ impl FsTool {
         Ok(())
     }
 
-    pub fn list(&self, path: &Utf8Path) -> anyhow::Result<Vec<FileEntry>> {
+    pub fn list(&self, path: &Utf8Path, options: &ListOptions) -> anyhow::Result<FileListing> {
+        let matcher = options
+            .glob
+            .as_deref()
+            .map(compile_glob)
+            .transpose()?;
+
+        let mut walker = workspace_walker(path, options.respect_ignore, options.include_hidden);
+        walker.max_depth(options.max_depth);
+        if options.sort == SortBy::Name {
+            walker.sort_by_file_name(|a, b| a.cmp(b));
+        }
+
         let mut entries = Vec::new();
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let file_type = entry.file_type()?;
-            let path = Utf8PathBuf::from_path_buf(entry.path())
+        let mut truncated = false;
+        for entry in walker.build() {
+            // One unreadable directory shouldn't hide the rest of the tree.
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    tracing::debug!("skipping unreadable entry: {}", err);
+                    continue;
+                }
+            };
+            if entry.depth() == 0 {
+                continue;
+            }
+            let entry_path = Utf8PathBuf::from_path_buf(entry.path().to_path_buf())
                 .map_err(|_| anyhow::anyhow!("Non-utf8 path"))?;
+            if let Some(matcher) = &matcher {
+                let relative = entry_path.strip_prefix(path).unwrap_or(&entry_path);
+                if !matcher.is_match(relative) {
+                    continue;
+                }
+            }
+
+            // Name order falls out of the sorted walk, so we can stop early.
+            // Modification order needs every entry before it can be cut.
+            if options.sort == SortBy::Name && entries.len() == options.limit {
+                truncated = true;
+                break;
+            }
+
+            let is_symlink = entry.path_is_symlink();
+            let metadata = fs::metadata(entry.path()).or_else(|_| fs::symlink_metadata(entry.path()))?;
             entries.push(FileEntry {
-                path,
-                is_dir: file_type.is_dir(),
+                path: entry_path,
+                is_dir: metadata.is_dir(),
+                is_symlink,
+                size: metadata.len(),
+                modified: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map(|duration| duration.as_secs()),
             });
         }
-        Ok(entries)
+
+        if options.sort == SortBy::Modified {
+            entries.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.path.cmp(&b.path)));
+            if entries.len() > options.limit {
+                entries.truncate(options.limit);
+                truncated = true;
+            }
+        }
+
+        Ok(FileListing { entries, truncated })
+    }
+
+    pub fn glob(&self, root: &Utf8Path, pattern: &str) -> anyhow::Result<FileListing> {
+        self.list(
+            root,
+            &ListOptions {
+                max_depth: None,
+                glob: Some(pattern.to_string()),
+                ..ListOptions::default()
+            },
+        )
+    }
+}
+
+pub(crate) fn compile_glob(pattern: &str) -> anyhow::Result<GlobMatcher> {
+    Ok(GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()?
+        .compile_matcher())
+}
+
+// Walks `root` honoring `.gitignore`, `.ignore` and `.alfredignore`, and never
+// descends into `.git` even when hidden files are included.
+pub(crate) fn workspace_walker(root: &Utf8Path, respect_ignore: bool, include_hidden: bool) -> WalkBuilder {
+    let mut walker = WalkBuilder::new(root);
+    walker
+        .hidden(!include_hidden)
+        .ignore(respect_ignore)
+        .git_ignore(respect_ignore)
+        .git_global(respect_ignore)
+        .git_exclude(respect_ignore)
+        .parents(respect_ignore)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != ".git");
+    if respect_ignore {
+        walker.add_custom_ignore_filename(ALFRED_IGNORE_FILE);
+    }
+    walker
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(root.join(ALFRED_IGNORE_FILE), "secret.txt\n").unwrap();
+        fs::write(root.join("secret.txt"), "hidden").unwrap();
+        fs::write(root.join("README.md"), "# readme").unwrap();
+        fs::write(root.join("src/lib.rs"), "pub mod nested;").unwrap();
+        fs::write(root.join("src/nested/mod.rs"), "").unwrap();
+        fs::write(root.join("target/debug/out.rs"), "").unwrap();
+        fs::write(root.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+        (dir, root)
+    }
+
+    fn relative(listing: &FileListing, root: &Utf8Path) -> Vec<String> {
+        listing
+            .entries
+            .iter()
+            .map(|entry| entry.path.strip_prefix(root).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_list_skips_ignored_and_git() {
+        let (_dir, root) = fixture();
+        let listing = FsTool.list(&root, &ListOptions::default()).unwrap();
+        assert_eq!(
+            relative(&listing, &root),
+            vec![".alfredignore", ".gitignore", "README.md", "src"]
+        );
+        assert!(!listing.truncated);
+    }
+
+    #[test]
+    fn test_list_recursive_with_limit() {
+        let (_dir, root) = fixture();
+        let options = ListOptions {
+            max_depth: None,
+            limit: 5,
+            ..ListOptions::default()
+        };
+        let listing = FsTool.list(&root, &options).unwrap();
+        assert_eq!(listing.entries.len(), 5);
+        assert!(listing.truncated);
+        assert_eq!(relative(&listing, &root)[4], "src/lib.rs");
+    }
+
+    #[test]
+    fn test_glob_matches_recursively() {
+        let (_dir, root) = fixture();
+        let listing = FsTool.glob(&root, "**/*.rs").unwrap();
+        assert_eq!(relative(&listing, &root), vec!["src/lib.rs", "src/nested/mod.rs"]);
+
+        let top_level = FsTool.glob(&root, "*.md").unwrap();
+        assert_eq!(relative(&top_level, &root), vec!["README.md"]);
+    }
+
+    #[test]
+    fn test_entry_metadata() {
+        let (_dir, root) = fixture();
+        let listing = FsTool.glob(&root, "README.md").unwrap();
+        let entry = &listing.entries[0];
+        assert_eq!(entry.size, 8);
+        assert!(!entry.is_dir);
+        assert!(!entry.is_symlink);
+        assert!(entry.modified.is_some());
     }
 }
@@ -1,35 +1,714 @@
 use std::process::Command;
 
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::env::EnvPolicy;
+
+#[derive(Debug, Error)]
+pub enum GitError {
+    #[error("`{0}` is not inside a git repository")]
+    NotARepository(String),
+    #[error("`git {command}` failed with status {status}: {stderr}")]
+    Failed {
+        command: String,
+        status: i32,
+        stderr: String,
+    },
+    #[error("failed to run git: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unexpected git output: {0}")]
+    Parse(String),
+    #[error("`{0}` is not a revision")]
+    InvalidRev(String),
+}
+
+pub type GitResult<T> = Result<T, GitError>;
+
+// The state of one side (index or worktree) of a porcelain v2 `XY` pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChange {
+    Unmodified,
+    Modified,
+    TypeChanged,
+    Added,
+    Deleted,
+    Renamed,
+    Copied,
+    Unmerged,
+}
+
+impl FileChange {
+    fn from_code(code: char) -> Self {
+        match code {
+            'M' => FileChange::Modified,
+            'T' => FileChange::TypeChanged,
+            'A' => FileChange::Added,
+            'D' => FileChange::Deleted,
+            'R' => FileChange::Renamed,
+            'C' => FileChange::Copied,
+            'U' => FileChange::Unmerged,
+            _ => FileChange::Unmodified,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Tracked,
+    Renamed,
+    Unmerged,
+    Untracked,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusEntry {
+    pub path: String,
+    // Source path of a rename or copy.
+    pub orig_path: Option<String>,
+    pub kind: EntryKind,
+    pub staged: FileChange,
+    pub unstaged: FileChange,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct GitWorkspaceStatus {
     pub clean: bool,
+    // `None` on a detached HEAD.
+    pub branch: Option<String>,
+    // `None` before the first commit.
+    pub head: Option<String>,
+    pub upstream: Option<String>,
+    pub ahead: u32,
+    pub behind: u32,
+    pub entries: Vec<StatusEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Commit {
+    pub hash: String,
+    pub parents: Vec<String>,
+    pub author_name: String,
+    pub author_email: String,
+    // Seconds since the Unix epoch.
+    pub timestamp: i64,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStat {
+    pub path: String,
+    // `None` for binary files.
+    pub additions: Option<u32>,
+    pub deletions: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitDetails {
+    pub commit: Commit,
+    pub files: Vec<FileStat>,
+    pub patch: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlameLine {
+    pub line: usize,
+    pub commit: String,
+    pub author: String,
+    pub timestamp: i64,
     pub summary: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Branch {
+    pub name: String,
+    pub current: bool,
+    pub head: String,
+    pub upstream: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogOptions {
+    // A revision or range such as `main..HEAD`; defaults to `HEAD`.
+    pub range: Option<String>,
+    pub paths: Vec<String>,
+    pub max_count: usize,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        Self {
+            range: None,
+            paths: Vec::new(),
+            max_count: 50,
+        }
+    }
 }
 
-#[derive(Debug, Default)]
-pub struct GitTool;
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "target", content = "rev", rename_all = "snake_case")]
+pub enum DiffTarget {
+    // Worktree against the index.
+    #[default]
+    Unstaged,
+    // Index against `HEAD`.
+    Staged,
+    // Worktree against a revision.
+    Rev(String),
+    // Between two revisions, e.g. `main...HEAD`.
+    Range(String),
+}
+
+// Field and record separators for `--format`; they can't appear in names or
+// commit messages in practice.
+const FIELD_SEP: char = '\x1f';
+const RECORD_SEP: char = '\x1e';
+const LOG_FORMAT: &str = "--format=%H%x1f%P%x1f%an%x1f%ae%x1f%at%x1f%s%x1f%b%x1e";
+
+#[derive(Debug, Clone, Default)]
+pub struct GitTool {
+    env: EnvPolicy,
+}
 
 impl GitTool {
-    pub fn status(&self, cwd: &str) -> anyhow::Result<GitWorkspaceStatus> {
-        let output = Command::new("git")
-            .arg("status")
-            .arg("--porcelain")
+    pub fn new(env: EnvPolicy) -> Self {
+        Self { env }
+    }
+
+    fn git(&self) -> Command {
+        let mut cmd = Command::new("git");
+        cmd.env_clear().envs(self.env.effective_from_process());
+        // Stable messages to match on, and never block on a credential prompt.
+        cmd.env("LC_ALL", "C").env("GIT_TERMINAL_PROMPT", "0");
+        cmd
+    }
+
+    pub(crate) fn run<I, S>(&self, cwd: &str, args: I) -> GitResult<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.run_with_env(cwd, &[], args)
+    }
+
+    // Like `run`, with extra variables such as `GIT_INDEX_FILE`.
+    pub(crate) fn run_with_env<I, S>(&self, cwd: &str, env: &[(&str, &str)], args: I) -> GitResult<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let args: Vec<_> = args.into_iter().map(|arg| arg.as_ref().to_os_string()).collect();
+        let output = self
+            .git()
+            .envs(env.iter().copied())
+            .args(&args)
             .current_dir(cwd)
             .output()?;
-        let summary = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        Ok(GitWorkspaceStatus {
-            clean: summary.is_empty(),
-            summary,
+        if output.status.success() {
+            return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if stderr.contains("not a git repository") {
+            return Err(GitError::NotARepository(cwd.to_string()));
+        }
+        Err(GitError::Failed {
+            command: args
+                .iter()
+                .map(|arg| arg.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            status: output.status.code().unwrap_or(-1),
+            stderr,
         })
     }
 
-    pub fn diff(&self, cwd: &str) -> anyhow::Result<String> {
-        let output = Command::new("git")
-            .arg("diff")
+    pub fn status(&self, cwd: &str) -> GitResult<GitWorkspaceStatus> {
+        let output = self.run(
+            cwd,
+            ["status", "--porcelain=v2", "--branch", "-z", "--untracked-files=all"],
+        )?;
+        parse_status(&output)
+    }
+
+    pub fn diff(&self, cwd: &str, target: &DiffTarget) -> GitResult<String> {
+        match target {
+            DiffTarget::Unstaged => self.run(cwd, ["diff"]),
+            DiffTarget::Staged => self.run(cwd, ["diff", "--cached"]),
+            DiffTarget::Rev(rev) | DiffTarget::Range(rev) => self.run(cwd, ["diff", check_rev(rev)?, "--"]),
+        }
+    }
+
+    pub fn log(&self, cwd: &str, options: &LogOptions) -> GitResult<Vec<Commit>> {
+        let mut args = vec![
+            "log".to_string(),
+            LOG_FORMAT.to_string(),
+            format!("--max-count={}", options.max_count),
+        ];
+        if let Some(range) = &options.range {
+            args.push(check_rev(range)?.to_string());
+        }
+        args.push("--".to_string());
+        args.extend(options.paths.iter().cloned());
+        match self.run(cwd, &args) {
+            Ok(output) => parse_log(&output),
+            // `git log` on an unborn branch is an empty history, not an error.
+            Err(GitError::Failed { stderr, .. })
+                if options.range.is_none() && stderr.contains("does not have any commits") =>
+            {
+                Ok(Vec::new())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn show(&self, cwd: &str, rev: &str) -> GitResult<CommitDetails> {
+        let rev = check_rev(rev)?;
+        let commit = parse_log(&self.run(cwd, ["show", "--no-patch", LOG_FORMAT, rev])?)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| GitError::Parse(format!("no commit for `{}`", rev)))?;
+        let numstat = self.run(cwd, ["show", "--format=", "--numstat", "-z", rev])?;
+        let patch = self.run(cwd, ["show", "--format=", "--patch", rev])?;
+        Ok(CommitDetails {
+            commit,
+            files: parse_numstat(&numstat),
+            patch,
+        })
+    }
+
+    // Lines `start..=end` (1-based) of `path` as of `rev`, or the worktree.
+    pub fn blame(&self, cwd: &str, path: &str, start: usize, end: usize, rev: Option<&str>) -> GitResult<Vec<BlameLine>> {
+        let range = format!("-L{},{}", start, end);
+        let mut args = vec!["blame", "--line-porcelain", range.as_str()];
+        args.extend(rev.map(check_rev).transpose()?);
+        args.extend(["--", path]);
+        parse_blame(&self.run(cwd, args)?)
+    }
+
+    pub fn branches(&self, cwd: &str) -> GitResult<Vec<Branch>> {
+        let output = self.run(
+            cwd,
+            [
+                "for-each-ref",
+                "--format=%(refname:short)%1f%(HEAD)%1f%(objectname)%1f%(upstream:short)",
+                "refs/heads",
+            ],
+        )?;
+        output
+            .lines()
+            .map(|line| {
+                let fields: Vec<&str> = line.split(FIELD_SEP).collect();
+                let [name, head_marker, head, upstream] = fields[..] else {
+                    return Err(GitError::Parse(line.to_string()));
+                };
+                Ok(Branch {
+                    name: name.to_string(),
+                    current: head_marker == "*",
+                    head: head.to_string(),
+                    upstream: (!upstream.is_empty()).then(|| upstream.to_string()),
+                })
+            })
+            .collect()
+    }
+
+    pub fn create_branch(&self, cwd: &str, name: &str, start: Option<&str>) -> GitResult<()> {
+        let mut args = vec!["branch", "--", name];
+        args.extend(start);
+        self.run(cwd, args)?;
+        Ok(())
+    }
+
+    pub fn switch(&self, cwd: &str, name: &str, create: bool) -> GitResult<()> {
+        let flag = if create { "--create" } else { "--no-guess" };
+        self.run(cwd, ["switch", flag, check_rev(name)?])?;
+        Ok(())
+    }
+
+    pub fn add(&self, cwd: &str, paths: &[String]) -> GitResult<()> {
+        let mut args = vec!["add".to_string(), "--".to_string()];
+        args.extend(paths.iter().cloned());
+        self.run(cwd, args)?;
+        Ok(())
+    }
+
+    // Unstages `paths` when `staged`, otherwise discards their worktree changes.
+    pub fn restore(&self, cwd: &str, paths: &[String], staged: bool) -> GitResult<()> {
+        let mut args = vec!["restore".to_string()];
+        if staged {
+            args.push("--staged".to_string());
+        }
+        args.push("--".to_string());
+        args.extend(paths.iter().cloned());
+        self.run(cwd, args)?;
+        Ok(())
+    }
+
+    // Commits what is staged and returns the new commit.
+    pub fn commit(&self, cwd: &str, message: &str) -> GitResult<Commit> {
+        self.run(cwd, ["commit", "--quiet", "--message", message])?;
+        self.head_commit(cwd)
+    }
+
+    // Like `commit`, but opens the user's editor on `message` first. Runs on
+    // the caller's terminal.
+    pub fn commit_with_editor(&self, cwd: &str, message: &str) -> GitResult<Commit> {
+        let status = self
+            .git()
+            .args(["commit", "--quiet", "--edit", "--message", message])
             .current_dir(cwd)
-            .output()?;
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            .status()?;
+        if !status.success() {
+            return Err(GitError::Failed {
+                command: "commit --edit".to_string(),
+                status: status.code().unwrap_or(-1),
+                stderr: String::new(),
+            });
+        }
+        self.head_commit(cwd)
+    }
+
+    fn head_commit(&self, cwd: &str) -> GitResult<Commit> {
+        parse_log(&self.run(cwd, ["log", "--max-count=1", LOG_FORMAT, "HEAD"])?)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| GitError::Parse("no commit at HEAD".to_string()))
+    }
+}
+
+// Revisions come from the model, and they go before `--`, where
+// `--output=<file>` would be taken as an option.
+fn check_rev(rev: &str) -> GitResult<&str> {
+    match rev.is_empty() || rev.starts_with('-') {
+        true => Err(GitError::InvalidRev(rev.to_string())),
+        false => Ok(rev),
+    }
+}
+
+fn parse_status(output: &str) -> GitResult<GitWorkspaceStatus> {
+    let mut status = GitWorkspaceStatus::default();
+    let mut records = output.split('\0').filter(|record| !record.is_empty());
+    while let Some(record) = records.next() {
+        let malformed = || GitError::Parse(record.to_string());
+        if let Some(header) = record.strip_prefix("# ") {
+            let (key, value) = header.split_once(' ').ok_or_else(malformed)?;
+            match key {
+                "branch.oid" if value != "(initial)" => status.head = Some(value.to_string()),
+                "branch.head" if value != "(detached)" => status.branch = Some(value.to_string()),
+                "branch.upstream" => status.upstream = Some(value.to_string()),
+                "branch.ab" => {
+                    let (ahead, behind) = value.split_once(' ').ok_or_else(malformed)?;
+                    status.ahead = ahead.trim_start_matches('+').parse().map_err(|_| malformed())?;
+                    status.behind = behind.trim_start_matches('-').parse().map_err(|_| malformed())?;
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let (tag, rest) = record.split_once(' ').ok_or_else(malformed)?;
+        // Field counts before the path for each record type.
+        let (kind, fields) = match tag {
+            "1" => (EntryKind::Tracked, 7),
+            "2" => (EntryKind::Renamed, 8),
+            "u" => (EntryKind::Unmerged, 9),
+            "?" => (EntryKind::Untracked, 0),
+            "!" => continue,
+            _ => return Err(malformed()),
+        };
+        let parts: Vec<&str> = rest.splitn(fields + 1, ' ').collect();
+        let path = parts.get(fields).ok_or_else(malformed)?.to_string();
+        let (staged, unstaged) = match kind {
+            EntryKind::Untracked => (FileChange::Unmodified, FileChange::Added),
+            _ => {
+                let mut xy = parts[0].chars();
+                let x = xy.next().ok_or_else(malformed)?;
+                let y = xy.next().ok_or_else(malformed)?;
+                (FileChange::from_code(x), FileChange::from_code(y))
+            }
+        };
+        let orig_path = match kind {
+            EntryKind::Renamed => Some(records.next().ok_or_else(malformed)?.to_string()),
+            _ => None,
+        };
+        status.entries.push(StatusEntry {
+            path,
+            orig_path,
+            kind,
+            staged,
+            unstaged,
+        });
+    }
+    status.clean = status.entries.is_empty();
+    Ok(status)
+}
+
+fn parse_log(output: &str) -> GitResult<Vec<Commit>> {
+    output
+        .split(RECORD_SEP)
+        .map(|record| record.trim_start_matches('\n'))
+        .filter(|record| !record.is_empty())
+        .map(|record| {
+            let fields: Vec<&str> = record.splitn(7, FIELD_SEP).collect();
+            let [hash, parents, author_name, author_email, timestamp, subject, body] = fields[..] else {
+                return Err(GitError::Parse(record.to_string()));
+            };
+            Ok(Commit {
+                hash: hash.to_string(),
+                parents: parents.split_whitespace().map(str::to_string).collect(),
+                author_name: author_name.to_string(),
+                author_email: author_email.to_string(),
+                timestamp: timestamp.parse().map_err(|_| GitError::Parse(record.to_string()))?,
+                subject: subject.to_string(),
+                body: body.trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+// `--numstat -z`: `added\tdeleted\tpath\0`, or for renames
+// `added\tdeleted\t\0from\0to\0`.
+fn parse_numstat(output: &str) -> Vec<FileStat> {
+    let mut stats = Vec::new();
+    let mut records = output.split('\0').map(|record| record.trim_start_matches('\n'));
+    while let Some(record) = records.next() {
+        let mut fields = record.splitn(3, '\t');
+        let (Some(additions), Some(deletions), Some(path)) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let path = if path.is_empty() {
+            let _from = records.next();
+            records.next().unwrap_or_default().to_string()
+        } else {
+            path.to_string()
+        };
+        stats.push(FileStat {
+            path,
+            additions: additions.parse().ok(),
+            deletions: deletions.parse().ok(),
+        });
+    }
+    stats
+}
+
+fn parse_blame(output: &str) -> GitResult<Vec<BlameLine>> {
+    let mut lines = Vec::new();
+    let mut current: Option<BlameLine> = None;
+    for line in output.lines() {
+        if let Some(content) = line.strip_prefix('\t') {
+            let mut entry = current.take().ok_or_else(|| GitError::Parse(line.to_string()))?;
+            entry.content = content.to_string();
+            lines.push(entry);
+            continue;
+        }
+        let Some(entry) = current.as_mut() else {
+            // `<sha> <orig line> <final line> [<group size>]` starts an entry.
+            let mut fields = line.split(' ');
+            let commit = fields.next().unwrap_or_default().to_string();
+            let line_no = fields.nth(1).and_then(|n| n.parse().ok());
+            let line_no = line_no.ok_or_else(|| GitError::Parse(line.to_string()))?;
+            current = Some(BlameLine {
+                line: line_no,
+                commit,
+                author: String::new(),
+                timestamp: 0,
+                summary: String::new(),
+                content: String::new(),
+            });
+            continue;
+        };
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        match key {
+            "author" => entry.author = value.to_string(),
+            "author-time" => entry.timestamp = value.parse().unwrap_or_default(),
+            "summary" => entry.summary = value.to_string(),
+            _ => {}
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo() -> (tempfile::TempDir, GitTool, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path().to_string_lossy().to_string();
+        let git = GitTool::default();
+        git.run(&cwd, ["init", "--quiet", "--initial-branch=main"]).unwrap();
+        git.run(&cwd, ["config", "user.name", "Test"]).unwrap();
+        git.run(&cwd, ["config", "user.email", "test@example.com"]).unwrap();
+        (dir, git, cwd)
+    }
+
+    fn write(cwd: &str, path: &str, contents: &str) {
+        std::fs::write(std::path::Path::new(cwd).join(path), contents).unwrap();
+    }
+
+    #[test]
+    fn test_not_a_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path().to_string_lossy().to_string();
+        let err = GitTool::default().status(&cwd).unwrap_err();
+        assert!(matches!(err, GitError::NotARepository(_)), "{err}");
+    }
+
+    #[test]
+    fn test_status_entries() {
+        let (_dir, git, cwd) = repo();
+        let status = git.status(&cwd).unwrap();
+        assert!(status.clean);
+        assert_eq!(status.branch.as_deref(), Some("main"));
+        assert_eq!(status.head, None);
+        assert!(git.log(&cwd, &LogOptions::default()).unwrap().is_empty());
+
+        write(&cwd, "a.txt", "a\n");
+        write(&cwd, "b.txt", "b\n");
+        git.add(&cwd, &["a.txt".to_string(), "b.txt".to_string()]).unwrap();
+        git.commit(&cwd, "initial").unwrap();
+        git.run(&cwd, ["mv", "a.txt", "renamed file.txt"]).unwrap();
+        write(&cwd, "b.txt", "b2\n");
+        write(&cwd, "new.txt", "new\n");
+
+        let status = git.status(&cwd).unwrap();
+        assert!(!status.clean);
+        assert_eq!(
+            status.entries,
+            vec![
+                StatusEntry {
+                    path: "b.txt".to_string(),
+                    orig_path: None,
+                    kind: EntryKind::Tracked,
+                    staged: FileChange::Unmodified,
+                    unstaged: FileChange::Modified,
+                },
+                StatusEntry {
+                    path: "renamed file.txt".to_string(),
+                    orig_path: Some("a.txt".to_string()),
+                    kind: EntryKind::Renamed,
+                    staged: FileChange::Renamed,
+                    unstaged: FileChange::Unmodified,
+                },
+                StatusEntry {
+                    path: "new.txt".to_string(),
+                    orig_path: None,
+                    kind: EntryKind::Untracked,
+                    staged: FileChange::Unmodified,
+                    unstaged: FileChange::Added,
+                },
+            ]
+        );
+        assert!(git.diff(&cwd, &DiffTarget::Unstaged).unwrap().contains("+b2"));
+        assert!(git.diff(&cwd, &DiffTarget::Staged).unwrap().contains("rename to renamed file.txt"));
+
+        git.restore(&cwd, &["b.txt".to_string()], false).unwrap();
+        assert_eq!(git.status(&cwd).unwrap().entries.len(), 2);
+    }
+
+    #[test]
+    fn test_option_like_revs_are_rejected() {
+        let (dir, git, cwd) = repo();
+        write(&cwd, "a.txt", "a\n");
+        git.add(&cwd, &["a.txt".to_string()]).unwrap();
+        git.commit(&cwd, "initial").unwrap();
+
+        let target = dir.path().join("written");
+        let rev = format!("--output={}", target.display());
+        let log = git.log(
+            &cwd,
+            &LogOptions {
+                range: Some(rev.clone()),
+                ..LogOptions::default()
+            },
+        );
+        assert!(matches!(log, Err(GitError::InvalidRev(_))));
+        assert!(matches!(git.diff(&cwd, &DiffTarget::Rev(rev.clone())), Err(GitError::InvalidRev(_))));
+        assert!(matches!(git.diff(&cwd, &DiffTarget::Range(rev.clone())), Err(GitError::InvalidRev(_))));
+        assert!(matches!(git.show(&cwd, &rev), Err(GitError::InvalidRev(_))));
+        assert!(matches!(git.blame(&cwd, "a.txt", 1, 1, Some(&rev)), Err(GitError::InvalidRev(_))));
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn test_history_blame_and_branches() {
+        let (_dir, git, cwd) = repo();
+        write(&cwd, "lib.rs", "fn a() {}\n");
+        git.add(&cwd, &["lib.rs".to_string()]).unwrap();
+        let first = git.commit(&cwd, "Add a\n\nWith a body.").unwrap();
+        assert_eq!(first.subject, "Add a");
+        assert_eq!(first.body, "With a body.");
+        assert!(first.parents.is_empty());
+
+        git.switch(&cwd, "feature", true).unwrap();
+        write(&cwd, "lib.rs", "fn a() {}\nfn b() {}\n");
+        write(&cwd, "other.rs", "\n");
+        git.add(&cwd, &["lib.rs".to_string(), "other.rs".to_string()]).unwrap();
+        let second = git.commit(&cwd, "Add b").unwrap();
+        assert_eq!(second.parents, vec![first.hash.clone()]);
+
+        let log = git
+            .log(
+                &cwd,
+                &LogOptions {
+                    range: Some("main..feature".to_string()),
+                    ..LogOptions::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(log, vec![second.clone()]);
+        let log = git
+            .log(
+                &cwd,
+                &LogOptions {
+                    paths: vec!["lib.rs".to_string()],
+                    ..LogOptions::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(log.len(), 2);
+
+        let details = git.show(&cwd, "HEAD").unwrap();
+        assert_eq!(details.commit, second);
+        assert_eq!(
+            details.files,
+            vec![
+                FileStat {
+                    path: "lib.rs".to_string(),
+                    additions: Some(1),
+                    deletions: Some(0),
+                },
+                FileStat {
+                    path: "other.rs".to_string(),
+                    additions: Some(1),
+                    deletions: Some(0),
+                },
+            ]
+        );
+        assert!(details.patch.contains("+fn b() {}"));
+        assert!(git.diff(&cwd, &DiffTarget::Rev("HEAD".to_string())).unwrap().is_empty());
+        assert!(git.diff(&cwd, &DiffTarget::Rev("main".to_string())).unwrap().contains("+fn b() {}"));
+        assert!(git.diff(&cwd, &DiffTarget::Range("main...HEAD".to_string())).unwrap().contains("+fn b() {}"));
+
+        let blame = git.blame(&cwd, "lib.rs", 1, 2, None).unwrap();
+        assert_eq!(blame.len(), 2);
+        assert_eq!((blame[0].line, blame[0].commit.as_str()), (1, first.hash.as_str()));
+        assert_eq!((blame[1].summary.as_str(), blame[1].content.as_str()), ("Add b", "fn b() {}"));
+        assert_eq!(blame[1].author, "Test");
+
+        git.create_branch(&cwd, "topic", Some("main")).unwrap();
+        let branches = git.branches(&cwd).unwrap();
+        let names: Vec<_> = branches.iter().map(|b| (b.name.as_str(), b.current)).collect();
+        assert_eq!(names, vec![("feature", true), ("main", false), ("topic", false)]);
+        assert_eq!(branches[2].head, first.hash);
+
+        git.switch(&cwd, "main", false).unwrap();
+        assert!(matches!(git.switch(&cwd, "missing", false), Err(GitError::Failed { .. })));
+        assert!(matches!(git.switch(&cwd, "--orphan=x", false), Err(GitError::InvalidRev(_))));
+        assert_eq!(git.status(&cwd).unwrap().branch.as_deref(), Some("main"));
     }
 }
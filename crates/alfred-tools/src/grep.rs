@@ -0,0 +1,314 @@
+use std::fs;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use globset::{GlobSet, GlobSetBuilder};
+use ignore::types::TypesBuilder;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::fs::{compile_glob, workspace_walker};
+
+const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+const MAX_SNIPPET_CHARS: usize = 240;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrepOptions {
+    pub literal: bool,
+    pub case_insensitive: bool,
+    // Lets matches span lines; `.` then also matches newlines.
+    pub multiline: bool,
+    pub context_before: usize,
+    pub context_after: usize,
+    // Globs relative to the search root, e.g. `src/**/*.rs`.
+    pub globs: Vec<String>,
+    // ripgrep-style type names such as `rust`, `py` or `ts`.
+    pub file_types: Vec<String>,
+    pub respect_ignore: bool,
+    pub include_hidden: bool,
+    pub max_matches: usize,
+    pub max_file_size: u64,
+}
+
+impl Default for GrepOptions {
+    fn default() -> Self {
+        Self {
+            literal: false,
+            case_insensitive: false,
+            multiline: false,
+            context_before: 0,
+            context_after: 0,
+            globs: Vec::new(),
+            file_types: Vec::new(),
+            respect_ignore: true,
+            include_hidden: true,
+            max_matches: 200,
+            max_file_size: 2 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrepMatch {
+    pub path: Utf8PathBuf,
+    // 1-based line and byte column of the start of the match.
+    pub line: usize,
+    pub column: usize,
+    pub snippet: String,
+    pub context_before: Vec<String>,
+    pub context_after: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrepResult {
+    pub matches: Vec<GrepMatch>,
+    pub files_searched: usize,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct GrepTool;
+
+impl GrepTool {
+    pub fn search(&self, root: &Utf8Path, pattern: &str, options: &GrepOptions) -> anyhow::Result<GrepResult> {
+        let regex = build_regex(pattern, options)?;
+        let globs = build_globs(&options.globs)?;
+
+        let mut walker = workspace_walker(root, options.respect_ignore, options.include_hidden);
+        walker.sort_by_file_name(|a, b| a.cmp(b));
+        if !options.file_types.is_empty() {
+            let mut types = TypesBuilder::new();
+            types.add_defaults();
+            for name in &options.file_types {
+                types.select(name);
+            }
+            walker.types(types.build()?);
+        }
+
+        let mut result = GrepResult {
+            matches: Vec::new(),
+            files_searched: 0,
+            truncated: false,
+        };
+
+        for entry in walker.build() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    tracing::debug!("skipping unreadable entry: {}", err);
+                    continue;
+                }
+            };
+            if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+                continue;
+            }
+            let path = Utf8PathBuf::from_path_buf(entry.path().to_path_buf())
+                .map_err(|_| anyhow::anyhow!("Non-utf8 path"))?;
+            if let Some(globs) = &globs {
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                if !globs.is_match(relative) {
+                    continue;
+                }
+            }
+            if entry.metadata().map(|meta| meta.len()).unwrap_or(0) > options.max_file_size {
+                continue;
+            }
+
+            let bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    tracing::debug!("skipping {}: {}", path, err);
+                    continue;
+                }
+            };
+            if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+                continue;
+            }
+            result.files_searched += 1;
+
+            let text = String::from_utf8_lossy(&bytes);
+            let remaining = options.max_matches - result.matches.len();
+            let (matches, file_truncated) = search_text(&path, &text, &regex, options, remaining);
+            result.matches.extend(matches);
+            if file_truncated {
+                result.truncated = true;
+                break;
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+fn build_regex(pattern: &str, options: &GrepOptions) -> anyhow::Result<Regex> {
+    let pattern = if options.literal {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    Ok(RegexBuilder::new(&pattern)
+        .case_insensitive(options.case_insensitive)
+        .multi_line(true)
+        .dot_matches_new_line(options.multiline)
+        .build()?)
+}
+
+fn build_globs(patterns: &[String]) -> anyhow::Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(compile_glob(pattern)?.glob().clone());
+    }
+    Ok(Some(builder.build()?))
+}
+
+fn search_text(
+    path: &Utf8Path,
+    text: &str,
+    regex: &Regex,
+    options: &GrepOptions,
+    limit: usize,
+) -> (Vec<GrepMatch>, bool) {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+        .collect();
+    let lines: Vec<&str> = text.lines().collect();
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset) - 1;
+
+    let mut matches = Vec::new();
+
+    let mut push = |start: usize, end: usize| -> bool {
+        if matches.len() == limit {
+            return false;
+        }
+        let first = line_of(start);
+        let last = line_of(end.saturating_sub(1).max(start));
+
+        let snippet = lines
+            .get(first..=last.min(lines.len().saturating_sub(1)))
+            .unwrap_or_default()
+            .join("\n");
+        let before_start = first.saturating_sub(options.context_before);
+        let after_end = (last + 1 + options.context_after).min(lines.len());
+        matches.push(GrepMatch {
+            path: path.to_path_buf(),
+            line: first + 1,
+            column: start - line_starts[first] + 1,
+            snippet: clip(&snippet),
+            context_before: lines[before_start..first].iter().map(|line| clip(line)).collect(),
+            context_after: lines[(last + 1).min(after_end)..after_end]
+                .iter()
+                .map(|line| clip(line))
+                .collect(),
+        });
+        true
+    };
+
+    if options.multiline {
+        for found in regex.find_iter(text) {
+            if !push(found.start(), found.end()) {
+                return (matches, true);
+            }
+        }
+    } else {
+        // Search line by line so `\s` and friends can't consume a newline.
+        for (idx, line) in lines.iter().enumerate() {
+            if let Some(found) = regex.find(line) {
+                let offset = line_starts[idx];
+                if !push(offset + found.start(), offset + found.end()) {
+                    return (matches, true);
+                }
+            }
+        }
+    }
+    (matches, false)
+}
+
+fn clip(line: &str) -> String {
+    let line = line.trim_end_matches('\r');
+    match line.char_indices().nth(MAX_SNIPPET_CHARS) {
+        Some((idx, _)) => format!("{}…", &line[..idx]),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::write(
+            root.join("src/lib.rs"),
+            "use std::fs;\n\npub fn read_config() {\n    let x = 1;\n}\n\nfn Helper() {}\n",
+        )
+        .unwrap();
+        fs::write(root.join("src/app.py"), "def read_config():\n    pass\n").unwrap();
+        fs::write(root.join("target/gen.rs"), "pub fn read_config() {}\n").unwrap();
+        fs::write(root.join("blob.bin"), b"read_config\0\x01").unwrap();
+        (dir, root)
+    }
+
+    #[test]
+    fn test_search_respects_ignore_and_binary() {
+        let (_dir, root) = fixture();
+        let result = GrepTool.search(&root, "read_config", &GrepOptions::default()).unwrap();
+        let paths: Vec<_> = result
+            .matches
+            .iter()
+            .map(|m| m.path.strip_prefix(&root).unwrap().to_string())
+            .collect();
+        assert_eq!(paths, vec!["src/app.py", "src/lib.rs"]);
+        let rust = &result.matches[1];
+        assert_eq!((rust.line, rust.column), (3, 8));
+        assert_eq!(rust.snippet, "pub fn read_config() {");
+    }
+
+    #[test]
+    fn test_search_case_context_and_types() {
+        let (_dir, root) = fixture();
+        let options = GrepOptions {
+            literal: true,
+            case_insensitive: true,
+            context_before: 1,
+            context_after: 1,
+            file_types: vec!["rust".to_string()],
+            ..GrepOptions::default()
+        };
+        let result = GrepTool.search(&root, "helper()", &options).unwrap();
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].line, 7);
+        assert_eq!(result.matches[0].context_before, vec![""]);
+        assert!(result.matches[0].context_after.is_empty());
+    }
+
+    #[test]
+    fn test_search_multiline_and_caps() {
+        let (_dir, root) = fixture();
+        let single = GrepTool.search(&root, r"\{\s+let", &GrepOptions::default()).unwrap();
+        assert!(single.matches.is_empty());
+
+        let options = GrepOptions {
+            multiline: true,
+            globs: vec!["src/*.rs".to_string()],
+            ..GrepOptions::default()
+        };
+        let multi = GrepTool.search(&root, r"\{\s+let", &options).unwrap();
+        assert_eq!(multi.matches.len(), 1);
+        assert_eq!(multi.matches[0].snippet, "pub fn read_config() {\n    let x = 1;");
+
+        let capped = GrepOptions {
+            max_matches: 1,
+            ..GrepOptions::default()
+        };
+        let result = GrepTool.search(&root, "fn", &capped).unwrap();
+        assert_eq!(result.matches.len(), 1);
+        assert!(result.truncated);
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use reqwest::redirect;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::NetworkConfig;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+const DEFAULT_MAX_REDIRECTS: usize = 5;
+// Column width for HTML rendered as text.
+const HTML_TEXT_WIDTH: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum HttpError {
+    #[error("invalid URL `{0}`")]
+    InvalidUrl(String),
+    #[error("only http and https URLs are supported, got `{0}`")]
+    UnsupportedScheme(String),
+    #[error("host `{0}` is not in the network allowlist")]
+    HostNotAllowed(String),
+    #[error("request timed out after {0} ms")]
+    Timeout(u64),
+    #[error("redirect rejected: {0}")]
+    Redirect(String),
+    #[error("invalid network allowlist pattern: {0}")]
+    Pattern(#[from] globset::Error),
+    #[error("request failed: {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HttpMethod {
+    #[default]
+    Get,
+    Post,
+    Put,
+    Patch,
+    Delete,
+}
+
+impl HttpMethod {
+    fn as_reqwest(self) -> reqwest::Method {
+        match self {
+            HttpMethod::Get => reqwest::Method::GET,
+            HttpMethod::Post => reqwest::Method::POST,
+            HttpMethod::Put => reqwest::Method::PUT,
+            HttpMethod::Patch => reqwest::Method::PATCH,
+            HttpMethod::Delete => reqwest::Method::DELETE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpRequest {
+    pub method: HttpMethod,
+    pub url: String,
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<String>,
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpResponse {
+    pub status: u16,
+    // After redirects.
+    pub url: String,
+    pub content_type: Option<String>,
+    pub body: String,
+    // The body was cut at the size cap.
+    pub truncated: bool,
+    // The body was HTML and has been rendered as Markdown-style text.
+    pub converted: bool,
+    pub duration_ms: u64,
+}
+
+// Host patterns from `[network] allow_hosts`. A pattern with a port only
+// matches that port; without one it matches any port.
+#[derive(Debug, Clone)]
+pub struct HostAllowlist {
+    patterns: GlobSet,
+}
+
+impl HostAllowlist {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<Self, HttpError> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = pattern.as_ref().to_ascii_lowercase();
+            builder.add(GlobBuilder::new(&pattern).literal_separator(true).build()?);
+        }
+        Ok(Self {
+            patterns: builder.build()?,
+        })
+    }
+
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+        let with_port = url.port_or_known_default().map(|port| format!("{}:{}", host, port));
+        self.patterns.is_match(&host) || with_port.is_some_and(|host| self.patterns.is_match(host))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpTool {
+    pub allowlist: Arc<HostAllowlist>,
+    pub default_timeout: Duration,
+    pub max_response_bytes: usize,
+    pub max_redirects: usize,
+}
+
+impl HttpTool {
+    pub fn from_config(config: &NetworkConfig) -> Result<Self, HttpError> {
+        Ok(Self {
+            allowlist: Arc::new(HostAllowlist::new(&config.allow_hosts)?),
+            default_timeout: DEFAULT_TIMEOUT,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+        })
+    }
+
+    pub async fn fetch(&self, request: &HttpRequest) -> Result<HttpResponse, HttpError> {
+        let url = Url::parse(&request.url).map_err(|_| HttpError::InvalidUrl(request.url.clone()))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(HttpError::UnsupportedScheme(url.scheme().to_string()));
+        }
+        if !self.allowlist.is_allowed(&url) {
+            return Err(HttpError::HostNotAllowed(url.host_str().unwrap_or_default().to_string()));
+        }
+
+        let timeout = request
+            .timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(self.default_timeout);
+        // Every hop is checked, so an allowed host can't bounce us elsewhere.
+        let allowlist = self.allowlist.clone();
+        let max_redirects = self.max_redirects;
+        let policy = redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                attempt.error(format!("more than {} redirects", max_redirects))
+            } else if !allowlist.is_allowed(attempt.url()) {
+                let host = attempt.url().host_str().unwrap_or_default().to_string();
+                attempt.error(format!("host `{}` is not in the network allowlist", host))
+            } else {
+                attempt.follow()
+            }
+        });
+        let client = reqwest::Client::builder()
+            .redirect(policy)
+            .timeout(timeout)
+            .user_agent(concat!("alfred/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+
+        let mut builder = client.request(request.method.as_reqwest(), url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        if let Some(body) = &request.body {
+            builder = builder.body(body.clone());
+        }
+
+        let started = Instant::now();
+        let timeout_ms = timeout.as_millis() as u64;
+        let map_err = |e: reqwest::Error| {
+            if e.is_timeout() {
+                HttpError::Timeout(timeout_ms)
+            } else if e.is_redirect() {
+                HttpError::Redirect(redirect_reason(&e))
+            } else {
+                HttpError::Request(e)
+            }
+        };
+        let mut response = builder.send().await.map_err(map_err)?;
+
+        let status = response.status().as_u16();
+        let final_url = response.url().to_string();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let mut bytes = Vec::new();
+        let mut truncated = false;
+        while let Some(chunk) = response.chunk().await.map_err(map_err)? {
+            let room = self.max_response_bytes - bytes.len();
+            if chunk.len() > room {
+                bytes.extend_from_slice(&chunk[..room]);
+                truncated = true;
+                break;
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        let is_html = content_type
+            .as_deref()
+            .is_some_and(|content_type| content_type.contains("html"));
+        let (body, converted) = match is_html {
+            true => match html2text::from_read(bytes.as_slice(), HTML_TEXT_WIDTH) {
+                Ok(text) => (text, true),
+                Err(_) => (String::from_utf8_lossy(&bytes).to_string(), false),
+            },
+            false => (String::from_utf8_lossy(&bytes).to_string(), false),
+        };
+
+        Ok(HttpResponse {
+            status,
+            url: final_url,
+            content_type,
+            body,
+            truncated,
+            converted,
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+}
+
+// reqwest wraps the policy's error; its message is the useful part.
+fn redirect_reason(error: &reqwest::Error) -> String {
+    let mut source = std::error::Error::source(error);
+    let mut reason = error.to_string();
+    while let Some(inner) = source {
+        reason = inner.to_string();
+        source = inner.source();
+    }
+    reason
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Serves canned responses by request path on a local port; every
+    // request's head and body is also echoed back on `/echo`.
+    pub(crate) async fn serve(routes: Vec<(&'static str, String)>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let routes = Arc::new(routes);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let routes = routes.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0u8; 4096];
+                    loop {
+                        let Ok(read) = stream.read(&mut buf).await else { return };
+                        if read == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..read]);
+                        let text = String::from_utf8_lossy(&request).to_string();
+                        let Some(head_end) = text.find("\r\n\r\n") else { continue };
+                        let length = text[..head_end]
+                            .lines()
+                            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap_or(0)))
+                            .unwrap_or(0);
+                        if request.len() >= head_end + 4 + length {
+                            break;
+                        }
+                    }
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let path = text.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    let response = match routes.iter().find(|(route, _)| *route == path) {
+                        Some((_, response)) => response.clone(),
+                        None if path == "/echo" => respond("200 OK", "text/plain", &text),
+                        None => respond("404 Not Found", "text/plain", "missing"),
+                    };
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        port
+    }
+
+    pub(crate) fn respond(status: &str, content_type: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            content_type,
+            body.len(),
+            body
+        )
+    }
+
+    fn redirect_to(location: &str) -> String {
+        format!("HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", location)
+    }
+
+    fn tool(hosts: &[&str]) -> HttpTool {
+        HttpTool::from_config(&NetworkConfig {
+            allow_hosts: hosts.iter().map(|host| host.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    fn get(url: String) -> HttpRequest {
+        HttpRequest {
+            url,
+            ..HttpRequest::default()
+        }
+    }
+
+    #[test]
+    fn test_allowlist_patterns() {
+        let allowlist = HostAllowlist::new(&["docs.rs", "*.example.com", "localhost:8080"]).unwrap();
+        let allowed = |url: &str| allowlist.is_allowed(&Url::parse(url).unwrap());
+        assert!(allowed("https://docs.rs/serde"));
+        assert!(allowed("https://api.example.com/v1"));
+        assert!(!allowed("https://example.com.evil.net/"));
+        assert!(!allowed("https://example.com/"));
+        assert!(allowed("http://localhost:8080/"));
+        assert!(!allowed("http://localhost:9090/"));
+        assert!(!HostAllowlist::new::<&str>(&[]).unwrap().is_allowed(&Url::parse("https://docs.rs").unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_html_post_and_caps() {
+        let page = "<html><body><h1>Guide</h1><p>Use <a href=\"/x\">this</a>.</p><script>evil()</script></body></html>";
+        let port = serve(vec![
+            ("/page", respond("200 OK", "text/html; charset=utf-8", page)),
+            ("/big", respond("200 OK", "text/plain", &"a".repeat(5000))),
+        ])
+        .await;
+        let tool = tool(&["127.0.0.1"]);
+
+        let response = tool.fetch(&get(format!("http://127.0.0.1:{}/page", port))).await.unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response.converted);
+        assert!(response.body.contains("# Guide"), "{}", response.body);
+        assert!(!response.body.contains("<p>"));
+
+        let mut request = get(format!("http://127.0.0.1:{}/echo", port));
+        request.method = HttpMethod::Post;
+        request.headers.insert("X-Token".to_string(), "abc".to_string());
+        request.body = Some("{\"q\":1}".to_string());
+        let echoed = tool.fetch(&request).await.unwrap().body;
+        assert!(echoed.starts_with("POST /echo"));
+        assert!(echoed.to_ascii_lowercase().contains("x-token: abc"));
+        assert!(echoed.ends_with("{\"q\":1}"));
+
+        let small = HttpTool {
+            max_response_bytes: 100,
+            ..tool.clone()
+        };
+        let response = small.fetch(&get(format!("http://127.0.0.1:{}/big", port))).await.unwrap();
+        assert!(response.truncated);
+        assert_eq!(response.body.len(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_enforces_allowlist_and_redirects() {
+        let port = serve(vec![
+            ("/loop", redirect_to("/loop")),
+            ("/away", redirect_to("http://localhost:1/")),
+            ("/hop", redirect_to("/final")),
+            ("/final", respond("200 OK", "text/plain", "done")),
+        ])
+        .await;
+        let tool = tool(&["127.0.0.1"]);
+
+        let err = tool.fetch(&get("https://docs.rs/".to_string())).await.unwrap_err();
+        assert!(matches!(err, HttpError::HostNotAllowed(host) if host == "docs.rs"));
+        let err = tool.fetch(&get("file:///etc/passwd".to_string())).await.unwrap_err();
+        assert!(matches!(err, HttpError::UnsupportedScheme(_)));
+
+        let response = tool.fetch(&get(format!("http://127.0.0.1:{}/hop", port))).await.unwrap();
+        assert_eq!(response.body, "done");
+        assert!(response.url.ends_with("/final"));
+
+        let err = tool.fetch(&get(format!("http://127.0.0.1:{}/away", port))).await.unwrap_err();
+        assert!(matches!(&err, HttpError::Redirect(reason) if reason.contains("localhost")), "{err}");
+        let err = tool.fetch(&get(format!("http://127.0.0.1:{}/loop", port))).await.unwrap_err();
+        assert!(matches!(&err, HttpError::Redirect(reason) if reason.contains("redirects")), "{err}");
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Notify};
+use tokio_util::sync::CancellationToken;
+
+use crate::shell::{kill_process_group, spawn_capture, OutputLine, OutputStream, ShellCommand, ShellTool};
+
+// Lines kept per job; older ones are dropped and counted.
+const MAX_JOB_LINES: usize = 2000;
+// How long `stop` waits after SIGTERM before killing the process group.
+const STOP_GRACE: Duration = Duration::from_secs(3);
+const PORT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Exited { code: Option<i32>, signal: Option<i32> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub name: String,
+    pub command_line: String,
+    pub pid: Option<u32>,
+    #[serde(flatten)]
+    pub state: JobState,
+    pub uptime_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobOutput {
+    pub lines: Vec<OutputLine>,
+    // Pass back as `since` to get only lines printed after this poll.
+    pub next: u64,
+    // Lines between `since` and the oldest kept line that were dropped.
+    pub dropped: u64,
+    pub job: JobInfo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadyCheck {
+    // A regex matched against each output line.
+    Pattern(String),
+    // A TCP port accepting connections on localhost.
+    Port(u16),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobReadiness {
+    pub ready: bool,
+    pub timed_out: bool,
+    pub matched_line: Option<String>,
+    pub job: JobInfo,
+}
+
+struct JobLog {
+    lines: VecDeque<OutputLine>,
+    // Sequence number of `lines[0]`.
+    first: u64,
+    state: JobState,
+}
+
+impl JobLog {
+    fn next(&self) -> u64 {
+        self.first + self.lines.len() as u64
+    }
+}
+
+struct Job {
+    command_line: String,
+    pid: Option<u32>,
+    started: Instant,
+    log: Mutex<JobLog>,
+    notify: Notify,
+    // Cancelling kills the whole process group.
+    kill: CancellationToken,
+}
+
+// Long-running processes (dev servers, watchers, databases) started by name
+// and left running while the agent keeps working. Dropping the manager kills
+// every job.
+pub struct JobManager {
+    tool: ShellTool,
+    jobs: Mutex<HashMap<String, Arc<Job>>>,
+}
+
+impl Default for JobManager {
+    fn default() -> Self {
+        Self::new(ShellTool::default())
+    }
+}
+
+impl JobManager {
+    // Jobs are spawned like `tool` commands (environment, sandbox) but
+    // ignore its timeout and output cap.
+    pub fn new(tool: ShellTool) -> Self {
+        Self {
+            tool,
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn start(&self, name: &str, command: ShellCommand) -> anyhow::Result<JobInfo> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            anyhow::bail!("Job names must be non-empty and contain no whitespace");
+        }
+        let mut jobs = self.lock_jobs()?;
+        if let Some(existing) = jobs.get(name) {
+            if existing.state() == JobState::Running {
+                anyhow::bail!("Job `{}` is already running", name);
+            }
+        }
+
+        let command_line = command
+            .script
+            .clone()
+            .unwrap_or_else(|| std::iter::once(&command.program).chain(&command.args).cloned().collect::<Vec<_>>().join(" "));
+        let (mut cmd, _) = self.tool.command(&command)?;
+        let mut child = cmd.spawn().with_context(|| format!("Failed to start job `{}`", name))?;
+
+        let job = Arc::new(Job {
+            command_line,
+            pid: child.id(),
+            started: Instant::now(),
+            log: Mutex::new(JobLog {
+                lines: VecDeque::new(),
+                first: 0,
+                state: JobState::Running,
+            }),
+            notify: Notify::new(),
+            kill: CancellationToken::new(),
+        });
+
+        let (tx, mut rx) = mpsc::channel::<OutputLine>(256);
+        spawn_capture(child.stdout.take(), 0, OutputStream::Stdout, Some(tx.clone()));
+        spawn_capture(child.stderr.take(), 0, OutputStream::Stderr, Some(tx));
+
+        let collector = job.clone();
+        tokio::spawn(async move {
+            while let Some(line) = rx.recv().await {
+                if let Ok(mut log) = collector.log.lock() {
+                    if log.lines.len() == MAX_JOB_LINES {
+                        log.lines.pop_front();
+                        log.first += 1;
+                    }
+                    log.lines.push_back(line);
+                }
+                collector.notify.notify_waiters();
+            }
+        });
+
+        let waiter = job.clone();
+        tokio::spawn(async move {
+            let status = tokio::select! {
+                status = child.wait() => status.ok(),
+                _ = waiter.kill.cancelled() => {
+                    kill_process_group(&mut child);
+                    child.wait().await.ok()
+                }
+            };
+            #[cfg(unix)]
+            let signal = status.and_then(|status| std::os::unix::process::ExitStatusExt::signal(&status));
+            #[cfg(not(unix))]
+            let signal = None;
+            if let Ok(mut log) = waiter.log.lock() {
+                log.state = JobState::Exited {
+                    code: status.and_then(|status| status.code()),
+                    signal,
+                };
+            }
+            waiter.notify.notify_waiters();
+        });
+
+        let info = job.info(name);
+        jobs.insert(name.to_string(), job);
+        Ok(info)
+    }
+
+    pub fn status(&self, name: &str) -> anyhow::Result<JobInfo> {
+        Ok(self.job(name)?.info(name))
+    }
+
+    pub fn list(&self) -> Vec<JobInfo> {
+        let Ok(jobs) = self.jobs.lock() else {
+            return Vec::new();
+        };
+        let mut infos: Vec<_> = jobs.iter().map(|(name, job)| job.info(name)).collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    // Output lines numbered from `since` (0 for everything still buffered),
+    // at most the last `max_lines` of them.
+    pub fn output(&self, name: &str, since: u64, max_lines: usize) -> anyhow::Result<JobOutput> {
+        let job = self.job(name)?;
+        let (lines, next, dropped) = {
+            let log = job.lock_log()?;
+            let start = since.max(log.first);
+            let skip = (start - log.first) as usize;
+            let available = log.lines.len().saturating_sub(skip);
+            let take_from = skip + available.saturating_sub(max_lines);
+            let lines: Vec<_> = log.lines.iter().skip(take_from).cloned().collect();
+            let dropped = (log.first.saturating_sub(since)) + (take_from - skip) as u64;
+            (lines, log.next(), dropped)
+        };
+        Ok(JobOutput {
+            lines,
+            next,
+            dropped,
+            job: job.info(name),
+        })
+    }
+
+    // Waits until the job prints a line matching the pattern (lines already
+    // printed count) or its port accepts connections. Gives up early if the
+    // job exits.
+    pub async fn wait_ready(&self, name: &str, check: &ReadyCheck, timeout: Duration) -> anyhow::Result<JobReadiness> {
+        let job = self.job(name)?;
+        let pattern = match check {
+            ReadyCheck::Pattern(pattern) => Some(Regex::new(pattern)?),
+            ReadyCheck::Port(_) => None,
+        };
+        let deadline = Instant::now() + timeout;
+        let mut cursor: u64 = 0;
+        let readiness = |ready, timed_out, matched_line| JobReadiness {
+            ready,
+            timed_out,
+            matched_line,
+            job: job.info(name),
+        };
+
+        loop {
+            let notified = job.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let state = {
+                let log = job.lock_log()?;
+                if let Some(pattern) = &pattern {
+                    let skip = cursor.saturating_sub(log.first) as usize;
+                    if let Some(line) = log.lines.iter().skip(skip).find(|line| pattern.is_match(&line.line)) {
+                        let line = line.line.clone();
+                        drop(log);
+                        return Ok(readiness(true, false, Some(line)));
+                    }
+                    cursor = log.next();
+                }
+                log.state
+            };
+            if let ReadyCheck::Port(port) = check {
+                if tokio::net::TcpStream::connect(("127.0.0.1", *port)).await.is_ok() {
+                    return Ok(readiness(true, false, None));
+                }
+            }
+            if state != JobState::Running {
+                return Ok(readiness(false, false, None));
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(readiness(false, true, None));
+            }
+            let wake_at = match check {
+                ReadyCheck::Pattern(_) => deadline,
+                ReadyCheck::Port(_) => deadline.min(now + PORT_POLL_INTERVAL),
+            };
+            tokio::select! {
+                _ = &mut notified => {}
+                _ = tokio::time::sleep_until(wake_at.into()) => {}
+            }
+        }
+    }
+
+    // SIGTERM to the process group, then SIGKILL if it is still running
+    // after a grace period. The job stays listed with its exit status.
+    pub async fn stop(&self, name: &str) -> anyhow::Result<JobInfo> {
+        let job = self.job(name)?;
+        if job.state() == JobState::Running {
+            #[cfg(unix)]
+            if let Some(pid) = job.pid {
+                unsafe {
+                    libc::killpg(pid as libc::pid_t, libc::SIGTERM);
+                }
+            }
+            #[cfg(not(unix))]
+            job.kill.cancel();
+            if !job.wait_exit(STOP_GRACE).await {
+                job.kill.cancel();
+                job.wait_exit(STOP_GRACE).await;
+            }
+        }
+        Ok(job.info(name))
+    }
+
+    // Stops the job if needed and forgets it.
+    pub async fn remove(&self, name: &str) -> anyhow::Result<JobInfo> {
+        let info = self.stop(name).await?;
+        self.lock_jobs()?.remove(name);
+        Ok(info)
+    }
+
+    pub fn kill_all(&self) {
+        if let Ok(jobs) = self.jobs.lock() {
+            for job in jobs.values() {
+                job.kill_now();
+            }
+        }
+    }
+
+    fn job(&self, name: &str) -> anyhow::Result<Arc<Job>> {
+        self.lock_jobs()?
+            .get(name)
+            .cloned()
+            .with_context(|| format!("No job named `{}`", name))
+    }
+
+    fn lock_jobs(&self) -> anyhow::Result<std::sync::MutexGuard<'_, HashMap<String, Arc<Job>>>> {
+        self.jobs.lock().map_err(|_| anyhow::anyhow!("jobs lock poisoned"))
+    }
+}
+
+impl Drop for JobManager {
+    fn drop(&mut self) {
+        self.kill_all();
+    }
+}
+
+impl Job {
+    fn lock_log(&self) -> anyhow::Result<std::sync::MutexGuard<'_, JobLog>> {
+        self.log.lock().map_err(|_| anyhow::anyhow!("job log lock poisoned"))
+    }
+
+    fn state(&self) -> JobState {
+        self.log.lock().map(|log| log.state).unwrap_or(JobState::Running)
+    }
+
+    fn info(&self, name: &str) -> JobInfo {
+        JobInfo {
+            name: name.to_string(),
+            command_line: self.command_line.clone(),
+            pid: self.pid,
+            state: self.state(),
+            uptime_ms: self.started.elapsed().as_millis() as u64,
+        }
+    }
+
+    async fn wait_exit(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.state() != JobState::Running {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return false;
+            }
+        }
+    }
+
+    // Synchronous kill for shutdown paths where the waiter task may never
+    // run again (e.g. the runtime is being dropped).
+    fn kill_now(&self) {
+        if self.state() != JobState::Running {
+            return;
+        }
+        #[cfg(unix)]
+        if let Some(pid) = self.pid {
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+        self.kill.cancel();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn manager() -> JobManager {
+        JobManager::new(ShellTool {
+            shell_program: "sh".to_string(),
+            shell_args: vec!["-c".to_string()],
+            ..ShellTool::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_job_output_and_readiness() {
+        let jobs = manager();
+        jobs.start("server", ShellCommand::script("for i in 1 2 3; do echo line $i; done; echo listening; sleep 30"))
+            .unwrap();
+        assert!(jobs.start("server", ShellCommand::script("true")).is_err());
+
+        let ready = jobs
+            .wait_ready("server", &ReadyCheck::Pattern("^listen".to_string()), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(ready.ready);
+        assert_eq!(ready.matched_line.as_deref(), Some("listening"));
+        assert_eq!(ready.job.state, JobState::Running);
+
+        let output = jobs.output("server", 0, 2).unwrap();
+        let lines: Vec<_> = output.lines.iter().map(|line| line.line.as_str()).collect();
+        assert_eq!(lines, vec!["line 3", "listening"]);
+        assert_eq!((output.next, output.dropped), (4, 2));
+        assert!(jobs.output("server", output.next, 10).unwrap().lines.is_empty());
+
+        let stopped = jobs.stop("server").await.unwrap();
+        assert!(matches!(stopped.state, JobState::Exited { .. }));
+        assert_eq!(jobs.list().len(), 1);
+        jobs.remove("server").await.unwrap();
+        assert!(jobs.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_ready_on_port_and_early_exit() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let jobs = manager();
+        jobs.start("idle", ShellCommand::script("sleep 30")).unwrap();
+        let ready = jobs
+            .wait_ready("idle", &ReadyCheck::Port(port), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(ready.ready);
+
+        jobs.start("crash", ShellCommand::script("echo boom >&2; exit 4")).unwrap();
+        let ready = jobs
+            .wait_ready("crash", &ReadyCheck::Pattern("never".to_string()), Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert!(!ready.ready && !ready.timed_out);
+        assert_eq!(ready.job.state, JobState::Exited { code: Some(4), signal: None });
+        // The crashed job's name can be reused.
+        jobs.start("crash", ShellCommand::script("true")).unwrap();
+    }
+}
@@ -1,8 +1,29 @@
+pub mod checkpoint;
 pub mod config;
+pub mod env;
 pub mod fs;
 pub mod git;
+pub mod grep;
+pub mod http;
+pub mod jobs;
+pub mod pty;
+pub mod registry;
 pub mod shell;
+pub mod template;
+pub mod worktree;
 
-pub use fs::{FileEntry, FsTool};
-pub use git::{GitTool, GitWorkspaceStatus};
-pub use shell::{CommandOutput, ShellCommand, ShellTool};
+pub use checkpoint::{Checkpoint, CheckpointError, Checkpoints, RewindOutcome};
+pub use env::EnvPolicy;
+pub use fs::{FileEntry, FileListing, FsTool, ListOptions, SortBy};
+pub use git::{
+    Branch, BlameLine, Commit, CommitDetails, DiffTarget, EntryKind, FileChange, FileStat, GitError, GitTool,
+    GitWorkspaceStatus, LogOptions, StatusEntry,
+};
+pub use grep::{GrepMatch, GrepOptions, GrepResult, GrepTool};
+pub use http::{HostAllowlist, HttpError, HttpMethod, HttpRequest, HttpResponse, HttpTool};
+pub use jobs::{JobInfo, JobManager, JobOutput, JobReadiness, JobState, ReadyCheck};
+pub use pty::{PtyManager, PtyOutput, PtyReadOptions, PtySessionInfo};
+pub use registry::{builtin_tools, SideEffect, ToolRegistry, ToolSpec};
+pub use shell::{CommandOutput, OutputLine, OutputStream, ShellCommand, Sandbox, ShellPolicy, ShellTool, Verdict};
+pub use template::{load_templates, HttpTemplate, TemplateError, TemplateOutput, TemplateValues};
+pub use worktree::TaskWorktree;
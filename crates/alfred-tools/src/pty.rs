@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::config::ShellConfig;
+use crate::env::EnvPolicy;
+use crate::shell::ShellCommand;
+
+const DEFAULT_ROWS: u16 = 40;
+const DEFAULT_COLS: u16 = 120;
+const SCROLLBACK_LINES: usize = 1000;
+// Output not yet returned by `read`; older bytes are dropped past this.
+const MAX_UNREAD_BYTES: usize = 64 * 1024;
+const MAX_TRANSCRIPT_BYTES: usize = 256 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PtyReadOptions {
+    // Regex to wait for in the new output. Without it, `read` returns once
+    // the program has been quiet for `idle_ms`.
+    pub wait_for: Option<String>,
+    pub idle_ms: u64,
+    pub timeout_ms: u64,
+}
+
+impl Default for PtyReadOptions {
+    fn default() -> Self {
+        Self {
+            wait_for: None,
+            idle_ms: 300,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PtyOutput {
+    pub id: String,
+    // Text printed since the previous read, with escape sequences removed.
+    pub output: String,
+    // The rendered terminal screen as the user would see it.
+    pub screen: String,
+    pub matched: bool,
+    pub timed_out: bool,
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PtySessionInfo {
+    pub id: String,
+    pub command_line: String,
+    pub exit_code: Option<i32>,
+}
+
+struct PtyState {
+    parser: vt100::Parser,
+    unread: String,
+    transcript: String,
+    last_output: Instant,
+    exit_code: Option<i32>,
+}
+
+struct PtySession {
+    command_line: String,
+    state: Arc<Mutex<PtyState>>,
+    notify: Arc<Notify>,
+    writer: Mutex<Box<dyn Write + Send>>,
+    killer: Mutex<Box<dyn ChildKiller + Send + Sync>>,
+    // Keeps the pty open for as long as the session exists.
+    _master: Mutex<Box<dyn MasterPty + Send>>,
+}
+
+// Long-lived programs running in pseudo-terminals, addressed by handle so an
+// agent can drive them across several turns. Dropping the manager kills them.
+pub struct PtyManager {
+    sessions: Mutex<HashMap<String, Arc<PtySession>>>,
+    next_id: AtomicU64,
+    shell: ShellConfig,
+    env: EnvPolicy,
+}
+
+impl Default for PtyManager {
+    fn default() -> Self {
+        Self::new(&ShellConfig::default(), EnvPolicy::default())
+    }
+}
+
+impl PtyManager {
+    pub fn new(shell: &ShellConfig, env: EnvPolicy) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            shell: shell.clone(),
+            env,
+        }
+    }
+
+    pub fn start(&self, command: &ShellCommand) -> anyhow::Result<String> {
+        let (mut builder, command_line) = match &command.script {
+            Some(script) => {
+                let mut builder = CommandBuilder::new(&self.shell.program);
+                builder.args(&self.shell.args);
+                builder.arg(script);
+                (builder, script.clone())
+            }
+            None => {
+                let mut builder = CommandBuilder::new(&command.program);
+                builder.args(&command.args);
+                let line = std::iter::once(command.program.as_str())
+                    .chain(command.args.iter().map(String::as_str))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                (builder, line)
+            }
+        };
+        match &command.cwd {
+            Some(cwd) => builder.cwd(cwd),
+            None => builder.cwd(std::env::current_dir()?),
+        }
+        builder.env_clear();
+        for (name, value) in self.env.effective_from_process() {
+            builder.env(name, value);
+        }
+        builder.env("TERM", "xterm-256color");
+
+        let pair = native_pty_system()
+            .openpty(PtySize {
+                rows: DEFAULT_ROWS,
+                cols: DEFAULT_COLS,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .context("Failed to open a pty")?;
+        let mut child = pair.slave.spawn_command(builder).context("Failed to spawn pty command")?;
+        drop(pair.slave);
+        let killer = child.clone_killer();
+        let mut reader = pair.master.try_clone_reader()?;
+        let writer = pair.master.take_writer()?;
+
+        let state = Arc::new(Mutex::new(PtyState {
+            parser: vt100::Parser::new(DEFAULT_ROWS, DEFAULT_COLS, SCROLLBACK_LINES),
+            unread: String::new(),
+            transcript: String::new(),
+            last_output: Instant::now(),
+            exit_code: None,
+        }));
+        let notify = Arc::new(Notify::new());
+
+        // portable-pty only offers blocking IO, so each session gets a thread.
+        let thread_state = state.clone();
+        let thread_notify = notify.clone();
+        std::thread::spawn(move || {
+            let mut chunk = [0u8; 4096];
+            let mut pending = Vec::new();
+            while let Ok(read) = reader.read(&mut chunk) {
+                if read == 0 {
+                    break;
+                }
+                pending.extend_from_slice(&chunk[..read]);
+                let text = take_utf8(&mut pending);
+                if let Ok(mut state) = thread_state.lock() {
+                    state.parser.process(&chunk[..read]);
+                    let clean = strip_ansi(&text);
+                    push_capped(&mut state.unread, &clean, MAX_UNREAD_BYTES);
+                    push_capped(&mut state.transcript, &clean, MAX_TRANSCRIPT_BYTES);
+                    state.last_output = Instant::now();
+                }
+                thread_notify.notify_waiters();
+            }
+            let code = child
+                .wait()
+                .map(|status| status.exit_code() as i32)
+                .unwrap_or(-1);
+            if let Ok(mut state) = thread_state.lock() {
+                state.exit_code = Some(code);
+            }
+            thread_notify.notify_waiters();
+        });
+
+        let id = format!("pty-{}", self.next_id.fetch_add(1, Ordering::Relaxed));
+        let session = PtySession {
+            command_line,
+            state,
+            notify,
+            writer: Mutex::new(writer),
+            killer: Mutex::new(killer),
+            _master: Mutex::new(pair.master),
+        };
+        self.sessions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("pty sessions lock poisoned"))?
+            .insert(id.clone(), Arc::new(session));
+        Ok(id)
+    }
+
+    // Writes raw input; send `\r` (or `\n`) to press Enter.
+    pub fn send(&self, id: &str, input: &str) -> anyhow::Result<()> {
+        let session = self.session(id)?;
+        let mut writer = session
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("pty writer lock poisoned"))?;
+        writer.write_all(input.as_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub async fn read(&self, id: &str, options: &PtyReadOptions) -> anyhow::Result<PtyOutput> {
+        let session = self.session(id)?;
+        let pattern = options.wait_for.as_deref().map(Regex::new).transpose()?;
+        let deadline = Instant::now() + Duration::from_millis(options.timeout_ms);
+        let idle = Duration::from_millis(options.idle_ms);
+
+        loop {
+            // Register interest before inspecting state so no wakeup is lost.
+            let notified = session.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let now = Instant::now();
+            let wake_at = {
+                let mut state = lock_state(&session)?;
+                let matched = pattern.as_ref().is_some_and(|pattern| pattern.is_match(&state.unread));
+                let idle_done = pattern.is_none() && now.duration_since(state.last_output) >= idle;
+                if matched || idle_done || state.exit_code.is_some() || now >= deadline {
+                    return Ok(PtyOutput {
+                        id: id.to_string(),
+                        output: std::mem::take(&mut state.unread),
+                        screen: state.parser.screen().contents(),
+                        matched,
+                        timed_out: !matched && !idle_done && state.exit_code.is_none(),
+                        exit_code: state.exit_code,
+                    });
+                }
+                if pattern.is_none() {
+                    deadline.min(state.last_output + idle)
+                } else {
+                    deadline
+                }
+            };
+
+            tokio::select! {
+                _ = &mut notified => {}
+                _ = tokio::time::sleep_until(wake_at.into()) => {}
+            }
+        }
+    }
+
+    pub fn screen(&self, id: &str) -> anyhow::Result<String> {
+        let session = self.session(id)?;
+        let state = lock_state(&session)?;
+        Ok(state.parser.screen().contents())
+    }
+
+    // Everything the program printed so far (capped), escape sequences removed.
+    pub fn transcript(&self, id: &str) -> anyhow::Result<String> {
+        let session = self.session(id)?;
+        let state = lock_state(&session)?;
+        Ok(state.transcript.clone())
+    }
+
+    pub fn terminate(&self, id: &str) -> anyhow::Result<()> {
+        let session = self
+            .sessions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("pty sessions lock poisoned"))?
+            .remove(id)
+            .with_context(|| format!("No pty session `{}`", id))?;
+        kill(&session);
+        Ok(())
+    }
+
+    pub fn list(&self) -> Vec<PtySessionInfo> {
+        let Ok(sessions) = self.sessions.lock() else {
+            return Vec::new();
+        };
+        let mut infos: Vec<_> = sessions
+            .iter()
+            .map(|(id, session)| PtySessionInfo {
+                id: id.clone(),
+                command_line: session.command_line.clone(),
+                exit_code: session.state.lock().ok().and_then(|state| state.exit_code),
+            })
+            .collect();
+        infos.sort_by(|a, b| a.id.cmp(&b.id));
+        infos
+    }
+
+    fn session(&self, id: &str) -> anyhow::Result<Arc<PtySession>> {
+        self.sessions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("pty sessions lock poisoned"))?
+            .get(id)
+            .cloned()
+            .with_context(|| format!("No pty session `{}`", id))
+    }
+}
+
+impl Drop for PtyManager {
+    fn drop(&mut self) {
+        if let Ok(sessions) = self.sessions.get_mut() {
+            for session in sessions.values() {
+                kill(session);
+            }
+        }
+    }
+}
+
+fn kill(session: &PtySession) {
+    let exited = session.state.lock().map(|state| state.exit_code.is_some()).unwrap_or(false);
+    if !exited {
+        if let Ok(mut killer) = session.killer.lock() {
+            let _ = killer.kill();
+        }
+    }
+}
+
+fn lock_state(session: &PtySession) -> anyhow::Result<std::sync::MutexGuard<'_, PtyState>> {
+    session
+        .state
+        .lock()
+        .map_err(|_| anyhow::anyhow!("pty state lock poisoned"))
+}
+
+// Takes the longest valid UTF-8 prefix, leaving a split character for later.
+fn take_utf8(pending: &mut Vec<u8>) -> String {
+    let valid = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        Err(err) if err.error_len().is_none() => err.valid_up_to(),
+        Err(_) => pending.len(),
+    };
+    let text = String::from_utf8_lossy(&pending[..valid]).to_string();
+    pending.drain(..valid);
+    text
+}
+
+fn push_capped(buffer: &mut String, text: &str, cap: usize) {
+    buffer.push_str(text);
+    if buffer.len() > cap {
+        let mut cut = buffer.len() - cap;
+        while !buffer.is_char_boundary(cut) {
+            cut += 1;
+        }
+        buffer.drain(..cut);
+    }
+}
+
+fn strip_ansi(text: &str) -> String {
+    static ANSI: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    let ansi = ANSI.get_or_init(|| {
+        Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)|\x1b[@-Z\\-_]")
+            .expect("valid ANSI regex")
+    });
+    ansi.replace_all(text, "").replace('\r', "")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn manager() -> PtyManager {
+        let shell = ShellConfig {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string()],
+            ..ShellConfig::default()
+        };
+        PtyManager::new(&shell, EnvPolicy::default())
+    }
+
+    fn wait_for(pattern: &str) -> PtyReadOptions {
+        PtyReadOptions {
+            wait_for: Some(pattern.to_string()),
+            ..PtyReadOptions::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interactive_prompt_over_multiple_reads() {
+        let manager = manager();
+        let id = manager
+            .start(&ShellCommand::script("printf 'name? '; read name; echo \"hi $name\"; sleep 5"))
+            .unwrap();
+
+        let prompt = manager.read(&id, &wait_for(r"name\? $")).await.unwrap();
+        assert!(prompt.matched);
+        assert_eq!(prompt.exit_code, None);
+
+        manager.send(&id, "bob\r").unwrap();
+        let reply = manager.read(&id, &wait_for("hi bob")).await.unwrap();
+        assert!(reply.matched);
+        assert!(!reply.output.contains("name?"));
+        assert!(reply.screen.contains("name? bob\nhi bob"));
+        assert!(manager.transcript(&id).unwrap().contains("name? bob"));
+
+        manager.terminate(&id).unwrap();
+        assert!(manager.list().is_empty());
+        assert!(manager.send(&id, "x").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_reports_exit_and_timeouts() {
+        let manager = manager();
+        let id = manager.start(&ShellCommand::script("echo done; exit 3")).unwrap();
+        let output = manager
+            .read(
+                &id,
+                &PtyReadOptions {
+                    wait_for: Some("never".to_string()),
+                    timeout_ms: 5_000,
+                    ..PtyReadOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(output.exit_code, Some(3));
+        assert!(!output.timed_out);
+        assert!(output.output.contains("done"));
+
+        let quiet = manager.start(&ShellCommand::script("sleep 5")).unwrap();
+        let output = manager
+            .read(
+                &quiet,
+                &PtyReadOptions {
+                    wait_for: Some("never".to_string()),
+                    timeout_ms: 200,
+                    ..PtyReadOptions::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(output.timed_out);
+        let idle = manager.read(&quiet, &PtyReadOptions::default()).await.unwrap();
+        assert!(!idle.timed_out);
+        assert_eq!(manager.list().len(), 2);
+    }
+
+    #[test]
+    fn test_strip_ansi_and_utf8_split() {
+        assert_eq!(strip_ansi("\x1b[1;32mok\x1b[0m\r\n\x1b]0;title\x07"), "ok\n");
+        let mut pending = "é".as_bytes()[..1].to_vec();
+        assert_eq!(take_utf8(&mut pending), "");
+        pending.push("é".as_bytes()[1]);
+        assert_eq!(take_utf8(&mut pending), "é");
+    }
+}
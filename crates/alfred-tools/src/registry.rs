@@ -0,0 +1,182 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::template::{load_templates, HttpTemplate, TemplateError};
+
+// How far a tool call can reach beyond the conversation; see
+// `docs/architecture.md`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SideEffect {
+    ReadOnly,
+    WritesFs,
+    Network,
+    // Changes state somewhere else, e.g. creates a ticket or deploys.
+    Transactional,
+}
+
+impl SideEffect {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SideEffect::ReadOnly => "read_only",
+            SideEffect::WritesFs => "writes_fs",
+            SideEffect::Network => "network",
+            SideEffect::Transactional => "transactional",
+        }
+    }
+
+    pub fn needs_confirmation(&self) -> bool {
+        matches!(self, SideEffect::Transactional)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    // JSON Schema for the call arguments.
+    pub parameters: Value,
+    pub side_effect: SideEffect,
+}
+
+fn spec(name: &str, description: &str, side_effect: SideEffect, parameters: Value) -> ToolSpec {
+    ToolSpec {
+        name: name.to_string(),
+        description: description.to_string(),
+        parameters,
+        side_effect,
+    }
+}
+
+fn object(properties: Value, required: &[&str]) -> Value {
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+// `FsTool`, `GrepTool`, `ShellTool`, `GitTool` and `HttpTool`.
+pub fn builtin_tools() -> Vec<ToolSpec> {
+    let path = json!({ "type": "string", "description": "Path relative to the workspace" });
+    vec![
+        spec(
+            "read_file",
+            "Read a UTF-8 file",
+            SideEffect::ReadOnly,
+            object(json!({ "path": path }), &["path"]),
+        ),
+        spec(
+            "write_file",
+            "Create or overwrite a file",
+            SideEffect::WritesFs,
+            object(json!({ "path": path, "contents": { "type": "string" } }), &["path", "contents"]),
+        ),
+        spec(
+            "list_files",
+            "List files under a directory, respecting ignore files",
+            SideEffect::ReadOnly,
+            object(json!({ "path": path, "glob": { "type": "string" } }), &[]),
+        ),
+        spec(
+            "grep",
+            "Search file contents with a regular expression",
+            SideEffect::ReadOnly,
+            object(json!({ "pattern": { "type": "string" }, "path": path }), &["pattern"]),
+        ),
+        spec(
+            "shell",
+            "Run a shell command in the workspace",
+            SideEffect::WritesFs,
+            object(json!({ "command": { "type": "string" }, "timeout_ms": { "type": "integer" } }), &["command"]),
+        ),
+        spec(
+            "git",
+            "Inspect the repository: status, diff, log, show and blame",
+            SideEffect::ReadOnly,
+            object(
+                json!({
+                    "operation": { "enum": ["status", "diff", "log", "show", "blame"] },
+                    "rev": { "type": "string" },
+                    "path": path,
+                }),
+                &["operation"],
+            ),
+        ),
+        spec(
+            "fetch",
+            "Fetch a URL from an allowlisted host; HTML comes back as text",
+            SideEffect::Network,
+            object(
+                json!({
+                    "url": { "type": "string" },
+                    "method": { "enum": ["GET", "POST", "PUT", "PATCH", "DELETE"] },
+                    "headers": { "type": "object", "additionalProperties": { "type": "string" } },
+                    "body": { "type": "string" },
+                }),
+                &["url"],
+            ),
+        ),
+    ]
+}
+
+// Every tool the agent can call in a project: the built-ins followed by the
+// project's HTTP templates.
+#[derive(Debug, Clone, Default)]
+pub struct ToolRegistry {
+    specs: Vec<ToolSpec>,
+    templates: Vec<HttpTemplate>,
+}
+
+impl ToolRegistry {
+    pub fn load(root: &Path) -> Result<Self, TemplateError> {
+        Self::with_templates(load_templates(root)?)
+    }
+
+    pub fn with_templates(templates: Vec<HttpTemplate>) -> Result<Self, TemplateError> {
+        let mut specs = builtin_tools();
+        for template in &templates {
+            if specs.iter().any(|spec| spec.name == template.name) {
+                return Err(TemplateError::Invalid {
+                    tool: template.name.clone(),
+                    message: "clashes with a built-in tool".to_string(),
+                });
+            }
+            specs.push(template.spec());
+        }
+        Ok(Self { specs, templates })
+    }
+
+    pub fn specs(&self) -> &[ToolSpec] {
+        &self.specs
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ToolSpec> {
+        self.specs.iter().find(|spec| spec.name == name)
+    }
+
+    pub fn template(&self, name: &str) -> Option<&HttpTemplate> {
+        self.templates.iter().find(|template| template.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_adds_templates_after_builtins() {
+        let template = |name: &str, method: &str| {
+            let toml = format!("name = \"{}\"\ndescription = \"d\"\nmethod = \"{}\"\nurl = \"https://x/\"\n", name, method);
+            HttpTemplate::parse(Path::new("t.toml"), &toml).unwrap()
+        };
+        let registry = ToolRegistry::with_templates(vec![template("deploy", "POST")]).unwrap();
+        assert_eq!(registry.specs().len(), builtin_tools().len() + 1);
+        assert_eq!(registry.get("fetch").unwrap().side_effect, SideEffect::Network);
+        let deploy = registry.get("deploy").unwrap();
+        assert!(deploy.side_effect.needs_confirmation());
+        assert!(registry.template("deploy").is_some());
+        assert!(registry.template("shell").is_none());
+
+        let err = ToolRegistry::with_templates(vec![template("shell", "GET")]).unwrap_err();
+        assert!(err.to_string().contains("built-in"));
+    }
+}
@@ -0,0 +1,549 @@
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::ShellConfig;
+use crate::env::EnvPolicy;
+
+pub mod parse;
+pub mod policy;
+pub mod sandbox;
+
+pub use policy::{ShellPolicy, Verdict};
+pub use sandbox::Sandbox;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(120);
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+// How long to wait for pipes to drain after the process group was killed.
+const DRAIN_GRACE: Duration = Duration::from_secs(2);
+// Longer lines are streamed in pieces rather than buffered indefinitely.
+const MAX_STREAMED_LINE_BYTES: usize = 4096;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShellCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: Option<String>,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    // When set, this shell string runs through the configured shell and
+    // `program`/`args` are ignored.
+    #[serde(default)]
+    pub script: Option<String>,
+}
+
+impl ShellCommand {
+    pub fn script(script: impl Into<String>) -> Self {
+        Self {
+            script: Some(script.into()),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandOutput {
+    pub status: i32,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: u64,
+    pub timed_out: bool,
+    pub cancelled: bool,
+    // Signal that terminated the process, if any (Unix only).
+    pub signal: Option<i32>,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    // Something the command left running still held stdout or stderr open,
+    // so anything it wrote after the drain grace is missing.
+    pub output_abandoned: bool,
+    // Names (never values) of the environment variables the process saw.
+    pub env: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShellTool {
+    pub default_timeout: Duration,
+    // Cap per stream; the head and tail of the output are kept.
+    pub max_output_bytes: usize,
+    // Shell used for `ShellCommand::script`, e.g. `bash -lc`.
+    pub shell_program: String,
+    pub shell_args: Vec<String>,
+    pub sandbox: Option<Sandbox>,
+    pub env: EnvPolicy,
+}
+
+impl Default for ShellTool {
+    fn default() -> Self {
+        Self::from_config(&ShellConfig::default())
+    }
+}
+
+enum Outcome {
+    Exited(std::process::ExitStatus),
+    TimedOut,
+    Cancelled,
+}
+
+impl ShellTool {
+    pub fn from_config(config: &ShellConfig) -> Self {
+        Self {
+            default_timeout: DEFAULT_TIMEOUT,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+            shell_program: config.program.clone(),
+            shell_args: config.args.clone(),
+            sandbox: None,
+            env: EnvPolicy::default(),
+        }
+    }
+
+    pub fn with_env(mut self, env: EnvPolicy) -> Self {
+        self.env = env;
+        self
+    }
+
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    pub async fn run(&self, command: ShellCommand) -> anyhow::Result<CommandOutput> {
+        self.run_with_cancel(command, &CancellationToken::new()).await
+    }
+
+    pub async fn run_with_cancel(
+        &self,
+        command: ShellCommand,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<CommandOutput> {
+        self.execute(command, cancel, None).await
+    }
+
+    // Like `run_with_cancel`, but also sends stdout/stderr lines to `sink` as
+    // they are produced. The returned output is the same capped aggregate.
+    pub async fn run_streaming(
+        &self,
+        command: ShellCommand,
+        cancel: &CancellationToken,
+        sink: mpsc::Sender<OutputLine>,
+    ) -> anyhow::Result<CommandOutput> {
+        self.execute(command, cancel, Some(sink)).await
+    }
+
+    async fn execute(
+        &self,
+        command: ShellCommand,
+        cancel: &CancellationToken,
+        sink: Option<mpsc::Sender<OutputLine>>,
+    ) -> anyhow::Result<CommandOutput> {
+        let timeout = command
+            .timeout_ms
+            .map(Duration::from_millis)
+            .unwrap_or(self.default_timeout);
+
+        let (mut cmd, env) = self.command(&command)?;
+        let started = Instant::now();
+        let mut child = cmd.spawn()?;
+        let stdout = spawn_capture(
+            child.stdout.take(),
+            self.max_output_bytes,
+            OutputStream::Stdout,
+            sink.clone(),
+        );
+        let stderr = spawn_capture(
+            child.stderr.take(),
+            self.max_output_bytes,
+            OutputStream::Stderr,
+            sink,
+        );
+
+        let outcome = tokio::select! {
+            status = child.wait() => Outcome::Exited(status?),
+            _ = tokio::time::sleep(timeout) => Outcome::TimedOut,
+            _ = cancel.cancelled() => Outcome::Cancelled,
+        };
+
+        let status = match outcome {
+            Outcome::Exited(status) => Some(status),
+            Outcome::TimedOut | Outcome::Cancelled => {
+                kill_process_group(&mut child);
+                child.wait().await.ok()
+            }
+        };
+        let duration_ms = started.elapsed().as_millis() as u64;
+
+        let deadline = tokio::time::Instant::now() + DRAIN_GRACE;
+        let ((stdout, stdout_truncated, stdout_drained), (stderr, stderr_truncated, stderr_drained)) =
+            tokio::join!(finish_capture(stdout, deadline), finish_capture(stderr, deadline));
+
+        #[cfg(unix)]
+        let signal = status.and_then(|status| std::os::unix::process::ExitStatusExt::signal(&status));
+        #[cfg(not(unix))]
+        let signal = None;
+
+        Ok(CommandOutput {
+            status: status.and_then(|status| status.code()).unwrap_or(-1),
+            stdout,
+            stderr,
+            duration_ms,
+            timed_out: matches!(outcome, Outcome::TimedOut),
+            cancelled: matches!(outcome, Outcome::Cancelled),
+            signal,
+            stdout_truncated,
+            stderr_truncated,
+            output_abandoned: !(stdout_drained && stderr_drained),
+            env: env.into_keys().collect(),
+        })
+    }
+
+    // The process for `command`: filtered environment, own process group and
+    // sandbox applied. Also returns the environment it will see.
+    pub(crate) fn command(&self, command: &ShellCommand) -> anyhow::Result<(Command, BTreeMap<String, String>)> {
+        let mut cmd = match &command.script {
+            Some(script) => {
+                let mut cmd = Command::new(&self.shell_program);
+                cmd.args(&self.shell_args).arg(script);
+                cmd
+            }
+            None => {
+                let mut cmd = Command::new(&command.program);
+                cmd.args(&command.args);
+                cmd
+            }
+        };
+        let env = self.env.effective_from_process();
+        cmd.env_clear().envs(&env);
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = &command.cwd {
+            cmd.current_dir(cwd);
+        }
+        #[cfg(unix)]
+        cmd.process_group(0);
+        #[cfg(unix)]
+        if let Some(sandbox) = &self.sandbox {
+            let prepared = sandbox.prepare()?;
+            // Safety: `apply` only issues syscalls on data prepared above.
+            unsafe {
+                cmd.pre_exec(move || prepared.apply());
+            }
+        }
+        Ok((cmd, env))
+    }
+}
+
+pub(crate) fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // The child leads its own process group, so this also reaches
+        // anything it spawned (e.g. `npm` -> `node`).
+        unsafe {
+            libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+        }
+        return;
+    }
+    let _ = child.start_kill();
+}
+
+// A stream being read in the background. The buffer is shared so whatever
+// was captured survives the reader being aborted.
+pub(crate) struct Capture {
+    buffer: Arc<Mutex<HeadTailBuffer>>,
+    handle: JoinHandle<()>,
+}
+
+pub(crate) fn spawn_capture<R>(
+    reader: Option<R>,
+    cap: usize,
+    stream: OutputStream,
+    mut sink: Option<mpsc::Sender<OutputLine>>,
+) -> Option<Capture>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let mut reader = reader?;
+    let buffer = Arc::new(Mutex::new(HeadTailBuffer::new(cap)));
+    let shared = buffer.clone();
+    let handle = tokio::spawn(async move {
+        let mut pending = Vec::new();
+        let mut chunk = [0u8; 8192];
+        while let Ok(read) = reader.read(&mut chunk).await {
+            if read == 0 {
+                break;
+            }
+            shared.lock().unwrap().push(&chunk[..read]);
+            if sink.is_none() {
+                continue;
+            }
+            pending.extend_from_slice(&chunk[..read]);
+            while let Some(end) = pending
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .or((pending.len() >= MAX_STREAMED_LINE_BYTES).then_some(MAX_STREAMED_LINE_BYTES - 1))
+            {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                emit_line(&mut sink, stream, &line).await;
+            }
+        }
+        if !pending.is_empty() {
+            emit_line(&mut sink, stream, &pending).await;
+        }
+    });
+    Some(Capture { buffer, handle })
+}
+
+async fn emit_line(sink: &mut Option<mpsc::Sender<OutputLine>>, stream: OutputStream, line: &[u8]) {
+    let Some(tx) = sink else {
+        return;
+    };
+    let line = String::from_utf8_lossy(line)
+        .trim_end_matches(['\n', '\r'])
+        .to_string();
+    // Keep draining the pipe even if nobody is listening any more.
+    if tx.send(OutputLine { stream, line }).await.is_err() {
+        *sink = None;
+    }
+}
+
+// Returns the captured text, whether it was capped and whether the pipe
+// reached EOF by `deadline`.
+async fn finish_capture(capture: Option<Capture>, deadline: tokio::time::Instant) -> (String, bool, bool) {
+    let Some(mut capture) = capture else {
+        return (String::new(), false, true);
+    };
+    // A daemonized grandchild may hold the pipe open forever; don't wait on
+    // it, but keep what it had written so far.
+    let drained = tokio::time::timeout_at(deadline, &mut capture.handle).await.is_ok();
+    if !drained {
+        capture.handle.abort();
+    }
+    let (text, truncated) = capture.buffer.lock().unwrap().render();
+    (text, truncated, drained)
+}
+
+// Keeps the first and last `cap / 2` bytes of a stream, which is where
+// compilers and test runners put the interesting parts.
+#[derive(Debug)]
+pub(crate) struct HeadTailBuffer {
+    cap: usize,
+    head: Vec<u8>,
+    tail: std::collections::VecDeque<u8>,
+    total: usize,
+}
+
+impl HeadTailBuffer {
+    fn new(cap: usize) -> Self {
+        Self {
+            cap,
+            head: Vec::new(),
+            tail: std::collections::VecDeque::new(),
+            total: 0,
+        }
+    }
+
+    fn push(&mut self, mut bytes: &[u8]) {
+        self.total += bytes.len();
+        let head_cap = self.cap / 2;
+        if self.head.len() < head_cap {
+            let take = bytes.len().min(head_cap - self.head.len());
+            self.head.extend_from_slice(&bytes[..take]);
+            bytes = &bytes[take..];
+        }
+        let tail_cap = self.cap - head_cap;
+        if bytes.len() >= tail_cap {
+            self.tail.clear();
+            self.tail.extend(&bytes[bytes.len() - tail_cap..]);
+            return;
+        }
+        let overflow = (self.tail.len() + bytes.len()).saturating_sub(tail_cap);
+        self.tail.drain(..overflow);
+        self.tail.extend(bytes);
+    }
+
+    fn render(&self) -> (String, bool) {
+        let kept = self.head.len() + self.tail.len();
+        let mut bytes = self.head.clone();
+        let truncated = self.total > kept;
+        if truncated {
+            let marker = format!("\n... [{} bytes truncated] ...\n", self.total - kept);
+            bytes.extend_from_slice(marker.as_bytes());
+        }
+        bytes.extend(&self.tail);
+        (String::from_utf8_lossy(&bytes).to_string(), truncated)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> ShellCommand {
+        ShellCommand {
+            program: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            ..ShellCommand::default()
+        }
+    }
+
+    #[test]
+    fn test_head_tail_buffer() {
+        let mut buffer = HeadTailBuffer::new(8);
+        buffer.push(b"abcdefgh");
+        buffer.push(b"ijkl");
+        let (text, truncated) = buffer.render();
+        assert!(truncated);
+        assert_eq!(text, "abcd\n... [4 bytes truncated] ...\nijkl");
+
+        let mut small = HeadTailBuffer::new(8);
+        small.push(b"abc");
+        assert_eq!(small.render(), ("abc".to_string(), false));
+    }
+
+    #[tokio::test]
+    async fn test_run_script_through_shell() {
+        let tool = ShellTool {
+            shell_program: "sh".to_string(),
+            shell_args: vec!["-c".to_string()],
+            ..ShellTool::default()
+        };
+        let output = tool
+            .run(ShellCommand::script("printf 'a\\nb\\nc\\n' | tail -1 && echo done"))
+            .await
+            .unwrap();
+        assert_eq!(output.stdout, "c\ndone\n");
+    }
+
+    #[tokio::test]
+    async fn test_run_filters_environment() {
+        let mut config = crate::config::EnvConfig::default();
+        config.set.insert("ALFRED_TEST_INJECTED".to_string(), "yes".to_string());
+        let tool = ShellTool::default().with_env(EnvPolicy::from_config(&config).unwrap());
+        let output = tool
+            .run(sh("echo ${ALFRED_TEST_INJECTED}-${OPENROUTER_API_KEY:-unset}"))
+            .await
+            .unwrap();
+        assert_eq!(output.stdout, "yes-unset\n");
+        assert!(output.env.contains(&"PATH".to_string()));
+        assert!(output.env.contains(&"ALFRED_TEST_INJECTED".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_run_captures_output() {
+        let output = ShellTool::default().run(sh("echo out; echo err >&2; exit 3")).await.unwrap();
+        assert_eq!(output.status, 3);
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+        assert!(!output.timed_out);
+        assert_eq!(output.signal, None);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_kills_process_group() {
+        let mut command = sh("sleep 30 & sleep 30; echo never");
+        command.timeout_ms = Some(200);
+        let output = ShellTool::default().run(command).await.unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.signal, Some(libc::SIGKILL));
+        assert!(output.duration_ms < 5_000);
+        assert!(output.stdout.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_background_grandchild_keeps_output() {
+        // `sleep` inherits both pipes and holds them open past the drain
+        // grace, which is shared rather than waited out once per stream.
+        let started = Instant::now();
+        let output = ShellTool::default().run(sh("echo hello; sleep 5 &")).await.unwrap();
+        assert!(started.elapsed() < DRAIN_GRACE + Duration::from_secs(1));
+        assert_eq!(output.status, 0);
+        assert_eq!(output.stdout, "hello\n");
+        assert!(output.output_abandoned);
+        assert!(!output.stdout_truncated);
+
+        let output = ShellTool::default().run(sh("echo hello")).await.unwrap();
+        assert!(!output.output_abandoned);
+    }
+
+    #[tokio::test]
+    async fn test_cancellation() {
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            trigger.cancel();
+        });
+        let output = ShellTool::default()
+            .run_with_cancel(sh("sleep 30"), &cancel)
+            .await
+            .unwrap();
+        assert!(output.cancelled);
+        assert!(!output.timed_out);
+    }
+
+    #[tokio::test]
+    async fn test_run_streaming_emits_lines() {
+        let (tx, mut rx) = mpsc::channel(16);
+        let output = ShellTool::default()
+            .run_streaming(sh("echo one; echo two >&2; printf three"), &CancellationToken::new(), tx)
+            .await
+            .unwrap();
+        assert_eq!(output.stdout, "one\nthree");
+
+        let mut lines = Vec::new();
+        while let Some(line) = rx.recv().await {
+            lines.push((line.stream, line.line));
+        }
+        lines.sort_by_key(|(stream, _)| stream.as_str());
+        assert_eq!(
+            lines,
+            vec![
+                (OutputStream::Stderr, "two".to_string()),
+                (OutputStream::Stdout, "one".to_string()),
+                (OutputStream::Stdout, "three".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_output_cap() {
+        let tool = ShellTool {
+            max_output_bytes: 16,
+            ..ShellTool::default()
+        };
+        let output = tool.run(sh("seq 1 1000")).await.unwrap();
+        assert!(output.stdout_truncated);
+        assert!(output.stdout.starts_with("1\n2\n3\n4\n"));
+        assert!(output.stdout.ends_with("99\n1000\n"));
+    }
+}
